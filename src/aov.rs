@@ -0,0 +1,141 @@
+use std::io;
+use std::path::Path;
+
+use crate::image::*;
+use crate::render::{AuxBuffers, Frame};
+
+// Arbitrary output variables: extra per-pixel channels
+// that can be written out alongside the beauty image.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov {
+    Depth,     // Distance from the camera along the ray.
+    Position,  // World-space position.
+    Normal,    // World-space shading normal.
+    Albedo,
+    Uv,        // Texture coordinates.
+    Object,    // Index of the object in the scene, or -1 for the background.
+    Primitive  // Index of the primitive within the object, or -1 for the background.
+}
+
+#[allow(dead_code)]
+impl Aov {
+    pub const ALL: [Aov; 7] = [Aov::Depth, Aov::Position, Aov::Normal, Aov::Albedo,
+                               Aov::Uv, Aov::Object, Aov::Primitive];
+
+    // The layer name used for this AOV in multi-channel files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth     => "depth",
+            Aov::Position  => "P",
+            Aov::Normal    => "N",
+            Aov::Albedo    => "albedo",
+            Aov::Uv        => "uv",
+            Aov::Object    => "object",
+            Aov::Primitive => "primitive"
+        }
+    }
+}
+
+impl AuxBuffers {
+    // Returns the channels making up an AOV, named as in OpenEXR.
+    pub fn channels(&self, aov: Aov) -> Vec<(String, Vec<f32>)> {
+        let layer = |names: &[&str], data: Vec<Vec<f32>>| -> Vec<(String, Vec<f32>)> {
+            names.iter().map(|name| format!("{}.{}", aov.name(), name)).zip(data).collect()
+        };
+        let index = |ids: &[Option<usize>]| ids.iter().map(|id| id.map_or(-1.0, |id| id as f32)).collect();
+
+        match aov {
+            Aov::Depth => layer(&["Z"], vec![self.depth.clone()]),
+            Aov::Position => layer(&["X", "Y", "Z"], vec![self.position.iter().map(|p| p.x).collect(),
+                                                          self.position.iter().map(|p| p.y).collect(),
+                                                          self.position.iter().map(|p| p.z).collect()]),
+            Aov::Normal => layer(&["X", "Y", "Z"], vec![self.normal.iter().map(|n| n.x).collect(),
+                                                        self.normal.iter().map(|n| n.y).collect(),
+                                                        self.normal.iter().map(|n| n.z).collect()]),
+            Aov::Albedo => layer(&["R", "G", "B"], vec![self.albedo.iter().map(|c| c.red).collect(),
+                                                        self.albedo.iter().map(|c| c.green).collect(),
+                                                        self.albedo.iter().map(|c| c.blue).collect()]),
+            Aov::Uv => layer(&["U", "V"], vec![self.uv.iter().map(|uv| uv.0).collect(),
+                                               self.uv.iter().map(|uv| uv.1).collect()]),
+            Aov::Object => layer(&["id"], vec![index(&self.object)]),
+            Aov::Primitive => layer(&["id"], vec![index(&self.primitive)])
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Frame {
+    // Writes a single AOV to its own file, either PFM or EXR depending on
+    // the extension. PFM only holds one or three channels, so the UV
+    // AOV gets an empty third channel there.
+    pub fn write_aov(&self, aov: Aov, filename: impl AsRef<Path>) -> io::Result<()> {
+        let (width, height) = (self.image.width, self.image.height);
+        let mut channels = self.aux.channels(aov);
+
+        match filename.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("exr") => write_exr(filename, width, height, &channels),
+            Some("pfm") => {
+                if channels.len() == 2 {
+                    channels.push((String::new(), vec![0.0; (width * height) as usize]));
+                }
+                let channels: Vec<&[f32]> = channels.iter().map(|c| c.1.as_slice()).collect();
+                write_pfm(filename, width, height, &channels)
+            },
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                    format!("unsupported AOV format: {}", filename.as_ref().display())))
+        }
+    }
+
+    // Writes the finished image along with the given AOVs and all
+    // light groups as layers of a single multi-channel EXR file.
+    // The light groups add up to the raw beauty, which goes in its own "raw" layer.
+    pub fn write_exr(&self, filename: impl AsRef<Path>, aovs: &[Aov]) -> io::Result<()> {
+        let mut channels = self.image.exr_channels();
+        channels.extend(self.raw.exr_channels().into_iter().map(|(name, data)| (format!("raw.{}", name), data)));
+        for &aov in aovs {
+            channels.extend(self.aux.channels(aov));
+        }
+        for group in self.light_groups.iter() {
+            channels.push((format!("light_{}.R", group.name), group.pixels.iter().map(|c| c.red).collect()));
+            channels.push((format!("light_{}.G", group.name), group.pixels.iter().map(|c| c.green).collect()));
+            channels.push((format!("light_{}.B", group.name), group.pixels.iter().map(|c| c.blue).collect()));
+        }
+        write_exr(filename, self.image.width, self.image.height, &channels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::{Point3, Vector3};
+    use crate::types::Color;
+
+    #[test]
+    fn aux_channels() {
+        // One pixel on an object, and one on the background.
+        let mut aux = AuxBuffers::with_capacity(2);
+        aux.albedo.extend(vec![Color::new(0.1, 0.2, 0.3), Color::black()]);
+        aux.normal.extend(vec![Vector3::y(), Vector3::zeros()]);
+        aux.depth.extend(vec![2.0, f32::INFINITY]);
+        aux.position.extend(vec![Point3::new(1.0, 2.0, 3.0), Point3::origin()]);
+        aux.uv.extend(vec![(0.25, 0.75), (0.0, 0.0)]);
+        aux.object.extend(vec![Some(3), None]);
+        aux.primitive.extend(vec![Some(7), None]);
+
+        let expected: [&[&str]; 7] = [&["depth.Z"], &["P.X", "P.Y", "P.Z"], &["N.X", "N.Y", "N.Z"],
+                                      &["albedo.R", "albedo.G", "albedo.B"], &["uv.U", "uv.V"],
+                                      &["object.id"], &["primitive.id"]];
+        for (&aov, &names) in Aov::ALL.iter().zip(expected.iter()) {
+            let channels = aux.channels(aov);
+            assert_eq!(channels.iter().map(|c| c.0.as_str()).collect::<Vec<_>>(), names);
+            assert!(channels.iter().all(|c| c.1.len() == 2));
+        }
+
+        assert_eq!(aux.channels(Aov::Position)[1].1, vec![2.0, 0.0]);
+        assert_eq!(aux.channels(Aov::Uv)[1].1, vec![0.75, 0.0]);
+        // The background has no index.
+        assert_eq!(aux.channels(Aov::Object)[0].1, vec![3.0, -1.0]);
+        assert_eq!(aux.channels(Aov::Primitive)[0].1, vec![7.0, -1.0]);
+    }
+}
@@ -24,7 +24,7 @@ impl Camera {
         let iso = Isometry3::face_towards(&pos, &(pos + dir), &up);
         Camera {
             matrix: iso,
-            focal_length: focal_length,
+            focal_length,
             canvas_size: Vector2::new(canvas_y  * aspect_ratio, canvas_y),
             image_size: Vector2::new((image_y as f32 * aspect_ratio) as u32, image_y)
         }
@@ -38,11 +38,23 @@ impl Camera {
 
     pub fn pos(&self) -> Point3<f32> { Point3::from(self.matrix.translation.vector) }
 
+    // The size in world space that a pixel covers at the given point, going by its distance
+    // from the camera (so points off to the side or behind it still get a sensible size).
+    #[allow(dead_code)]
+    pub fn pixel_size(&self, point: Point3<f32>) -> f32 {
+        let distance = (point - self.pos()).norm().max(self.focal_length);
+        distance * self.canvas_size.y / (self.image_size.y as f32 * self.focal_length)
+    }
+
     // Takes a 2D point in the image space and
     // maps it to the 3D point on the canvas.
-    fn project(&self, x: u32, y: u32) -> Point3<f32> {
+    fn project(&self, x: u32, y: u32) -> Point3<f32> { self.project_offset(x, y, 0.5, 0.5) }
+
+    // Same as above, but with an offset (from 0 to 1) within the
+    // pixel instead of its center. Used for anti-aliasing.
+    fn project_offset(&self, x: u32, y: u32, dx: f32, dy: f32) -> Point3<f32> {
         // convert point from raster coordinates to center-based coordinates
-        let pixelndc = Point2::new(x as f32 + 0.5 - self.image_size.x as f32 * 0.5, -(y as f32 + 0.5) + self.image_size.y as f32 * 0.5);
+        let pixelndc = Point2::new(x as f32 + dx - self.image_size.x as f32 * 0.5, -(y as f32 + dy) + self.image_size.y as f32 * 0.5);
 
         let point: Point3<f32> = Point::from(pixelndc.coords.component_div(&self.image_size.map(|x| x as f32))
                                                             .component_mul(&self.canvas_size)
@@ -55,6 +67,11 @@ impl Camera {
     pub fn raycast(&self, x: u32, y: u32) -> Ray {
         Ray::from_points(self.pos(), self.project(x, y))
     }
+
+    // Same as above, but through an offset (from 0 to 1) within the pixel.
+    pub fn raycast_offset(&self, x: u32, y: u32, dx: f32, dy: f32) -> Ray {
+        Ray::from_points(self.pos(), self.project_offset(x, y, dx, dy))
+    }
 }
 
 
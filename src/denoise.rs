@@ -0,0 +1,167 @@
+use std::io;
+
+use crate::image::Image;
+use crate::render::AuxBuffers;
+use crate::types::*;
+
+// Edge-avoiding à-trous wavelet denoiser (Dammertz et al., 2010).
+// Each pass blurs the image with a sparse 5x5 B3-spline kernel whose
+// taps are spread twice as far apart as in the pass before. The
+// contribution of each tap is cut off wherever the color, albedo,
+// normal or depth buffers differ too much from the center pixel,
+// which keeps edges and texture detail sharp.
+#[derive(Clone, Debug)]
+pub struct Denoiser {
+    pub iterations: u32, // Number of passes. The filter spans 4 * 2^iterations pixels, and passes
+                         // beyond the point where it spans the whole image are skipped.
+
+    pub color_sigma: f32, // Tolerated color difference, halved with every pass. All sigmas have to be positive.
+    pub albedo_sigma: f32,
+    pub normal_sigma: f32,
+    pub depth_sigma: f32  // Tolerated depth difference, relative to the depth of the center pixel.
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            color_sigma: 0.6,
+            albedo_sigma: 0.1,
+            normal_sigma: 0.3,
+            depth_sigma: 0.05
+        }
+    }
+}
+
+const B3_SPLINE: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+fn distance_squared(a: Color, b: Color) -> f32 {
+    let d = a - b;
+    d.red * d.red + d.green * d.green + d.blue * d.blue
+}
+
+// Divides out the surface albedo, so that only lighting gets filtered.
+// Components without any albedo are left as they are.
+fn demodulate(color: Color, albedo: Color) -> Color {
+    color.zip(albedo, |c, a| if a > 1e-3 { c / a } else { c })
+}
+
+fn remodulate(light: Color, albedo: Color) -> Color {
+    light.zip(albedo, |c, a| if a > 1e-3 { c * a } else { c })
+}
+
+impl Denoiser {
+    // Denoises the image in place, using the auxiliary buffers
+    // rendered along with it as guides.
+    pub fn apply(&self, image: &mut Image, aux: &AuxBuffers) -> io::Result<()> {
+        let sigmas = [self.color_sigma, self.albedo_sigma, self.normal_sigma, self.depth_sigma];
+        if !sigmas.iter().all(|&sigma| sigma > 0.0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "denoiser sigmas have to be positive"));
+        }
+
+        let (width, height) = (image.width as i32, image.height as i32);
+
+        let mut light: Vec<Color> = image.pixels.iter().zip(aux.albedo.iter())
+                                         .map(|(&color, &albedo)| demodulate(color, albedo))
+                                         .collect();
+
+        for iteration in 0..self.iterations {
+            // Once the taps reach past the image, only the center one is left.
+            let step = match 1u32.checked_shl(iteration) {
+                Some(step) if step < width.max(height) as u32 => step as i32,
+                _ => break
+            };
+            let color_sigma = self.color_sigma * 0.5f32.powi(iteration as i32);
+
+            light = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| {
+                let p = (y * width + x) as usize;
+
+                let mut total = Color::black();
+                let mut total_weight = 0.0;
+
+                for (ky, hy) in B3_SPLINE.iter().enumerate() {
+                    for (kx, hx) in B3_SPLINE.iter().enumerate() {
+                        let qx = x + (kx as i32 - 2) * step;
+                        let qy = y + (ky as i32 - 2) * step;
+                        if qx < 0 || qy < 0 || qx >= width || qy >= height { continue; }
+                        let q = (qy * width + qx) as usize;
+
+                        let weight = hx * hy
+                            * (-distance_squared(light[p], light[q]) / (color_sigma * color_sigma)).exp()
+                            * (-distance_squared(aux.albedo[p], aux.albedo[q]) / (self.albedo_sigma * self.albedo_sigma)).exp()
+                            * self.normal_weight(aux, p, q)
+                            * self.depth_weight(aux, p, q);
+
+                        total += light[q] * weight;
+                        total_weight += weight;
+                    }
+                }
+
+                // The center tap always has full weight, so this is never zero.
+                total / total_weight
+            }).collect();
+        }
+
+        for (pixel, (light, &albedo)) in image.pixels.iter_mut().zip(light.into_iter().zip(aux.albedo.iter())) {
+            *pixel = remodulate(light, albedo);
+        }
+        Ok(())
+    }
+
+    fn normal_weight(&self, aux: &AuxBuffers, p: usize, q: usize) -> f32 {
+        let (np, nq) = (aux.normal[p], aux.normal[q]);
+        // A zero normal marks the background, which only blends with itself.
+        match (np.norm_squared() > 0.0, nq.norm_squared() > 0.0) {
+            (false, false) => 1.0,
+            (true, true) => (-(np - nq).norm_squared() / (self.normal_sigma * self.normal_sigma)).exp(),
+            _ => 0.0
+        }
+    }
+
+    fn depth_weight(&self, aux: &AuxBuffers, p: usize, q: usize) -> f32 {
+        let (dp, dq) = (aux.depth[p], aux.depth[q]);
+        match (dp.is_finite(), dq.is_finite()) {
+            (false, false) => 1.0,
+            (true, true) => (-(dp - dq).abs() / (self.depth_sigma * dp.max(1e-3))).exp(),
+            _ => 0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector3;
+
+    #[test]
+    fn denoise_keeps_edges() {
+        // Left half is a noisy bright surface, right half a dark one at a different depth.
+        let (width, height) = (16, 8);
+        let mut image = Image::new(width, height);
+        let mut aux = AuxBuffers::with_capacity((width * height) as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let left = x < width / 2;
+                let noise = if (x + y) % 2 == 0 { 0.1 } else { -0.1 };
+                let color = if left { Color::gray(0.8 + noise) } else { Color::gray(0.1) };
+                image.set(x, y, color, 1.0);
+
+                aux.albedo.push(Color::white());
+                aux.normal.push(Vector3::z());
+                aux.depth.push(if left { 1.0 } else { 5.0 });
+            }
+        }
+
+        Denoiser { iterations: 40, ..Denoiser::default() }.apply(&mut image, &aux).unwrap();
+
+        // The noise is smoothed out...
+        assert!((image.get(2, 2).red - image.get(3, 2).red).abs() < 0.05);
+        // ...but the edge isn't blurred.
+        assert!(image.get(width / 2 - 1, 4).red > 0.7);
+        assert!(image.get(width / 2, 4).red < 0.15);
+
+        let error = Denoiser { color_sigma: 0.0, ..Denoiser::default() }.apply(&mut image, &aux).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,417 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::types::Color;
+
+// A floating-point RGBA framebuffer. Colors are stored linear
+// and premultiplied by alpha, so values above 1.0 are preserved.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+
+    pub pixels: Vec<Color>,
+    pub alpha: Vec<f32>
+}
+
+#[allow(dead_code)]
+impl Image {
+    // Creates a new black, fully transparent image.
+    // Panics if the pixel count doesn't fit in memory.
+    pub fn new(width: u32, height: u32) -> Self {
+        let size = (width as usize).checked_mul(height as usize).expect("image size overflows");
+        Image {
+            width, height,
+            pixels: vec![Color::black(); size],
+            alpha: vec![0.0; size]
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize { y as usize * self.width as usize + x as usize }
+
+    pub fn get(&self, x: u32, y: u32) -> Color { self.pixels[self.index(x, y)] }
+    pub fn get_alpha(&self, x: u32, y: u32) -> f32 { self.alpha[self.index(x, y)] }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Color, alpha: f32) {
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+        self.alpha[index] = alpha;
+    }
+
+    // Samples the image at a continuous position with bilinear filtering,
+    // where pixel centers lie at half-integer coordinates. Edges are clamped.
+    pub fn sample(&self, x: f32, y: f32) -> Color {
+        let ((x0, y0), (x1, y1), (fx, fy)) = self.bilinear(x, y);
+        let top    = self.get(x0, y0).lerp(self.get(x1, y0), fx);
+        let bottom = self.get(x0, y1).lerp(self.get(x1, y1), fx);
+        top.lerp(bottom, fy)
+    }
+
+    // Same as above, but for the alpha channel.
+    pub fn sample_alpha(&self, x: f32, y: f32) -> f32 {
+        let ((x0, y0), (x1, y1), (fx, fy)) = self.bilinear(x, y);
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let top    = lerp(self.get_alpha(x0, y0), self.get_alpha(x1, y0), fx);
+        let bottom = lerp(self.get_alpha(x0, y1), self.get_alpha(x1, y1), fx);
+        lerp(top, bottom, fy)
+    }
+
+    // Returns the two pixels around a position along each axis, and how far it is between them.
+    fn bilinear(&self, x: f32, y: f32) -> ((u32, u32), (u32, u32), (f32, f32)) {
+        let x = (x - 0.5).max(0.0).min((self.width - 1) as f32);
+        let y = (y - 0.5).max(0.0).min((self.height - 1) as f32);
+
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        ((x0, y0), (x1, y1), (x.fract(), y.fract()))
+    }
+
+    // Returns the color of a pixel with the alpha premultiplication undone.
+    pub fn get_straight(&self, x: u32, y: u32) -> Color {
+        let alpha = self.get_alpha(x, y);
+        if alpha > 0.0 { self.get(x, y) / alpha } else { Color::black() }
+    }
+
+    // Writes the image to a file, choosing the format from the extension.
+    // PPM and PFM have no alpha channel, so their pixels are left composited over black;
+    // PAM stores straight (non-premultiplied) RGBA, and EXR premultiplied float RGBA.
+    pub fn write(&self, filename: impl AsRef<Path>) -> io::Result<()> {
+        let filename = filename.as_ref();
+        match filename.extension().and_then(|ext| ext.to_str()) {
+            Some("ppm") => self.write_ppm(filename),
+            Some("pam") => self.write_pam(filename),
+            Some("pfm") => self.write_pfm(filename),
+            Some("exr") => self.write_exr(filename),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                    format!("unsupported image format: {}", filename.display())))
+        }
+    }
+
+    pub fn write_ppm(&self, filename: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(filename)?);
+        file.write_all(format!("P6\n{} {}\n255\n", self.width, self.height).as_bytes())?;
+        for color in self.pixels.iter() {
+            file.write_all(&color.to_byte_array())?;
+        }
+        file.flush()
+    }
+
+    pub fn write_pam(&self, filename: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(filename)?);
+        file.write_all(format!("P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+                               self.width, self.height).as_bytes())?;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                file.write_all(&self.get_straight(x, y).to_byte_array())?;
+                file.write_all(&[(255.0 * self.get_alpha(x, y)) as u8])?;
+            }
+        }
+        file.flush()
+    }
+
+    // Writes the RGB channels as a floating-point PFM image.
+    pub fn write_pfm(&self, filename: impl AsRef<Path>) -> io::Result<()> {
+        let red:   Vec<f32> = self.pixels.iter().map(|c| c.red).collect();
+        let green: Vec<f32> = self.pixels.iter().map(|c| c.green).collect();
+        let blue:  Vec<f32> = self.pixels.iter().map(|c| c.blue).collect();
+        write_pfm(filename, self.width, self.height, &[&red, &green, &blue])
+    }
+
+    // Writes the image as a floating-point RGBA OpenEXR file.
+    pub fn write_exr(&self, filename: impl AsRef<Path>) -> io::Result<()> {
+        write_exr(filename, self.width, self.height, &self.exr_channels())
+    }
+
+    // Returns the image's channels, named as in OpenEXR.
+    pub fn exr_channels(&self) -> Vec<(String, Vec<f32>)> {
+        vec![("R".to_string(), self.pixels.iter().map(|c| c.red).collect()),
+             ("G".to_string(), self.pixels.iter().map(|c| c.green).collect()),
+             ("B".to_string(), self.pixels.iter().map(|c| c.blue).collect()),
+             ("A".to_string(), self.alpha.clone())]
+    }
+
+    // Reads an image from a file, choosing the format from the extension.
+    // Only the Netpbm formats (PBM excluded) can be read for now.
+    pub fn read(filename: impl AsRef<Path>) -> io::Result<Self> {
+        let filename = filename.as_ref();
+        match filename.extension().and_then(|ext| ext.to_str()) {
+            Some("ppm") | Some("pgm") | Some("pnm") => Image::read_pnm(filename),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                    format!("unsupported image format: {}", filename.display())))
+        }
+    }
+
+    // Reads a grayscale or RGB Netpbm image, in plain (P2, P3) or binary (P5, P6) form,
+    // with 8 or 16 bits per sample. Values are taken as linear, like write_ppm writes them.
+    pub fn read_pnm(filename: impl AsRef<Path>) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        let mut data = Vec::new();
+        File::open(filename)?.read_to_end(&mut data)?;
+
+        // Splits off the next whitespace-separated token, skipping comments.
+        let mut pos = 0;
+        let token = |pos: &mut usize| -> io::Result<String> {
+            loop {
+                while *pos < data.len() && data[*pos].is_ascii_whitespace() { *pos += 1; }
+                if *pos < data.len() && data[*pos] == b'#' {
+                    while *pos < data.len() && data[*pos] != b'\n' { *pos += 1; }
+                } else { break; }
+            }
+            let start = *pos;
+            while *pos < data.len() && !data[*pos].is_ascii_whitespace() { *pos += 1; }
+            if start == *pos { return Err(invalid("unexpected end of PNM file")); }
+            Ok(String::from_utf8_lossy(&data[start..*pos]).into_owned())
+        };
+        let number = |pos: &mut usize| -> io::Result<u32> {
+            token(pos)?.parse().map_err(|_| invalid("invalid number in PNM file"))
+        };
+
+        let magic = token(&mut pos)?;
+        let (channels, binary) = match magic.as_str() {
+            "P2" => (1, false),
+            "P3" => (3, false),
+            "P5" => (1, true),
+            "P6" => (3, true),
+            _ => return Err(invalid("unsupported PNM type"))
+        };
+        let width = number(&mut pos)?;
+        let height = number(&mut pos)?;
+        let max = number(&mut pos)?;
+        if max == 0 || max > 65535 { return Err(invalid("invalid PNM maximum value")); }
+
+        let too_large = || invalid("PNM image too large");
+        let count = (width as usize).checked_mul(height as usize)
+                                    .and_then(|n| n.checked_mul(channels))
+                                    .ok_or_else(too_large)?;
+        let samples: Vec<u32> = if binary {
+            pos += 1; // A single whitespace character separates the header from the data.
+            let size = if max > 255 { 2 } else { 1 };
+            let end = count.checked_mul(size).and_then(|n| n.checked_add(pos)).ok_or_else(too_large)?;
+            let body = data.get(pos..end).ok_or_else(|| invalid("truncated PNM file"))?;
+            if size == 2 { body.chunks(2).map(|b| u32::from(b[0]) << 8 | u32::from(b[1])).collect() }
+            else { body.iter().map(|&b| u32::from(b)).collect() }
+        } else {
+            (0..count).map(|_| number(&mut pos)).collect::<io::Result<_>>()?
+        };
+
+        let mut image = Image::new(width, height);
+        for (i, sample) in samples.chunks(channels).enumerate() {
+            let value = |c: usize| sample[c] as f32 / max as f32;
+            image.pixels[i] = if channels == 1 { Color::gray(value(0)) } else { Color::new(value(0), value(1), value(2)) };
+            image.alpha[i] = 1.0;
+        }
+        Ok(image)
+    }
+}
+
+// Writes one or three channels of float data, in row-major
+// order from the top of the image, as a little-endian PFM file.
+pub fn write_pfm(filename: impl AsRef<Path>, width: u32, height: u32, channels: &[&[f32]]) -> io::Result<()> {
+    let magic = match channels.len() {
+        1 => "Pf",
+        3 => "PF",
+        n => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                       format!("PFM needs 1 or 3 channels, not {}", n)))
+    };
+
+    let mut file = BufWriter::new(File::create(filename)?);
+    // A negative scale marks the data as little-endian.
+    file.write_all(format!("{}\n{} {}\n-1.0\n", magic, width, height).as_bytes())?;
+
+    // PFM rows go from the bottom of the image to the top.
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let index = y as usize * width as usize + x as usize;
+            for channel in channels {
+                file.write_all(&channel[index].to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()
+}
+
+// Writes named channels of float data as an uncompressed scanline
+// OpenEXR file. Channel names may use layer prefixes such as "N.X".
+pub fn write_exr(filename: impl AsRef<Path>, width: u32, height: u32, channels: &[(String, Vec<f32>)]) -> io::Result<()> {
+    fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(kind.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    }
+
+    // Channels have to be stored in alphabetical order.
+    let mut channels: Vec<&(String, Vec<f32>)> = channels.iter().collect();
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut channel_list = Vec::new();
+    for (name, _) in channels.iter() {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&2i32.to_le_bytes()); // Pixel type: 32-bit float.
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);       // Perceptually linear flag and padding.
+        channel_list.extend_from_slice(&1i32.to_le_bytes()); // x sampling.
+        channel_list.extend_from_slice(&1i32.to_le_bytes()); // y sampling.
+    }
+    channel_list.push(0);
+
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1].iter()
+                                                                     .flat_map(|x| x.to_le_bytes().to_vec())
+                                                                     .collect();
+
+    let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    attribute(&mut header, "channels", "chlist", &channel_list);
+    attribute(&mut header, "compression", "compression", &[0]);
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+
+    let mut file = BufWriter::new(File::create(filename)?);
+    file.write_all(&header)?;
+
+    // Every scanline is its own chunk, made up of its y coordinate,
+    // its size in bytes, and then each channel's row in turn.
+    let line_size = 4 * width as usize * channels.len();
+    let table_end = header.len() + 8 * height as usize;
+    for y in 0..height as usize {
+        file.write_all(&((table_end + y * (8 + line_size)) as u64).to_le_bytes())?;
+    }
+
+    for y in 0..height {
+        file.write_all(&(y as i32).to_le_bytes())?;
+        file.write_all(&(line_size as i32).to_le_bytes())?;
+        for (_, data) in channels.iter() {
+            let row = y as usize * width as usize;
+            for value in data[row..row + width as usize].iter() {
+                file.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    // A 2 * 2 image with a half-covered pixel in the top left corner.
+    fn test_image() -> Image {
+        let mut image = Image::new(2, 2);
+        image.set(0, 0, Color::new(0.5, 0.25, 0.0), 0.5);
+        image.set(1, 0, Color::gray(1.0), 1.0);
+        image.set(0, 1, Color::new(0.0, 0.0, 2.0), 1.0);
+        image
+    }
+
+    // Writes a file with a name unique to the test process, and returns its contents.
+    fn written(name: &str, write: impl FnOnce(&Path) -> io::Result<()>) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("{}_{}", std::process::id(), name));
+        write(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn image_write_pam() {
+        let bytes = written("image_write_pam.pam", |path| test_image().write(path));
+        let header = b"P7\nWIDTH 2\nHEIGHT 2\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+        assert_eq!(&bytes[..header.len()], &header[..]);
+        assert_eq!(bytes.len(), header.len() + 4 * 4);
+
+        // Colors are stored straight, with the premultiplication undone.
+        assert_eq!(&bytes[header.len()..header.len() + 4], &[255, 127, 0, 127]);
+        // Uncovered pixels are black and transparent.
+        assert_eq!(&bytes[bytes.len() - 4..], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn image_write_pfm() {
+        let bytes = written("image_write_pfm.pfm", |path| test_image().write(path));
+        // A negative scale for little-endian data.
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], &header[..]);
+        assert_eq!(bytes.len(), header.len() + 4 * 3 * 4);
+
+        // Rows run from the bottom up, so the first pixel is the bottom left one.
+        let first: Vec<f32> = bytes[header.len()..header.len() + 12].chunks(4)
+                                                                    .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                                                                    .collect();
+        assert_eq!(first, vec![0.0, 0.0, 2.0]);
+
+        let single = written("image_write_pfm_single.pfm", |path| write_pfm(path, 1, 1, &[&[0.5]]));
+        assert_eq!(&single[..], &[&b"Pf\n1 1\n-1.0\n"[..], &0.5f32.to_le_bytes()].concat()[..]);
+
+        let error = write_pfm(std::env::temp_dir().join("unused.pfm"), 1, 1, &[&[0.0], &[0.0]]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn image_write_exr() {
+        let channels = vec![("Z".to_string(), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+                            ("N.X".to_string(), vec![0.0; 6]),
+                            ("A".to_string(), vec![-1.0, -2.0, -3.0, -4.0, -5.0, -6.0])];
+        let bytes = written("image_write_exr.exr", |path| write_exr(path, 3, 2, &channels));
+        let int = |at: usize| i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let long = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize;
+        let float = |at: usize| f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+
+        // Magic number, then version 2 with no flags.
+        assert_eq!(&bytes[..8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        // Walk the attributes, up to the empty name ending the header.
+        let string = |at: usize| {
+            let end = at + bytes[at..].iter().position(|&b| b == 0).unwrap();
+            (String::from_utf8(bytes[at..end].to_vec()).unwrap(), end + 1)
+        };
+        let mut attributes = Vec::new();
+        let mut at = 8;
+        loop {
+            let (name, next) = string(at);
+            if name.is_empty() { at = next; break; }
+            let (_, next) = string(next);
+            let size = int(next) as usize;
+            attributes.push((name, bytes[next + 4..next + 4 + size].to_vec()));
+            at = next + 4 + size;
+        }
+        let names: Vec<&str> = attributes.iter().map(|a| a.0.as_str()).collect();
+        assert_eq!(names, vec!["channels", "compression", "dataWindow", "displayWindow",
+                               "lineOrder", "pixelAspectRatio", "screenWindowCenter", "screenWindowWidth"]);
+
+        // Channels are listed in alphabetical order, each followed by 16 bytes of format.
+        let list = &attributes[0].1;
+        let mut listed = Vec::new();
+        let mut i = 0;
+        while list[i] != 0 {
+            let end = i + list[i..].iter().position(|&b| b == 0).unwrap();
+            listed.push(String::from_utf8(list[i..end].to_vec()).unwrap());
+            i = end + 1 + 16;
+        }
+        assert_eq!(listed, vec!["A", "N.X", "Z"]);
+        assert_eq!(i, list.len() - 1);
+
+        // The offset table points at one chunk per scanline, right after it and each other.
+        let line_size = 4 * 3 * 3;
+        let table_end = at + 8 * 2;
+        for y in 0..2 {
+            let offset = long(at + 8 * y);
+            assert_eq!(offset, table_end + y * (8 + line_size));
+            assert_eq!(int(offset), y as i32);
+            assert_eq!(int(offset + 4), line_size as i32);
+        }
+        assert_eq!(bytes.len(), table_end + 2 * (8 + line_size));
+
+        // The second scanline starts with its row of the first channel.
+        let second = table_end + 8 + line_size + 8;
+        assert_eq!((float(second), float(second + 4), float(second + 8)), (-4.0, -5.0, -6.0));
+    }
+}
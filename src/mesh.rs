@@ -0,0 +1,9 @@
+// Reading, writing and processing of triangle meshes.
+
+mod obj; pub use obj::*;
+mod ply; #[allow(unused_imports)] pub use ply::*;
+mod stl; #[allow(unused_imports)] pub use stl::*;
+mod generate; #[allow(unused_imports)] pub use generate::*;
+mod clean; #[allow(unused_imports)] pub use clean::*;
+mod subdivide; #[allow(unused_imports)] pub use subdivide::*;
+mod displace; #[allow(unused_imports)] pub use displace::*;
@@ -0,0 +1,312 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use na::{distance, Vector3};
+
+use crate::object::{Face, NormalWeighting, TriangleMesh};
+use crate::types::*;
+
+// Edges of a mesh that keep it from being a closed, manifold, consistently wound surface.
+#[allow(dead_code)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EdgeReport {
+    pub open: Vec<(usize, usize)>,         // Edges with a single triangle, along holes and borders.
+    pub non_manifold: Vec<(usize, usize)>, // Edges shared by more than two triangles.
+    pub misoriented: Vec<(usize, usize)>   // Edges whose two triangles run along them the same way.
+}
+
+#[allow(dead_code)]
+impl EdgeReport {
+    pub fn is_closed_manifold(&self) -> bool { self.open.is_empty() && self.non_manifold.is_empty() }
+    pub fn is_oriented(&self) -> bool { self.misoriented.is_empty() }
+}
+
+// Merges points closer together than the tolerance, returning the merged
+// points and, for every original point, the index of the one it became.
+pub fn weld(points: &[Point3f], tolerance: f32) -> (Vec<Point3f>, Vec<usize>) {
+    // Points are hashed into cells as large as the tolerance,
+    // so any match is in the same cell or a neighboring one.
+    let size = tolerance.max(1e-6);
+    let cell = |point: &Point3f| point.coords.map(|x| (x / size).floor() as i64);
+
+    let mut cells: HashMap<Vector3<i64>, Vec<usize>> = HashMap::new();
+    let mut welded = Vec::new();
+    let indices = points.iter().map(|point| {
+        let center = cell(point);
+        for x in -1..=1 { for y in -1..=1 { for z in -1..=1 {
+            if let Some(found) = cells.get(&(center + Vector3::new(x, y, z)))
+                .and_then(|list| list.iter().find(|&&i| distance(&welded[i], point) <= tolerance)) {
+                return *found;
+            }
+        }}}
+
+        welded.push(*point);
+        cells.entry(center).or_default().push(welded.len() - 1);
+        welded.len() - 1
+    }).collect();
+
+    (welded, indices)
+}
+
+// Rebuilds a mesh with new vertices and faces, keeping the rest of it.
+// The vertex colors have to match the new vertices.
+#[allow(dead_code)]
+fn rebuild(mesh: TriangleMesh, vertices: Vec<Point3f>, colors: Vec<Color>, faces: Vec<Face>) -> TriangleMesh {
+    let TriangleMesh { normals, uvs, materials, .. } = mesh;
+    TriangleMesh::from_faces(vertices, normals, uvs, materials, faces).with_vertex_colors(colors)
+}
+
+// Keeps only the given faces of a mesh.
+#[allow(dead_code)]
+fn with_faces(mesh: TriangleMesh, faces: Vec<Face>) -> TriangleMesh {
+    let TriangleMesh { vertices, normals, uvs, colors, materials, .. } = mesh;
+    TriangleMesh::from_faces(vertices, normals, uvs, materials, faces).with_vertex_colors(colors)
+}
+
+// Returns the edges of every triangle, keyed by their vertices in increasing order.
+// Each one lists the triangles along it, and whether they run along it from the lower vertex.
+#[allow(dead_code)]
+fn edge_map(faces: &[Face]) -> HashMap<(usize, usize), Vec<(usize, bool)>> {
+    let mut edges: HashMap<(usize, usize), Vec<(usize, bool)>> = HashMap::new();
+    for (i, face) in faces.iter().enumerate() {
+        let (a, b, c) = face.vertices;
+        for &(from, to) in &[(a, b), (b, c), (c, a)] {
+            edges.entry((from.min(to), from.max(to))).or_default().push((i, from < to));
+        }
+    }
+    edges
+}
+
+// Merges vertices closer together than the tolerance. Vertices no triangle uses are dropped.
+#[allow(dead_code)]
+pub fn weld_vertices(mesh: TriangleMesh, tolerance: f32) -> TriangleMesh {
+    let faces = mesh.faces();
+    let mut used = vec![false; mesh.vertices.len()];
+    for face in &faces {
+        let (a, b, c) = face.vertices;
+        used[a] = true; used[b] = true; used[c] = true;
+    }
+
+    let kept: Vec<usize> = (0..mesh.vertices.len()).filter(|&v| used[v]).collect();
+    let (vertices, indices) = weld(&kept.iter().map(|&v| mesh.vertices[v]).collect::<Vec<_>>(), tolerance);
+    let mut remap = vec![0; mesh.vertices.len()];
+    for (i, &v) in kept.iter().enumerate() { remap[v] = indices[i]; }
+
+    // Merged vertices take the color of the first one.
+    let mut colors = Vec::new();
+    if !mesh.colors.is_empty() {
+        colors = vec![Color::black(); vertices.len()];
+        for &v in kept.iter().rev() { colors[remap[v]] = mesh.colors[v]; }
+    }
+
+    let faces = faces.into_iter()
+        .map(|face| { let (a, b, c) = face.vertices; Face { vertices: (remap[a], remap[b], remap[c]), ..face } })
+        .collect();
+    rebuild(mesh, vertices, colors, faces)
+}
+
+// Removes triangles with repeated vertices or no area to speak of (compared to their longest edge),
+// which have no proper normal. Returns the mesh and how many were removed.
+#[allow(dead_code)]
+pub fn remove_degenerate(mesh: TriangleMesh) -> (TriangleMesh, usize) {
+    let faces = mesh.faces();
+    let count = faces.len();
+    let faces: Vec<Face> = faces.into_iter().filter(|face| {
+        let (a, b, c) = face.vertices;
+        if a == b || b == c || c == a { return false; }
+
+        let (a, b, c) = (mesh.vertices[a], mesh.vertices[b], mesh.vertices[c]);
+        let longest = (b - a).norm_squared().max((c - b).norm_squared()).max((a - c).norm_squared());
+        (b - a).cross(&(c - a)).norm() > 1e-6 * longest
+    }).collect();
+
+    let removed = count - faces.len();
+    (with_faces(mesh, faces), removed)
+}
+
+// Removes triangles over the same vertices as an earlier one, whichever way they are wound.
+// Returns the mesh and how many were removed.
+#[allow(dead_code)]
+pub fn remove_duplicates(mesh: TriangleMesh) -> (TriangleMesh, usize) {
+    let faces = mesh.faces();
+    let count = faces.len();
+    let mut seen = HashSet::new();
+    let faces: Vec<Face> = faces.into_iter().filter(|face| {
+        let (a, b, c) = face.vertices;
+        let mut key = [a, b, c];
+        key.sort_unstable();
+        seen.insert(key)
+    }).collect();
+
+    let removed = count - faces.len();
+    (with_faces(mesh, faces), removed)
+}
+
+// Flips triangles so that neighbors are wound consistently, running along their shared edges
+// in opposite directions. Each connected piece follows its first triangle, except that closed
+// pieces are turned to face outwards. Returns the mesh and how many triangles were flipped.
+// Pieces that can't be wound consistently, like a Moebius strip, are left with edges where
+// neighbors run the same way, which edge_report() lists as misoriented.
+// Vertex normals are left as they are, so they should be recomputed afterwards.
+#[allow(dead_code)]
+pub fn orient(mesh: TriangleMesh) -> (TriangleMesh, usize) {
+    let mut faces = mesh.faces();
+    let edges = edge_map(&faces);
+    let mut flipped: Vec<Option<bool>> = vec![None; faces.len()];
+
+    for start in 0..faces.len() {
+        if flipped[start].is_some() { continue; }
+        flipped[start] = Some(false);
+
+        let mut piece = vec![start];
+        let mut closed = true;
+        let mut queue = VecDeque::from(vec![start]);
+        while let Some(i) = queue.pop_front() {
+            let (a, b, c) = faces[i].vertices;
+            for &(from, to) in &[(a, b), (b, c), (c, a)] {
+                let edge = &edges[&(from.min(to), from.max(to))];
+                if edge.len() != 2 { closed = false; continue; }
+
+                // Flipping a triangle reverses the direction it runs along the edge.
+                let forward = (from < to) != flipped[i].unwrap();
+                let &(other, other_forward) = edge.iter().find(|&&(j, _)| j != i).unwrap_or(&edge[0]);
+                if flipped[other].is_none() {
+                    flipped[other] = Some(other_forward == forward);
+                    piece.push(other);
+                    queue.push_back(other);
+                }
+            }
+        }
+
+        // The signed volume enclosed by a closed piece is negative if it faces inwards.
+        if closed {
+            let volume: f32 = piece.iter().map(|&i| {
+                let (a, b, c) = faces[i].vertices;
+                let (a, b, c) = (mesh.vertices[a].coords, mesh.vertices[b].coords, mesh.vertices[c].coords);
+                let sign = if flipped[i].unwrap() { -1.0 } else { 1.0 };
+                sign * a.dot(&b.cross(&c))
+            }).sum();
+            if volume < 0.0 {
+                for &i in &piece { flipped[i] = flipped[i].map(|f| !f); }
+            }
+        }
+    }
+
+    let mut count = 0;
+    for (face, flip) in faces.iter_mut().zip(flipped) {
+        if flip == Some(true) { *face = face.reversed(); count += 1; }
+    }
+    (with_faces(mesh, faces), count)
+}
+
+// Finds the open, non-manifold and misoriented edges of a mesh.
+#[allow(dead_code)]
+pub fn edge_report(mesh: &TriangleMesh) -> EdgeReport {
+    let mut report = EdgeReport::default();
+    for (&edge, tris) in &edge_map(&mesh.faces()) {
+        match tris.len() {
+            1 => report.open.push(edge),
+            2 => if tris[0].1 == tris[1].1 { report.misoriented.push(edge) },
+            _ => report.non_manifold.push(edge)
+        }
+    }
+    report.open.sort_unstable();
+    report.non_manifold.sort_unstable();
+    report.misoriented.sort_unstable();
+    report
+}
+
+// Runs all cleanup steps on a mesh: welds vertices within the tolerance, removes degenerate
+// and duplicate triangles, orients them consistently and recomputes smooth normals with the
+// given crease angle (in radians).
+#[allow(dead_code)]
+pub fn clean(mesh: TriangleMesh, tolerance: f32, crease_angle: f32) -> TriangleMesh {
+    let mesh = weld_vertices(mesh, tolerance);
+    let (mesh, _) = remove_degenerate(mesh);
+    let (mesh, _) = remove_duplicates(mesh);
+    let (mesh, _) = orient(mesh);
+    mesh.smooth(NormalWeighting::Angle, crease_angle)
+}
+
+#[cfg(test)]
+mod tests {
+    use na::Point3;
+
+    use super::*;
+    use crate::mesh::cube;
+    use crate::object::Surface;
+
+    #[test]
+    fn clean_triangles() {
+        // A quad as two triangles with their own copies of the shared vertices, along with
+        // a triangle with a repeated vertex, a sliver, and a copy of the first triangle facing the other way.
+        let vertices = vec![
+            Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(1.0, 1.0, 0.0),
+            Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 0.0), Point3::new(0.0, 1.0, 1e-6),
+            Point3::new(0.5, 0.5, 0.0)
+        ];
+        let tris = vec![(0, 1, 2), (3, 4, 5), (0, 0, 1), (0, 6, 2), (2, 1, 0)];
+        let mesh = TriangleMesh::new_solid(vertices, tris, Texture::white());
+        // The degenerate triangles still get a proper normal.
+        assert!(mesh.geometric_normal(Point3::new(0.0, 0.0, 0.0)).iter().all(|x| x.is_finite()));
+
+        let mesh = weld_vertices(mesh, 1e-4);
+        assert_eq!(mesh.vertices.len(), 5);
+        let (mesh, removed) = remove_degenerate(mesh);
+        assert_eq!(removed, 2);
+        let (mesh, removed) = remove_duplicates(mesh);
+        assert_eq!(removed, 1);
+        assert_eq!(mesh.triangles.len(), 2);
+
+        let report = edge_report(&mesh);
+        assert_eq!(report.open.len(), 4);
+        assert!(report.non_manifold.is_empty());
+
+        // A third triangle on the diagonal makes it non-manifold.
+        let mut faces = mesh.faces();
+        faces.push(Face::new(0, 2, 4, 0));
+        let mut vertices = mesh.vertices.clone();
+        vertices.push(Point3::new(0.5, 0.5, 1.0));
+        let mesh = TriangleMesh::from_faces(vertices, Vec::new(), Vec::new(), vec![Box::new(|_, _| Texture::white())], faces);
+        assert_eq!(edge_report(&mesh).non_manifold, vec![(0, 2)]);
+    }
+
+    #[test]
+    fn clean_orient() {
+        let mesh = weld_vertices(cube(2.0, |_, _| Texture::white()), 1e-4);
+        assert_eq!(mesh.vertices.len(), 8);
+        assert!(edge_report(&mesh).is_closed_manifold());
+
+        // Turn the cube inside out, and flip a couple of triangles back.
+        let faces = mesh.faces().into_iter().enumerate()
+            .map(|(i, face)| if i == 3 || i == 8 { face } else { face.reversed() })
+            .collect();
+        let mesh = with_faces(mesh, faces);
+
+        let (mesh, flipped) = orient(mesh);
+        assert_eq!(flipped, 10);
+        let mesh = mesh.smooth(NormalWeighting::Angle, 0.5);
+        for &point in &[Point3::new(1.0, 0.2, 0.3), Point3::new(-0.4, -1.0, 0.1), Point3::new(0.5, 0.5, -1.0)] {
+            assert!(mesh.normal(point).dot(&point.coords) > 0.0);
+        }
+        assert_eq!(mesh.intervals(Ray::new(Point3::new(0.3, 0.2, -5.0), Vector3::z())), vec![(4.0, 6.0)]);
+        assert!(edge_report(&mesh).is_oriented());
+
+        // A Moebius strip has one side, so some edge stays misoriented whichever way it's wound.
+        let n = 8;
+        let vertices = (0..n).flat_map(|i| {
+            let angle = 2.0 * std::f32::consts::PI * i as f32 / n as f32;
+            let center = Vector3::new(angle.cos(), 0.0, angle.sin()) * 2.0;
+            let across = Vector3::new(angle.cos(), 0.0, angle.sin()) * (angle / 2.0).cos() + Vector3::y() * (angle / 2.0).sin();
+            vec![Point3::from(center + 0.5 * across), Point3::from(center - 0.5 * across)]
+        }).collect();
+        let tris = (0..n).flat_map(|i| {
+            let (top, bottom) = (2 * i, 2 * i + 1);
+            // Going round once turns the strip over, so the last quad joins top to bottom.
+            let (next_top, next_bottom) = if i + 1 < n { (2 * i + 2, 2 * i + 3) } else { (1, 0) };
+            vec![(top, bottom, next_bottom), (top, next_bottom, next_top)]
+        }).collect();
+        let (strip, _) = orient(TriangleMesh::new_solid(vertices, tris, Texture::white()));
+        let report = edge_report(&strip);
+        assert!(report.non_manifold.is_empty() && !report.is_oriented());
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use na::{Point3, Vector3};
+
+use crate::camera::Camera;
+use crate::object::{Deferred, Face, NormalWeighting, TriangleMesh};
+use crate::types::*;
+use super::subdivide::{average_unit, average_uv, key, split, Corners};
+
+// How finely to tessellate a mesh: the longest an edge may be,
+// either in world space or in pixels as seen from a camera.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum Detail<'a> {
+    EdgeLength(f32),
+    Pixels(&'a Camera, f32)
+}
+
+#[allow(dead_code)]
+impl Detail<'_> {
+    // The longest an edge around the given point may be, in world space.
+    fn edge_length(&self, point: Point3f) -> f32 {
+        match *self {
+            Detail::EdgeLength(length) => length,
+            Detail::Pixels(camera, pixels) => pixels * camera.pixel_size(point)
+        }
+    }
+}
+
+// The most times an edge is split in two, which bounds the size of the mesh
+// when the detail asks for too much (such as right in front of the camera).
+#[allow(dead_code)]
+const MAX_SPLITS: usize = 12;
+
+// Splits a triangle along the middles of its edges ab, bc and ca that have one.
+#[allow(dead_code)]
+fn split_marked((a, b, c): (usize, usize, usize), middles: [Option<usize>; 3]) -> Vec<(usize, usize, usize)> {
+    let marked = middles.iter().filter(|m| m.is_some()).count();
+    // Turn the triangle so that the first edge is split, or with two split, so that the last one isn't.
+    let r = (0..3).find(|&r| match marked {
+        1 => middles[r].is_some(),
+        2 => middles[(r + 2) % 3].is_none(),
+        _ => true
+    }).unwrap_or(0);
+    let corners = [a, b, c];
+    let v = |i: usize| corners[(i + r) % 3];
+    let m = |i: usize| middles[(i + r) % 3].unwrap();
+
+    match marked {
+        0 => vec![(a, b, c)],
+        1 => vec![(v(0), m(0), v(2)), (m(0), v(1), v(2))],
+        2 => vec![(m(0), v(1), m(1)), (v(0), m(0), m(1)), (v(0), m(1), v(2))],
+        _ => split((a, b, c), (m(0), m(1), m(2))).to_vec()
+    }
+}
+
+#[allow(dead_code)]
+fn split_corners<T: Copy>(corners: &mut Corners<T>, (a, b, c): (usize, usize, usize), marked: [bool; 3]) -> Vec<(usize, usize, usize)> {
+    let mut middle = |marked: bool, from: usize, to: usize| if marked { Some(corners.edge(from, to)) } else { None };
+    let middles = [middle(marked[0], a, b), middle(marked[1], b, c), middle(marked[2], c, a)];
+    split_marked((a, b, c), middles)
+}
+
+// Splits the triangles of a mesh until no edge is longer than the detail asks for. Edges are split
+// at their middles, which are shared by the triangles on both sides so no cracks open up. Only long
+// edges are split, so a mesh seen from a camera gets fine up close and stays coarse far away.
+// Texture coordinates, normals and vertex colors are interpolated linearly.
+#[allow(dead_code)]
+pub fn tessellate_adaptive(mesh: TriangleMesh, detail: Detail) -> TriangleMesh {
+    let mut faces = mesh.faces();
+    let TriangleMesh { mut vertices, normals, uvs, mut colors, materials, .. } = mesh;
+    let mut normals = Corners::new(normals, average_unit);
+    let mut uvs = Corners::new(uvs, average_uv);
+
+    for _ in 0..MAX_SPLITS {
+        let mut middles: HashMap<(usize, usize), Option<usize>> = HashMap::new();
+        for face in &faces {
+            let (a, b, c) = face.vertices;
+            for &(from, to) in &[(a, b), (b, c), (c, a)] {
+                let (vertices, colors) = (&mut vertices, &mut colors);
+                middles.entry(key(from, to)).or_insert_with(|| {
+                    let middle = Point3::from((vertices[from].coords + vertices[to].coords) / 2.0);
+                    if (vertices[to] - vertices[from]).norm() <= detail.edge_length(middle) { return None; }
+                    vertices.push(middle);
+                    if !colors.is_empty() { colors.push(0.5 * (colors[from] + colors[to])); }
+                    Some(vertices.len() - 1)
+                });
+            }
+        }
+        if middles.values().all(Option::is_none) { break; }
+
+        faces = faces.into_iter().flat_map(|face| {
+            let (a, b, c) = face.vertices;
+            let middles = [middles[&key(a, b)], middles[&key(b, c)], middles[&key(c, a)]];
+            let marked = [middles[0].is_some(), middles[1].is_some(), middles[2].is_some()];
+            let face_normals = face.normals.map(|handles| split_corners(&mut normals, handles, marked));
+            let face_uvs = face.uvs.map(|handles| split_corners(&mut uvs, handles, marked));
+
+            split_marked((a, b, c), middles).into_iter().enumerate().map(|(i, vertices)| Face {
+                vertices,
+                normals: face_normals.as_ref().map(|n| n[i]),
+                uvs: face_uvs.as_ref().map(|t| t[i]),
+                material: face.material
+            }).collect::<Vec<_>>()
+        }).collect();
+    }
+
+    TriangleMesh::from_faces(vertices, normals.values, uvs.values, materials, faces).with_vertex_colors(colors)
+}
+
+// Moves every vertex along its normal by the height map at its texture coordinates, times the scale.
+// Vertices with several normals (along creases) or texture coordinates (along seams) move along
+// the average normal by the height at the first coordinates, and those without texture coordinates
+// stay put. Flat shaded meshes move along the face normals. Triangles only stay joined where they
+// share vertices, so meshes with separate copies of the same point (such as from STL files) have
+// to be welded first (see weld_vertices), or they tear apart along those edges.
+// Normals are recomputed afterwards, keeping faces that meet at more than the crease angle sharp.
+#[allow(dead_code)]
+pub fn displace(mesh: TriangleMesh, height: impl Fn(f32, f32) -> f32, scale: f32, crease_angle: f32) -> TriangleMesh {
+    let faces = mesh.faces();
+    let TriangleMesh { mut vertices, normals, uvs, colors, materials, .. } = mesh;
+
+    let mut directions = vec![Vector3::zeros(); vertices.len()];
+    let mut coordinates = vec![None; vertices.len()];
+    for face in &faces {
+        let (a, b, c) = face.vertices;
+        let face_normal = (vertices[b] - vertices[a]).cross(&(vertices[c] - vertices[a]));
+        let corner_normals = face.normals
+            .map(|(na, nb, nc)| [normals[na].into_inner(), normals[nb].into_inner(), normals[nc].into_inner()])
+            .unwrap_or([face_normal; 3]);
+        let corner_uvs = face.uvs.map(|(ta, tb, tc)| [uvs[ta], uvs[tb], uvs[tc]]);
+
+        for (i, &v) in [a, b, c].iter().enumerate() {
+            directions[v] += corner_normals[i];
+            if coordinates[v].is_none() { coordinates[v] = corner_uvs.map(|t| t[i]); }
+        }
+    }
+
+    for (v, vertex) in vertices.iter_mut().enumerate() {
+        if let (Some(direction), Some((s, t))) = (directions[v].try_normalize(0.0), coordinates[v]) {
+            *vertex += direction * scale * height(s, t);
+        }
+    }
+
+    TriangleMesh::from_faces(vertices, normals, uvs, materials, faces)
+        .with_vertex_colors(colors)
+        .smooth(NormalWeighting::Angle, crease_angle)
+}
+
+// Tessellates a mesh to the given detail and then displaces it, turning a coarse proxy into fine surface detail.
+#[allow(dead_code)]
+pub fn displace_adaptive(mesh: TriangleMesh, detail: Detail, height: impl Fn(f32, f32) -> f32, scale: f32, crease_angle: f32) -> TriangleMesh {
+    displace(tessellate_adaptive(mesh, detail), height, scale, crease_angle)
+}
+
+// A mesh displaced at render time. The proxy is tessellated until its edges are no longer than
+// the given number of pixels, as seen from the camera rendering the scene, and then displaced by
+// the height map (see displace). Added to a scene's deferred geometry, it's built by Scene::prepare.
+#[allow(dead_code)]
+pub struct Displacement {
+    pub proxy: TriangleMesh,
+    pub height: Box<dyn Fn(f32, f32) -> f32>,
+    pub scale: f32,
+    pub pixels: f32,
+    pub crease_angle: f32
+}
+
+impl Deferred for Displacement {
+    fn build(self: Box<Self>, camera: &Camera) -> TriangleMesh {
+        let Displacement { proxy, height, scale, pixels, crease_angle } = *self;
+        displace_adaptive(proxy, Detail::Pixels(camera, pixels), height, scale, crease_angle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::{edge_report, grid, icosphere};
+    use crate::object::{Scene, Surface};
+    use crate::render::{render, RenderSettings};
+
+    fn edges(mesh: &TriangleMesh) -> Vec<(Point3f, Point3f)> {
+        mesh.faces().iter().flat_map(|face| {
+            let (a, b, c) = face.vertices;
+            vec![(a, b), (b, c), (c, a)].into_iter().map(|(from, to)| (mesh.vertices[from], mesh.vertices[to])).collect::<Vec<_>>()
+        }).collect()
+    }
+
+    #[test]
+    fn displace_tessellate() {
+        let mesh = tessellate_adaptive(grid(4.0, 4.0, 1, 1, |u, v| Texture::new(u, v, 0.0, 1.0)), Detail::EdgeLength(0.5));
+        assert!(edges(&mesh).iter().all(|(a, b)| (b - a).norm() <= 0.5));
+        // No cracks: the only open edges are along the border of the grid.
+        let report = edge_report(&mesh);
+        assert!(report.non_manifold.is_empty());
+        let on_border = |p: Point3f| p.x.abs() == 2.0 || p.z.abs() == 2.0;
+        assert!(report.open.iter().all(|&(a, b)| on_border(mesh.vertices[a]) && on_border(mesh.vertices[b])));
+        let (u, v) = mesh.uv(Point3::new(0.3, 0.0, -1.1));
+        assert!((u - 0.575).abs() < 1e-5 && (v - 0.225).abs() < 1e-5);
+
+        // Seen from a camera, edges up close end up shorter than those far away.
+        let camera = Camera::new(Point3::new(0.0, 1.0, -12.0), Vector3::z(), 1.0, 1.0, 2.0, 100);
+        let mesh = tessellate_adaptive(grid(20.0, 20.0, 1, 1, |_, _| Texture::white()), Detail::Pixels(&camera, 8.0));
+        let longest = |near: bool| edges(&mesh).iter()
+            .filter(|(a, b)| ((a.z + b.z) / 2.0 < -5.0) == near)
+            .map(|(a, b)| (b - a).norm())
+            .fold(0.0, f32::max);
+        assert!(longest(true) > 0.0 && longest(true) < longest(false));
+        assert!(edges(&mesh).iter().all(|(a, b)| (b - a).norm() <= 8.0 * camera.pixel_size(Point3::from((a.coords + b.coords) / 2.0))));
+    }
+
+    #[test]
+    fn displace_height() {
+        // A grid raised by its u coordinate becomes a slope.
+        let mesh = displace_adaptive(grid(4.0, 4.0, 1, 1, |_, _| Texture::white()), Detail::EdgeLength(1.0), |u, _| u, 1.0, 0.5);
+        assert!(mesh.vertices.iter().all(|p| (p.y - (p.x / 4.0 + 0.5)).abs() < 1e-5));
+        let normal = mesh.normal(Point3::new(0.2, 0.55, 0.3));
+        assert!((normal.into_inner() - Vector3::new(-0.25, 1.0, 0.0).normalize()).norm() < 1e-4);
+
+        // A sphere pushed out evenly stays a closed sphere, seams and all.
+        let mesh = displace(icosphere(1.0, 2, |_, _| Texture::white()), |_, _| 1.0, 1.0, 0.5);
+        assert!(mesh.vertices.iter().all(|p| (p.coords.norm() - 2.0).abs() < 1e-4));
+        assert_eq!(mesh.intervals(Ray::new(Point3::new(0.1, 0.2, -5.0), Vector3::z())).len(), 1);
+        assert!(mesh.normal(Point3::new(0.0, 2.0, 0.0)).y > 0.99);
+    }
+
+    #[test]
+    fn displace_deferred() {
+        let camera = Camera::new(Point3::new(0.0, 5.0, -5.0), Vector3::new(0.0, -1.0, 1.0), 1.0, 1.0, 2.0, 16);
+        let displacement = Displacement { proxy: grid(4.0, 4.0, 1, 1, |_, _| Texture::white()), height: Box::new(|u, _| u),
+                                          scale: 1.0, pixels: 1.0, crease_angle: 0.5 };
+        let mut scene = Scene { objects: Vec::new(), lights: Vec::new(), deferred: vec![Box::new(displacement)], background: Color::black() };
+
+        // The displaced mesh only exists once the scene is prepared for the camera.
+        assert_eq!(render(&camera, &scene, &RenderSettings::default()).err().unwrap().kind(), std::io::ErrorKind::InvalidInput);
+        scene.prepare(&camera);
+        assert!(scene.deferred.is_empty() && scene.objects.len() == 1);
+        let t = scene.objects[0].intersect(Ray::new(Point3::new(1.0, 5.0, 0.3), -Vector3::y())).unwrap();
+        assert!((t - (5.0 - 0.75)).abs() < 1e-4);
+        assert!(render(&camera, &scene, &RenderSettings::default()).is_ok());
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use na::{Matrix3, Point3, Unit, Vector3};
+
+use crate::object::{Cone, Cylinder, Face, Surface, Tessellation, Torus, TriangleMesh};
+use crate::types::*;
+
+// Generators for common meshes. Each one is centered on (or stands on) the origin,
+// with y pointing up, and is smooth shaded and textured with its own texture coordinates.
+// Instances can be used to place them in the world.
+
+// Creates a cube with the given edge length. Every face is mapped separately over 0-1.
+#[allow(dead_code)]
+pub fn cube(size: f32, texture: impl 'static + Fn(f32, f32) -> Texture) -> TriangleMesh {
+    let mut tessellation = Tessellation::new();
+    for axis in 0..3 {
+        for &side in &[1.0, -1.0] {
+            // The two other axes, in an order that makes them span the face counterclockwise seen from outside.
+            let (a, b) = if side > 0.0 { ((axis + 1) % 3, (axis + 2) % 3) } else { ((axis + 2) % 3, (axis + 1) % 3) };
+            tessellation.patch(1, 1, |s, t| {
+                let mut p = Vector3::zeros();
+                p[axis] = side;
+                p[a] = 2.0 * s - 1.0;
+                p[b] = 2.0 * t - 1.0;
+                let mut normal = Vector3::zeros();
+                normal[axis] = side;
+                (Point3::from(0.5 * size * p), normal, (s, t))
+            });
+        }
+    }
+    tessellation.textured(Box::new(texture))
+}
+
+// Creates a sphere out of a grid of segments around the axis and rings from pole to pole,
+// textured with spherical coordinates like Sphere.
+#[allow(dead_code)]
+pub fn uv_sphere(radius: f32, segments: usize, rings: usize, texture: impl 'static + Fn(f32, f32) -> Texture) -> TriangleMesh {
+    let mut tessellation = Tessellation::new();
+    tessellation.patch(segments.max(3), rings.max(2), |s, t| {
+        let (phi, theta) = (2.0 * PI * (s - 0.5), PI * t);
+        let normal = Vector3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+        (Point3::from(radius * normal), normal, (s, t))
+    });
+    tessellation.textured(Box::new(texture))
+}
+
+// Creates a sphere by splitting the faces of an icosahedron into four, as many times as the level,
+// which spreads the triangles much more evenly than a UV sphere does. It's textured with spherical
+// coordinates, with vertices on the seam and at the poles split so the coordinates don't wrap.
+#[allow(dead_code)]
+pub fn icosphere(radius: f32, level: usize, texture: impl 'static + Fn(f32, f32) -> Texture) -> TriangleMesh {
+    let g = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut points: Vec<Vector3f> = [
+        (-1.0, g, 0.0), (1.0, g, 0.0), (-1.0, -g, 0.0), (1.0, -g, 0.0),
+        (0.0, -1.0, g), (0.0, 1.0, g), (0.0, -1.0, -g), (0.0, 1.0, -g),
+        (g, 0.0, -1.0), (g, 0.0, 1.0), (-g, 0.0, -1.0), (-g, 0.0, 1.0)
+    ].iter().map(|&(x, y, z)| Vector3::new(x, y, z).normalize()).collect();
+    let mut tris = vec![
+        (0, 11, 5), (0, 5, 1), (0, 1, 7), (0, 7, 10), (0, 10, 11),
+        (1, 5, 9), (5, 11, 4), (11, 10, 2), (10, 7, 6), (7, 1, 8),
+        (3, 9, 4), (3, 4, 2), (3, 2, 6), (3, 6, 8), (3, 8, 9),
+        (4, 9, 5), (2, 4, 11), (6, 2, 10), (8, 6, 7), (9, 8, 1)
+    ];
+
+    for _ in 0..level {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: usize, b: usize| *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+            points.push((points[a] + points[b]).normalize());
+            points.len() - 1
+        });
+        tris = tris.into_iter().flat_map(|(a, b, c)| {
+            let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+            vec![(a, ab, ca), (b, bc, ab), (c, ca, bc), (ab, bc, ca)]
+        }).collect();
+    }
+
+    let mut uvs = Vec::with_capacity(3 * tris.len());
+    let faces = tris.iter().enumerate().map(|(i, &(a, b, c))| {
+        let mut corners: Vec<(f32, f32)> = [a, b, c].iter()
+            .map(|&v| (0.5 + points[v].z.atan2(points[v].x) / (2.0 * PI), points[v].y.acos() / PI))
+            .collect();
+        // Triangles straddling the seam get coordinates past 1 rather than wrapping back around.
+        if corners.iter().any(|uv| uv.0 > 0.75) {
+            for uv in corners.iter_mut() { if uv.0 < 0.25 { uv.0 += 1.0; } }
+        }
+        // At a pole the angle around the axis is undefined, so the one between the other corners is used.
+        for j in 0..3 {
+            if points[[a, b, c][j]].xz().norm() < 1e-6 { corners[j].0 = 0.5 * (corners[(j + 1) % 3].0 + corners[(j + 2) % 3].0); }
+        }
+        uvs.extend(corners);
+        Face { vertices: (a, b, c), normals: Some((a, b, c)), uvs: Some((3 * i, 3 * i + 1, 3 * i + 2)), material: 0 }
+    }).collect();
+
+    let normals = points.iter().map(|&p| Unit::new_unchecked(p)).collect();
+    let vertices = points.into_iter().map(|p| Point3::from(radius * p)).collect();
+    TriangleMesh::from_faces(vertices, normals, uvs, vec![Box::new(texture)], faces)
+}
+
+// Creates a capped cylinder standing on the origin, textured like Cylinder.
+#[allow(dead_code)]
+pub fn cylinder(radius: f32, height: f32, segments: usize, texture: impl 'static + Fn(f32, f32) -> Texture) -> TriangleMesh {
+    let cylinder = Cylinder::new(Point3::origin(), Point3::new(0.0, height, 0.0), radius, texture);
+    cylinder.tessellate(segments.max(3)).expect("cylinders can be tessellated")
+}
+
+// Creates a capped cone with its base on the origin, textured like Cone.
+#[allow(dead_code)]
+pub fn cone(radius: f32, height: f32, segments: usize, texture: impl 'static + Fn(f32, f32) -> Texture) -> TriangleMesh {
+    let cone = Cone::new(Point3::origin(), Point3::new(0.0, height, 0.0), radius, texture);
+    cone.tessellate(segments.max(3)).expect("cones can be tessellated")
+}
+
+// Creates a torus lying in the xz-plane, with segments around the axis and sides around the tube, textured like Torus.
+#[allow(dead_code)]
+pub fn torus(major_radius: f32, minor_radius: f32, segments: usize, sides: usize,
+             texture: impl 'static + Fn(f32, f32) -> Texture) -> TriangleMesh {
+    Torus::new(Point3::origin(), Vector3::y(), major_radius, minor_radius, texture).tessellate_grid(segments.max(3), sides.max(3))
+}
+
+// Creates a flat grid in the xz-plane facing up, mapped over 0-1 along x and z.
+#[allow(dead_code)]
+pub fn grid(width: f32, depth: f32, columns: usize, rows: usize, texture: impl 'static + Fn(f32, f32) -> Texture) -> TriangleMesh {
+    let mut tessellation = Tessellation::new();
+    tessellation.patch(columns, rows, |s, t| (Point3::new(width * (s - 0.5), 0.0, depth * (t - 0.5)), Vector3::y(), (s, t)));
+    tessellation.textured(Box::new(texture))
+}
+
+// Control points of the Utah teapot, with z pointing up.
+#[allow(dead_code)]
+const TEAPOT_POINTS: [(f32, f32, f32); 127] = [
+    (0.2, 0.0, 2.7), (0.2, -0.112, 2.7), (0.112, -0.2, 2.7), (0.0, -0.2, 2.7),
+    (1.3375, 0.0, 2.53125), (1.3375, -0.749, 2.53125), (0.749, -1.3375, 2.53125), (0.0, -1.3375, 2.53125),
+    (1.4375, 0.0, 2.53125), (1.4375, -0.805, 2.53125), (0.805, -1.4375, 2.53125), (0.0, -1.4375, 2.53125),
+    (1.5, 0.0, 2.4), (1.5, -0.84, 2.4), (0.84, -1.5, 2.4), (0.0, -1.5, 2.4),
+    (1.75, 0.0, 1.875), (1.75, -0.98, 1.875), (0.98, -1.75, 1.875), (0.0, -1.75, 1.875),
+    (2.0, 0.0, 1.35), (2.0, -1.12, 1.35), (1.12, -2.0, 1.35), (0.0, -2.0, 1.35),
+    (2.0, 0.0, 0.9), (2.0, -1.12, 0.9), (1.12, -2.0, 0.9), (0.0, -2.0, 0.9),
+    (-2.0, 0.0, 0.9), (2.0, 0.0, 0.45), (2.0, -1.12, 0.45), (1.12, -2.0, 0.45),
+    (0.0, -2.0, 0.45), (1.5, 0.0, 0.225), (1.5, -0.84, 0.225), (0.84, -1.5, 0.225),
+    (0.0, -1.5, 0.225), (1.5, 0.0, 0.15), (1.5, -0.84, 0.15), (0.84, -1.5, 0.15),
+    (0.0, -1.5, 0.15), (-1.6, 0.0, 2.025), (-1.6, -0.3, 2.025), (-1.5, -0.3, 2.25),
+    (-1.5, 0.0, 2.25), (-2.3, 0.0, 2.025), (-2.3, -0.3, 2.025), (-2.5, -0.3, 2.25),
+    (-2.5, 0.0, 2.25), (-2.7, 0.0, 2.025), (-2.7, -0.3, 2.025), (-3.0, -0.3, 2.25),
+    (-3.0, 0.0, 2.25), (-2.7, 0.0, 1.8), (-2.7, -0.3, 1.8), (-3.0, -0.3, 1.8),
+    (-3.0, 0.0, 1.8), (-2.7, 0.0, 1.575), (-2.7, -0.3, 1.575), (-3.0, -0.3, 1.35),
+    (-3.0, 0.0, 1.35), (-2.5, 0.0, 1.125), (-2.5, -0.3, 1.125), (-2.65, -0.3, 0.9375),
+    (-2.65, 0.0, 0.9375), (-2.0, -0.3, 0.9), (-1.9, -0.3, 0.6), (-1.9, 0.0, 0.6),
+    (1.7, 0.0, 1.425), (1.7, -0.66, 1.425), (1.7, -0.66, 0.6), (1.7, 0.0, 0.6),
+    (2.6, 0.0, 1.425), (2.6, -0.66, 1.425), (3.1, -0.66, 0.825), (3.1, 0.0, 0.825),
+    (2.3, 0.0, 2.1), (2.3, -0.25, 2.1), (2.4, -0.25, 2.025), (2.4, 0.0, 2.025),
+    (2.7, 0.0, 2.4), (2.7, -0.25, 2.4), (3.3, -0.25, 2.4), (3.3, 0.0, 2.4),
+    (2.8, 0.0, 2.475), (2.8, -0.25, 2.475), (3.525, -0.25, 2.49375), (3.525, 0.0, 2.49375),
+    (2.9, 0.0, 2.475), (2.9, -0.15, 2.475), (3.45, -0.15, 2.5125), (3.45, 0.0, 2.5125),
+    (2.8, 0.0, 2.4), (2.8, -0.15, 2.4), (3.2, -0.15, 2.4), (3.2, 0.0, 2.4),
+    (0.0, 0.0, 3.15), (0.8, 0.0, 3.15), (0.8, -0.45, 3.15), (0.45, -0.8, 3.15),
+    (0.0, -0.8, 3.15), (0.0, 0.0, 2.85), (1.4, 0.0, 2.4), (1.4, -0.784, 2.4),
+    (0.784, -1.4, 2.4), (0.0, -1.4, 2.4), (0.4, 0.0, 2.55), (0.4, -0.224, 2.55),
+    (0.224, -0.4, 2.55), (0.0, -0.4, 2.55), (1.3, 0.0, 2.55), (1.3, -0.728, 2.55),
+    (0.728, -1.3, 2.55), (0.0, -1.3, 2.55), (1.3, 0.0, 2.4), (1.3, -0.728, 2.4),
+    (0.728, -1.3, 2.4), (0.0, -1.3, 2.4), (0.0, 0.0, 0.0), (1.425, -0.798, 0.0),
+    (1.5, 0.0, 0.075), (1.425, 0.0, 0.0), (0.798, -1.425, 0.0), (0.0, -1.5, 0.075),
+    (0.0, -1.425, 0.0), (1.5, -0.84, 0.075), (0.84, -1.5, 0.075)
+];
+
+// The teapot's patches, as 4x4 control points. The rim, body, lid and bottom cover a quarter
+// turn and are mirrored into all four quadrants; the handle and spout are mirrored across y.
+#[allow(dead_code)]
+const TEAPOT_PATCHES: [([usize; 16], bool); 10] = [
+    ([102, 103, 104, 105, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15], true),
+    ([12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27], true),
+    ([24, 25, 26, 27, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40], true),
+    ([96, 96, 96, 96, 97, 98, 99, 100, 101, 101, 101, 101, 0, 1, 2, 3], true),
+    ([0, 1, 2, 3, 106, 107, 108, 109, 110, 111, 112, 113, 114, 115, 116, 117], true),
+    ([118, 118, 118, 118, 124, 122, 119, 121, 123, 126, 125, 120, 40, 39, 38, 37], true),
+    ([41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56], false),
+    ([53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 28, 65, 66, 67], false),
+    ([68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83], false),
+    ([80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95], false)
+];
+
+// Evaluates the cubic Bernstein polynomials at t, along with their derivatives.
+#[allow(dead_code)]
+fn bernstein(t: f32) -> ([f32; 4], [f32; 4]) {
+    let s = 1.0 - t;
+    ([s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t],
+     [-3.0 * s * s, 3.0 * s * (s - 2.0 * t), 3.0 * t * (2.0 * s - t), 3.0 * t * t])
+}
+
+// Creates the Utah teapot, standing on the origin and about 3.15 units high, by tessellating
+// each of its 32 Bezier patches into a grid of the given resolution. Every patch is mapped over 0-1.
+#[allow(dead_code)]
+pub fn teapot(resolution: usize, texture: impl 'static + Fn(f32, f32) -> Texture) -> TriangleMesh {
+    // Turns z up into y up.
+    let to_world = Matrix3::new(1.0, 0.0, 0.0,
+                                0.0, 0.0, 1.0,
+                                0.0, -1.0, 0.0);
+    let mut tessellation = Tessellation::new();
+
+    for &(indices, quadrants) in &TEAPOT_PATCHES {
+        let mirrors: &[(f32, f32)] = if quadrants { &[(1.0, 1.0), (-1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)] } else { &[(1.0, 1.0), (1.0, -1.0)] };
+
+        for &(mx, my) in mirrors {
+            let points: Vec<Vector3f> = indices.iter()
+                .map(|&i| { let (x, y, z) = TEAPOT_POINTS[i]; to_world * Vector3::new(mx * x, my * y, z) })
+                .collect();
+            let evaluate = |s: f32, t: f32| {
+                let ((bs, ds), (bt, dt)) = (bernstein(s), bernstein(t));
+                let (mut p, mut dp_ds, mut dp_dt) = (Vector3::zeros(), Vector3::zeros(), Vector3::zeros());
+                for j in 0..4 {
+                    for i in 0..4 {
+                        let point = points[4 * j + i];
+                        p += bs[i] * bt[j] * point;
+                        dp_ds += ds[i] * bt[j] * point;
+                        dp_dt += bs[i] * dt[j] * point;
+                    }
+                }
+                // Mirroring reverses the orientation of the patch.
+                (p, mx * my * dp_ds.cross(&dp_dt))
+            };
+
+            tessellation.patch(resolution, resolution, |s, t| {
+                let (p, mut normal) = evaluate(s, t);
+                // Where a row of control points collapses into one (at the top of the lid and the
+                // bottom), the derivatives vanish, so the normal is taken from just inside the patch.
+                if normal.norm_squared() < 1e-12 {
+                    normal = evaluate(s.clamp(1e-3, 1.0 - 1e-3), t.clamp(1e-3, 1.0 - 1e-3)).1;
+                }
+                (Point3::from(p), normal, (s, t))
+            });
+        }
+    }
+    tessellation.textured(Box::new(texture))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Surface;
+
+    #[test]
+    fn generate_shapes() {
+        let cube = cube(2.0, |_, _| Texture::white());
+        assert_eq!(cube.triangles.len(), 12);
+        assert_eq!(cube.intervals(Ray::new(Point3::new(0.5, 0.5, -5.0), Vector3::z())), vec![(4.0, 6.0)]);
+
+        let sphere = icosphere(2.0, 2, |_, _| Texture::white());
+        assert_eq!(sphere.triangles.len(), 320);
+        assert_eq!(sphere.vertices.len(), 162);
+        assert!(sphere.vertices.iter().all(|p| (p.coords.norm() - 2.0).abs() < 1e-5));
+        // No triangle's texture coordinates wrap around the seam.
+        for face in sphere.faces() {
+            let (a, b, c) = face.uvs.unwrap();
+            let u = [sphere.uvs[a].0, sphere.uvs[b].0, sphere.uvs[c].0];
+            assert!(u.iter().cloned().fold(0.0, f32::max) - u.iter().cloned().fold(1.0, f32::min) < 0.25);
+        }
+
+        let sphere = uv_sphere(1.0, 16, 8, |_, _| Texture::white());
+        let t = sphere.intersect(Ray::new(Point3::new(0.0, 0.0, -5.0), Vector3::z())).unwrap();
+        assert!((t - 4.0).abs() < 0.05);
+
+        let cylinder = cylinder(1.0, 2.0, 16, |_, _| Texture::white());
+        assert_eq!(cylinder.intersect(Ray::new(Point3::new(0.0, 5.0, 0.0), -Vector3::y())), Some(3.0));
+        let cone = cone(1.0, 2.0, 16, |_, _| Texture::white());
+        let ray = Ray::new(Point3::new(-5.0, 1.0, 0.0), Vector3::x());
+        assert!((cone.intersect(ray).unwrap() - 4.5).abs() < 0.01);
+        assert!(cone.normal(ray.project(4.5)).x < 0.0);
+
+        let torus = torus(2.0, 0.5, 24, 12, |_, _| Texture::white());
+        assert_eq!(torus.intervals(Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::x())).len(), 2);
+
+        let grid = grid(4.0, 2.0, 4, 2, |_, _| Texture::white());
+        assert_eq!(grid.triangles.len(), 16);
+        let (u, v) = grid.uv(Point3::new(1.0, 0.0, 0.5));
+        assert!((u - 0.75).abs() < 1e-5 && (v - 0.75).abs() < 1e-5);
+    }
+
+    #[test]
+    fn generate_teapot() {
+        let teapot = teapot(4, |_, _| Texture::white());
+        // The patches that collapse at the top of the lid and the bottom lose a triangle per quad along that edge.
+        assert_eq!(teapot.triangles.len(), 32 * 4 * 4 * 2 - 8 * 4);
+
+        let (min, max) = teapot.vertices.iter().fold((Vector3::repeat(f32::INFINITY), Vector3::repeat(f32::NEG_INFINITY)),
+                                                     |(min, max), p| (min.zip_map(&p.coords, f32::min), max.zip_map(&p.coords, f32::max)));
+        assert!((min - Vector3::new(-3.0, 0.0, -2.0)).norm() < 1e-5);
+        assert!((max - Vector3::new(3.43, 3.15, 2.0)).norm() < 0.1);
+
+        // The lid's knob from above, and the body from the side, both face outwards.
+        let ray = Ray::new(Point3::new(0.0, 5.0, 0.01), -Vector3::y());
+        let t = teapot.intersect(ray).unwrap();
+        assert!((t - 1.85).abs() < 0.01);
+        assert!(teapot.normal(ray.project(t)).y > 0.99);
+        let ray = Ray::new(Point3::new(0.0, 1.0, 5.0), -Vector3::z());
+        assert!(teapot.normal(ray.project(teapot.intersect(ray).unwrap())).z > 0.9);
+    }
+}
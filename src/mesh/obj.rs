@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use na::{Point3, Unit, Vector3};
+
+use crate::image::Image;
+use crate::object::{Face, Material, TriangleMesh};
+use crate::types::*;
+
+// A material from an MTL library. Only the diffuse color and map take part
+// in shading for now; the rest is kept for when the renderer supports it.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub diffuse: Color,  // Kd
+    pub specular: Color, // Ks
+    pub shininess: f32,  // Ns
+    pub opacity: f32,    // d, or 1 - Tr
+
+    pub diffuse_map: Option<PathBuf>, // map_Kd
+    pub specular_map: Option<PathBuf>, // map_Ks
+    pub opacity_map: Option<PathBuf>, // map_d
+    pub bump_map: Option<PathBuf>     // map_Bump or bump
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        MtlMaterial {
+            name: String::new(),
+            diffuse: Color::gray(0.8),
+            specular: Color::black(),
+            shininess: 0.0,
+            opacity: 1.0,
+            diffuse_map: None,
+            specular_map: None,
+            opacity_map: None,
+            bump_map: None
+        }
+    }
+}
+
+// A polygon of an OBJ file. Every corner holds handles to a position,
+// and optionally to texture coordinates and a normal.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjFace {
+    pub corners: Vec<(usize, Option<usize>, Option<usize>)>,
+    pub material: Option<usize>, // Index into the materials, if one was in use.
+    pub group: usize             // Index into the groups.
+}
+
+// The contents of an OBJ file, with polygons kept as they are.
+#[allow(dead_code)]
+#[derive(Clone, Debug, Default)]
+pub struct Obj {
+    pub positions: Vec<Point3f>,
+    pub uvs: Vec<(f32, f32)>,
+    pub normals: Vec<Vector3f>,
+    pub faces: Vec<ObjFace>,
+    pub groups: Vec<String>, // Names of the groups and objects, in order of appearance.
+    pub materials: Vec<MtlMaterial>,
+
+    pub warnings: Vec<String> // Problems that didn't stop the file from loading.
+}
+
+#[allow(dead_code)]
+fn invalid(line: usize, message: impl AsRef<str>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, message.as_ref()))
+}
+
+#[allow(dead_code)]
+fn parse_floats(line: usize, args: &[&str], count: usize) -> io::Result<Vec<f32>> {
+    if args.len() < count { return Err(invalid(line, format!("expected {} numbers", count))); }
+    args.iter().take(count)
+        .map(|arg| arg.parse().map_err(|_| invalid(line, format!("invalid number '{}'", arg))))
+        .collect()
+}
+
+// Resolves a 1-based (or negative, counting back from the end) index into a list of the given length.
+#[allow(dead_code)]
+fn parse_index(line: usize, arg: &str, len: usize) -> io::Result<usize> {
+    let index: isize = arg.parse().map_err(|_| invalid(line, format!("invalid index '{}'", arg)))?;
+    let resolved = if index < 0 { len as isize + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= len as isize {
+        return Err(invalid(line, format!("index {} out of range", index)));
+    }
+    Ok(resolved as usize)
+}
+
+// Texture maps can have options before the file name, which comes last.
+#[allow(dead_code)]
+fn parse_map(line: usize, args: &[&str], dir: &Path) -> io::Result<PathBuf> {
+    args.last().map(|name| dir.join(name)).ok_or_else(|| invalid(line, "missing map file name"))
+}
+
+// Parses the contents of an MTL file. Map paths are taken relative to the given directory.
+#[allow(dead_code)]
+pub fn parse_mtl(source: &str, dir: &Path) -> io::Result<Vec<MtlMaterial>> {
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+
+    for (number, text) in source.lines().enumerate() {
+        let number = number + 1;
+        let mut tokens = text.split('#').next().unwrap_or("").split_whitespace();
+        let keyword = match tokens.next() { Some(keyword) => keyword, None => continue };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            materials.push(MtlMaterial { name: args.join(" "), ..MtlMaterial::default() });
+            continue;
+        }
+        let material = materials.last_mut().ok_or_else(|| invalid(number, "material property before newmtl"))?;
+
+        match keyword {
+            "Kd" | "Ks" => {
+                // A single value stands for a gray.
+                let color = if args.len() >= 3 {
+                    let rgb = parse_floats(number, &args, 3)?;
+                    Color::new(rgb[0], rgb[1], rgb[2])
+                } else { Color::gray(parse_floats(number, &args, 1)?[0]) };
+                if keyword == "Kd" { material.diffuse = color; } else { material.specular = color; }
+            },
+            "Ns" => material.shininess = parse_floats(number, &args, 1)?[0],
+            "d" => material.opacity = parse_floats(number, &args, 1)?[0],
+            "Tr" => material.opacity = 1.0 - parse_floats(number, &args, 1)?[0],
+            "map_Kd" => material.diffuse_map = Some(parse_map(number, &args, dir)?),
+            "map_Ks" => material.specular_map = Some(parse_map(number, &args, dir)?),
+            "map_d" => material.opacity_map = Some(parse_map(number, &args, dir)?),
+            "map_Bump" | "map_bump" | "bump" => material.bump_map = Some(parse_map(number, &args, dir)?),
+            _ => () // Other properties don't affect rendering.
+        }
+    }
+
+    Ok(materials)
+}
+
+// Reads an MTL file.
+#[allow(dead_code)]
+pub fn read_mtl(filename: impl AsRef<Path>) -> io::Result<Vec<MtlMaterial>> {
+    let filename = filename.as_ref();
+    parse_mtl(&fs::read_to_string(filename)?, filename.parent().unwrap_or_else(|| Path::new("")))
+}
+
+#[allow(dead_code)]
+impl Obj {
+    // Reads an OBJ file, along with the MTL libraries it references.
+    pub fn read(filename: impl AsRef<Path>) -> io::Result<Self> {
+        let filename = filename.as_ref();
+        Obj::parse(&fs::read_to_string(filename)?, filename.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    // Parses the contents of an OBJ file. MTL libraries are looked up relative to the given directory.
+    pub fn parse(source: &str, dir: &Path) -> io::Result<Self> {
+        let mut obj = Obj::default();
+        let mut material = None;
+        let mut group = None;
+
+        for (number, text) in source.lines().enumerate() {
+            let number = number + 1;
+            let mut tokens = text.split('#').next().unwrap_or("").split_whitespace();
+            let keyword = match tokens.next() { Some(keyword) => keyword, None => continue };
+            let args: Vec<&str> = tokens.collect();
+
+            match keyword {
+                "v" => {
+                    let v = parse_floats(number, &args, 3)?;
+                    obj.positions.push(Point3::new(v[0], v[1], v[2]));
+                },
+                "vt" => {
+                    let v = parse_floats(number, &args, 1)?;
+                    obj.uvs.push((v[0], if args.len() > 1 { parse_floats(number, &args, 2)?[1] } else { 0.0 }));
+                },
+                "vn" => {
+                    let v = parse_floats(number, &args, 3)?;
+                    obj.normals.push(Vector3::new(v[0], v[1], v[2]));
+                },
+                "f" => {
+                    if args.len() < 3 { return Err(invalid(number, "face with fewer than 3 corners")); }
+
+                    let corners = args.iter().map(|arg| {
+                        let mut parts = arg.split('/');
+                        let position = parse_index(number, parts.next().unwrap_or(""), obj.positions.len())?;
+                        let uv = match parts.next() {
+                            Some(part) if !part.is_empty() => Some(parse_index(number, part, obj.uvs.len())?),
+                            _ => None
+                        };
+                        let normal = match parts.next() {
+                            Some(part) if !part.is_empty() => Some(parse_index(number, part, obj.normals.len())?),
+                            _ => None
+                        };
+                        Ok((position, uv, normal))
+                    }).collect::<io::Result<_>>()?;
+
+                    let group = *group.get_or_insert_with(|| {
+                        obj.groups.push("default".to_string());
+                        obj.groups.len() - 1
+                    });
+                    obj.faces.push(ObjFace { corners, material, group });
+                },
+                "g" | "o" => {
+                    let name = if args.is_empty() { "default".to_string() } else { args.join(" ") };
+                    group = Some(match obj.groups.iter().position(|other| *other == name) {
+                        Some(index) => index,
+                        None => { obj.groups.push(name); obj.groups.len() - 1 }
+                    });
+                },
+                "usemtl" => {
+                    let name = args.join(" ");
+                    material = obj.materials.iter().position(|m| m.name == name);
+                    if material.is_none() {
+                        obj.warnings.push(format!("line {}: unknown material '{}'", number, name));
+                    }
+                },
+                "mtllib" => {
+                    for name in args {
+                        match read_mtl(dir.join(name)) {
+                            Ok(materials) => obj.materials.extend(materials),
+                            Err(error) => obj.warnings.push(format!("line {}: could not read '{}': {}", number, name, error))
+                        }
+                    }
+                },
+                _ => () // Smoothing groups, curves and the like are ignored.
+            }
+        }
+
+        Ok(obj)
+    }
+
+    // Takes the geometry of a mesh, for writing it out. Its materials are
+    // texture maps rather than MTL materials, so they are left out.
+    pub fn from_mesh(mesh: &TriangleMesh) -> Self {
+        let faces = mesh.triangles.iter().map(|tri| {
+            let corner = |i: usize, position: usize| {
+                let pick = |(a, b, c): (usize, usize, usize)| [a, b, c][i];
+                (position, tri.uvs.map(pick), tri.normals.map(pick))
+            };
+            ObjFace { corners: vec![corner(0, tri.v1), corner(1, tri.v2), corner(2, tri.v3)], material: None, group: 0 }
+        }).collect();
+
+        Obj {
+            positions: mesh.vertices.clone(),
+            uvs: mesh.uvs.clone(),
+            normals: mesh.normals.iter().map(|normal| normal.into_inner()).collect(),
+            faces,
+            groups: vec!["default".to_string()],
+            ..Obj::default()
+        }
+    }
+
+    pub fn write(&self, filename: impl AsRef<Path>) -> io::Result<()> {
+        self.write_to(&mut BufWriter::new(File::create(filename)?))
+    }
+
+    // Writes the vertex data and faces, switching groups and materials as the faces do.
+    // Material libraries aren't written, only the names of the materials used.
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        for p in &self.positions { writeln!(out, "v {} {} {}", p.x, p.y, p.z)?; }
+        for (u, v) in &self.uvs { writeln!(out, "vt {} {}", u, v)?; }
+        for n in &self.normals { writeln!(out, "vn {} {} {}", n.x, n.y, n.z)?; }
+
+        let (mut group, mut material) = (None, None);
+        for face in &self.faces {
+            if group != Some(face.group) {
+                writeln!(out, "g {}", self.groups[face.group])?;
+                group = Some(face.group);
+            }
+            if let Some(m) = face.material.filter(|&m| material != Some(m)) {
+                writeln!(out, "usemtl {}", self.materials[m].name)?;
+                material = Some(m);
+            }
+
+            write!(out, "f")?;
+            for &(p, t, n) in &face.corners {
+                match (t, n) {
+                    (Some(t), Some(n)) => write!(out, " {}/{}/{}", p + 1, t + 1, n + 1)?,
+                    (Some(t), None) => write!(out, " {}/{}", p + 1, t + 1)?,
+                    (None, Some(n)) => write!(out, " {}//{}", p + 1, n + 1)?,
+                    (None, None) => write!(out, " {}", p + 1)?
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    // Builds a single mesh out of all faces.
+    pub fn mesh(&mut self) -> TriangleMesh {
+        let faces: Vec<usize> = (0..self.faces.len()).collect();
+        let mut warnings = Vec::new();
+        let mesh = self.build_mesh(&faces, &mut HashMap::new(), &mut warnings);
+        self.warnings.extend(warnings);
+        mesh
+    }
+
+    // Builds a mesh for every group or object, along with its name.
+    pub fn meshes(&mut self) -> Vec<(String, TriangleMesh)> {
+        let mut images = HashMap::new();
+        let mut warnings = Vec::new();
+        let meshes = (0..self.groups.len()).filter_map(|group| {
+            let faces: Vec<usize> = (0..self.faces.len()).filter(|&i| self.faces[i].group == group).collect();
+            if faces.is_empty() { return None; }
+            Some((self.groups[group].clone(), self.build_mesh(&faces, &mut images, &mut warnings)))
+        }).collect();
+        self.warnings.extend(warnings);
+        meshes
+    }
+
+    // Builds a mesh from some of the faces, taking only the vertex data and materials they use.
+    // Polygons are split into fans of triangles, which assumes they are convex.
+    fn build_mesh(&self, faces: &[usize], images: &mut HashMap<PathBuf, Option<Rc<Image>>>,
+                  warnings: &mut Vec<String>) -> TriangleMesh {
+        let mut positions = HashMap::new();
+        let mut uvs = HashMap::new();
+        let mut normals = HashMap::new();
+        let mut materials = HashMap::new();
+        let (mut mesh_positions, mut mesh_uvs, mut mesh_normals) = (Vec::new(), Vec::new(), Vec::new());
+        let mut mesh_materials = Vec::new();
+        let mut tris = Vec::new();
+
+        fn handle<T: Copy>(map: &mut HashMap<usize, usize>, list: &mut Vec<T>, index: usize, value: T) -> usize {
+            *map.entry(index).or_insert_with(|| { list.push(value); list.len() - 1 })
+        }
+
+        for &f in faces {
+            let face = &self.faces[f];
+            let material = *materials.entry(face.material).or_insert_with(|| {
+                let source = face.material.map(|m| self.materials[m].clone()).unwrap_or_default();
+                mesh_materials.push(load_material(&source, images, warnings));
+                mesh_materials.len() - 1
+            });
+
+            let corners: Vec<(usize, Option<usize>, Option<usize>)> = face.corners.iter().map(|&(p, t, n)| (
+                handle(&mut positions, &mut mesh_positions, p, self.positions[p]),
+                t.map(|t| handle(&mut uvs, &mut mesh_uvs, t, self.uvs[t])),
+                n.map(|n| handle(&mut normals, &mut mesh_normals, n, Unit::new_normalize(self.normals[n])))
+            )).collect();
+
+            let textured = corners.iter().all(|c| c.1.is_some());
+            let smooth = corners.iter().all(|c| c.2.is_some());
+            for i in 1..corners.len() - 1 {
+                let (a, b, c) = (corners[0], corners[i], corners[i + 1]);
+                tris.push(Face {
+                    vertices: (a.0, b.0, c.0),
+                    normals: if smooth { Some((a.2.unwrap(), b.2.unwrap(), c.2.unwrap())) } else { None },
+                    uvs: if textured { Some((a.1.unwrap(), b.1.unwrap(), c.1.unwrap())) } else { None },
+                    material
+                });
+            }
+        }
+
+        TriangleMesh::from_faces(mesh_positions, mesh_normals, mesh_uvs, mesh_materials, tris)
+    }
+}
+
+// Turns an MTL material into a texture map, loading its diffuse map if it has one.
+#[allow(dead_code)]
+fn load_material(source: &MtlMaterial, images: &mut HashMap<PathBuf, Option<Rc<Image>>>, warnings: &mut Vec<String>) -> Material {
+    let diffuse = source.diffuse;
+    let map = source.diffuse_map.as_ref().and_then(|path| {
+        images.entry(path.clone()).or_insert_with(|| match Image::read(path) {
+            Ok(image) => Some(Rc::new(image)),
+            Err(error) => {
+                warnings.push(format!("could not read texture '{}': {}", path.display(), error));
+                None
+            }
+        }).clone()
+    });
+
+    match map {
+        // Texture coordinates wrap around, with v pointing up the image.
+        Some(image) => Box::new(move |u, v| {
+            let x = u.rem_euclid(1.0) * image.width as f32;
+            let y = (1.0 - v.rem_euclid(1.0)) * image.height as f32;
+            Texture { color: diffuse * image.sample(x, y), albedo: 1.0 }
+        }),
+        None => Box::new(move |_, _| Texture { color: diffuse, albedo: 1.0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{Sphere, Surface};
+
+    #[test]
+    fn obj_parse() {
+        let dir = std::env::temp_dir().join(format!("obj_parse_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("scene.mtl"), "newmtl red\nKd 1 0 0\nKs 0.5 0.5 0.5\nd 0.5\n\
+                                          newmtl checker\nKd 1 1 1\nmap_Kd -s 1 1 1 checker.pgm\n").unwrap();
+        fs::write(dir.join("checker.pgm"), "P2\n2 2\n1\n1 0\n0 1\n").unwrap();
+
+        let source = "mtllib scene.mtl\n\
+                      v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                      vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                      vn 0 0 2\n\
+                      o quad\nusemtl checker\n\
+                      f 1/1/1 2/2/1 3/3/1 4/4/1 # A quad, split in two.\n\
+                      o triangle\nusemtl red\n\
+                      f -4//-1 -3//-1 -1//-1\n\
+                      usemtl missing\n";
+        let mut obj = Obj::parse(source, &dir).unwrap();
+
+        assert_eq!(obj.groups, vec!["quad".to_string(), "triangle".to_string()]);
+        assert_eq!(obj.faces[1].corners, vec![(0, None, Some(0)), (1, None, Some(0)), (3, None, Some(0))]);
+        assert_eq!(obj.materials[0].opacity, 0.5);
+        assert_eq!(obj.warnings.len(), 1);
+
+        let meshes = obj.meshes();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(obj.warnings.len(), 1);
+        let (_, quad) = &meshes[0];
+        assert_eq!(quad.triangles.len(), 2);
+        assert_eq!(quad.normal(Point3::new(0.2, 0.7, 0.0)).into_inner(), Vector3::z());
+        // The checker map has a white pixel in its top left and bottom right.
+        assert_eq!(quad.get_texture(Point3::new(0.1, 0.9, 0.0)).color, Color::white());
+        assert_eq!(quad.get_texture(Point3::new(0.9, 0.9, 0.0)).color, Color::black());
+
+        let (_, triangle) = &meshes[1];
+        assert_eq!(triangle.vertices.len(), 3);
+        assert_eq!(triangle.get_texture(Point3::new(0.2, 0.2, 0.0)).color, Color::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn obj_write() {
+        let sphere = Sphere::new_solid(0.0, 0.0, 0.0, 1.0, Texture::white());
+        let mesh = sphere.tessellate(8).unwrap();
+        let mut data = Vec::new();
+        Obj::from_mesh(&mesh).write_to(&mut data).unwrap();
+
+        let mut obj = Obj::parse(std::str::from_utf8(&data).unwrap(), Path::new("")).unwrap();
+        assert_eq!(obj.positions, mesh.vertices);
+        assert_eq!(obj.uvs, mesh.uvs);
+        assert_eq!(obj.faces[0].corners[1], (mesh.triangles[0].v2, Some(mesh.triangles[0].v2), Some(mesh.triangles[0].v2)));
+
+        let copy = obj.mesh();
+        assert_eq!(copy.triangles.len(), mesh.triangles.len());
+        let point = Point3::new(0.0, 0.0, 1.0);
+        assert!((copy.normal(point).into_inner() - mesh.normal(point).into_inner()).norm() < 1e-5);
+    }
+
+    #[test]
+    fn obj_errors() {
+        assert!(Obj::parse("v 0 0 0\nf 1 2 3\n", Path::new("")).is_err());
+        assert!(Obj::parse("v 0 0\n", Path::new("")).is_err());
+        assert!(Obj::parse("v 0 0 0\nv 0 0 0\nf 1 2\n", Path::new("")).is_err());
+    }
+}
@@ -0,0 +1,541 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use na::{Point3, Vector3};
+
+use crate::object::TriangleMesh;
+use crate::types::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+// The scalar types a property can have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scalar { I8, U8, I16, U16, I32, U32, F32, F64 }
+
+impl Scalar {
+    fn name(self) -> &'static str {
+        match self {
+            Scalar::I8 => "char",
+            Scalar::U8 => "uchar",
+            Scalar::I16 => "short",
+            Scalar::U16 => "ushort",
+            Scalar::I32 => "int",
+            Scalar::U32 => "uint",
+            Scalar::F32 => "float",
+            Scalar::F64 => "double"
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8
+        }
+    }
+
+    // The value standing for full intensity when the type is used for a color.
+    fn full(self) -> f32 {
+        match self {
+            Scalar::I8 => 127.0,
+            Scalar::U8 => 255.0,
+            Scalar::I16 => 32767.0,
+            Scalar::U16 => 65535.0,
+            Scalar::I32 => 2_147_483_647.0,
+            Scalar::U32 => 4_294_967_295.0,
+            Scalar::F32 | Scalar::F64 => 1.0
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar) // Types of the length and of the items.
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+// The contents of a PLY file. Of all elements, only vertices and faces are kept.
+#[derive(Clone, Debug)]
+pub struct Ply {
+    pub format: PlyFormat,
+    pub positions: Vec<Point3f>,
+    pub normals: Vec<Vector3f>,  // The other vertex attributes are either
+    pub colors: Vec<Color>,      // given for every vertex or not at all.
+    pub uvs: Vec<(f32, f32)>,
+    pub faces: Vec<Vec<usize>>,  // Polygons, as indices into the vertices.
+    pub comments: Vec<String>
+}
+
+fn invalid(message: impl AsRef<str>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.as_ref())
+}
+
+fn truncated(element: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, format!("body ends within element '{}'", element))
+}
+
+// The data following the header, read one value at a time.
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { data: &'a [u8], offset: usize, big_endian: bool }
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, kind: Scalar, element: &str) -> io::Result<f64> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(|| truncated(element))?;
+                token.parse().map_err(|_| invalid(format!("invalid number '{}' in element '{}'", token, element)))
+            },
+            Body::Binary { data, offset, big_endian } => {
+                let size = kind.size();
+                let bytes = data.get(*offset..*offset + size).ok_or_else(|| truncated(element))?;
+                *offset += size;
+
+                let mut b = [0; 8];
+                b[..size].copy_from_slice(bytes);
+                if *big_endian { b[..size].reverse(); }
+
+                Ok(match kind {
+                    Scalar::I8 => f64::from(b[0] as i8),
+                    Scalar::U8 => f64::from(b[0]),
+                    Scalar::I16 => f64::from(i16::from_le_bytes([b[0], b[1]])),
+                    Scalar::U16 => f64::from(u16::from_le_bytes([b[0], b[1]])),
+                    Scalar::I32 => f64::from(i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    Scalar::U32 => f64::from(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    Scalar::F32 => f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    Scalar::F64 => f64::from_le_bytes(b)
+                })
+            }
+        }
+    }
+}
+
+// Splits the header off the data, returning its lines and the rest.
+fn split_header(data: &[u8]) -> io::Result<(Vec<&str>, &[u8])> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while let Some(length) = data[start..].iter().position(|&b| b == b'\n') {
+        let line = std::str::from_utf8(&data[start..start + length])
+            .map_err(|_| invalid(format!("line {}: header is not text", lines.len() + 1)))?
+            .trim_end_matches('\r');
+        start += length + 1;
+        if line.trim() == "end_header" { return Ok((lines, &data[start..])); }
+        lines.push(line);
+    }
+    Err(invalid("header has no end_header line"))
+}
+
+// Parses the header lines into the format, comments and elements.
+fn parse_header(lines: &[&str]) -> io::Result<(PlyFormat, Vec<String>, Vec<Element>)> {
+    if lines.first().map(|line| line.trim()) != Some("ply") { return Err(invalid("not a PLY file")); }
+
+    let mut format = None;
+    let mut comments = Vec::new();
+    let mut elements: Vec<Element> = Vec::new();
+
+    for (number, line) in lines.iter().enumerate().skip(1) {
+        let error = |message: &str| invalid(format!("line {}: {}", number + 1, message));
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.first() {
+            Some(&"format") => {
+                if tokens.get(2) != Some(&"1.0") { return Err(error("unsupported format version")); }
+                format = Some(match tokens[1] {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    other => return Err(error(&format!("unknown format '{}'", other)))
+                });
+            },
+            Some(&"comment") | Some(&"obj_info") =>
+                comments.push(line.trim_start()[tokens[0].len()..].trim().to_string()),
+            Some(&"element") => {
+                if tokens.len() != 3 { return Err(error("expected an element name and count")); }
+                let count = tokens[2].parse().map_err(|_| error("invalid element count"))?;
+                elements.push(Element { name: tokens[1].to_string(), count, properties: Vec::new() });
+            },
+            Some(&"property") => {
+                let element = elements.last_mut().ok_or_else(|| error("property outside of an element"))?;
+                let kind = |name: &str| Scalar::parse(name).ok_or_else(|| error(&format!("unknown type '{}'", name)));
+                let property = match tokens.len() {
+                    3 => Property::Scalar(tokens[2].to_string(), kind(tokens[1])?),
+                    5 if tokens[1] == "list" => {
+                        let length = kind(tokens[2])?;
+                        if let Scalar::F32 | Scalar::F64 = length { return Err(error("list length must be an integer")); }
+                        Property::List(tokens[4].to_string(), length, kind(tokens[3])?)
+                    },
+                    _ => return Err(error("malformed property"))
+                };
+                element.properties.push(property);
+            },
+            None => (),
+            Some(other) => return Err(error(&format!("unknown keyword '{}'", other)))
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid("header has no format line"))?;
+    Ok((format, comments, elements))
+}
+
+#[allow(dead_code)]
+impl Ply {
+    pub fn read(filename: impl AsRef<Path>) -> io::Result<Self> {
+        Ply::parse(&fs::read(filename)?)
+    }
+
+    // Parses the contents of a PLY file, either ASCII or binary.
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        let (lines, body) = split_header(data)?;
+        let (format, comments, elements) = parse_header(&lines)?;
+
+        let mut body = match format {
+            PlyFormat::Ascii => Body::Ascii(std::str::from_utf8(body)
+                .map_err(|_| invalid("ASCII body is not text"))?.split_ascii_whitespace()),
+            _ => Body::Binary { data: body, offset: 0, big_endian: format == PlyFormat::BinaryBigEndian }
+        };
+
+        let mut ply = Ply {
+            format,
+            positions: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            faces: Vec::new(),
+            comments
+        };
+
+        for element in &elements {
+            let name = element.name.as_str();
+            // Where each scalar property goes: its slot among the vertex attributes below.
+            let slots: Vec<Option<usize>> = element.properties.iter().map(|property| match property {
+                Property::Scalar(property, _) if name == "vertex" => match property.as_str() {
+                    "x" => Some(0), "y" => Some(1), "z" => Some(2),
+                    "nx" => Some(3), "ny" => Some(4), "nz" => Some(5),
+                    "red" | "r" | "diffuse_red" => Some(6),
+                    "green" | "g" | "diffuse_green" => Some(7),
+                    "blue" | "b" | "diffuse_blue" => Some(8),
+                    "u" | "s" | "texture_u" | "texture_s" => Some(9),
+                    "v" | "t" | "texture_v" | "texture_t" => Some(10),
+                    _ => None
+                },
+                _ => None
+            }).collect();
+            let has = |range: std::ops::Range<usize>| range.clone().all(|slot| slots.contains(&Some(slot)));
+
+            if name == "vertex" && !has(0..3) { return Err(invalid("vertices have no x, y and z properties")); }
+            let (normals, colors, uvs) = (name == "vertex" && has(3..6), name == "vertex" && has(6..9), name == "vertex" && has(9..11));
+
+            for _ in 0..element.count {
+                let mut values = [0.0f32; 11];
+                for (property, slot) in element.properties.iter().zip(&slots) {
+                    match property {
+                        Property::Scalar(_, kind) => {
+                            let value = body.read(*kind, name)?;
+                            if let Some(slot) = *slot {
+                                // Colors are scaled so that full intensity is 1.
+                                values[slot] = if (6..9).contains(&slot) { value as f32 / kind.full() } else { value as f32 };
+                            }
+                        },
+                        Property::List(property, length, kind) => {
+                            let length = body.read(*length, name)?;
+                            if length < 0.0 || length.fract() != 0.0 {
+                                return Err(invalid(format!("invalid list length {} in element '{}'", length, name)));
+                            }
+                            let items = (0..length as usize).map(|_| body.read(*kind, name)).collect::<io::Result<Vec<f64>>>()?;
+
+                            if name == "face" && (property == "vertex_indices" || property == "vertex_index") {
+                                if items.len() < 3 { return Err(invalid("face with fewer than 3 vertices")); }
+                                if let Some(index) = items.iter().find(|&&index| index < 0.0 || index.fract() != 0.0) {
+                                    return Err(invalid(format!("invalid vertex index {}", index)));
+                                }
+                                ply.faces.push(items.into_iter().map(|index| index as usize).collect());
+                            }
+                        }
+                    }
+                }
+
+                if name == "vertex" {
+                    ply.positions.push(Point3::new(values[0], values[1], values[2]));
+                    if normals { ply.normals.push(Vector3::new(values[3], values[4], values[5])); }
+                    if colors { ply.colors.push(Color::new(values[6], values[7], values[8])); }
+                    if uvs { ply.uvs.push((values[9], values[10])); }
+                }
+            }
+        }
+
+        if let Some(index) = ply.faces.iter().flatten().find(|&&index| index >= ply.positions.len()) {
+            return Err(invalid(format!("vertex index {} out of range", index)));
+        }
+
+        Ok(ply)
+    }
+
+    // Takes the geometry, normals, texture coordinates and vertex colors of a mesh,
+    // for writing it out as little-endian binary. Since PLY only has per-vertex
+    // attributes, vertices are split wherever their normals or texture coordinates differ.
+    pub fn from_mesh(mesh: &TriangleMesh) -> Self {
+        let smooth = mesh.triangles.iter().any(|tri| tri.normals.is_some());
+        let textured = mesh.triangles.iter().any(|tri| tri.uvs.is_some());
+
+        let mut ply = Ply {
+            format: PlyFormat::BinaryLittleEndian,
+            positions: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            faces: Vec::new(),
+            comments: Vec::new()
+        };
+        let mut corners = HashMap::new();
+
+        for (i, tri) in mesh.triangles.iter().enumerate() {
+            let (a, b, c) = (mesh.vertices[tri.v1], mesh.vertices[tri.v2], mesh.vertices[tri.v3]);
+            let face_normal = (b - a).cross(&(c - a)).try_normalize(0.0).unwrap_or_else(Vector3::zeros);
+
+            let face = [tri.v1, tri.v2, tri.v3].iter().enumerate().map(|(corner, &v)| {
+                let pick = |(a, b, c): (usize, usize, usize)| [a, b, c][corner];
+                let (normal, uv) = (tri.normals.map(pick), tri.uvs.map(pick));
+                // Flat shaded corners of a smooth mesh take the normal of their own face.
+                let flat = if smooth && normal.is_none() { Some(i) } else { None };
+
+                *corners.entry((v, normal, uv, flat)).or_insert_with(|| {
+                    ply.positions.push(mesh.vertices[v]);
+                    if smooth { ply.normals.push(normal.map_or(face_normal, |n| mesh.normals[n].into_inner())); }
+                    if textured { ply.uvs.push(uv.map_or((0.0, 0.0), |t| mesh.uvs[t])); }
+                    if !mesh.colors.is_empty() { ply.colors.push(mesh.colors[v]); }
+                    ply.positions.len() - 1
+                })
+            }).collect();
+            ply.faces.push(face);
+        }
+
+        ply
+    }
+
+    pub fn write(&self, filename: impl AsRef<Path>) -> io::Result<()> {
+        self.write_to(&mut BufWriter::new(File::create(filename)?))
+    }
+
+    // Writes the vertices and faces in the format of the file. Colors are written as bytes.
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let format = match self.format {
+            PlyFormat::Ascii => "ascii",
+            PlyFormat::BinaryLittleEndian => "binary_little_endian",
+            PlyFormat::BinaryBigEndian => "binary_big_endian"
+        };
+        writeln!(out, "ply\nformat {} 1.0", format)?;
+        for comment in &self.comments { writeln!(out, "comment {}", comment)?; }
+
+        let mut properties = vec!["x", "y", "z"];
+        if !self.normals.is_empty() { properties.extend(&["nx", "ny", "nz"]); }
+        let colors = properties.len();
+        if !self.colors.is_empty() { properties.extend(&["red", "green", "blue"]); }
+        let uvs = properties.len();
+        if !self.uvs.is_empty() { properties.extend(&["s", "t"]); }
+        let kind = |i: usize| if (colors..uvs).contains(&i) { Scalar::U8 } else { Scalar::F32 };
+
+        writeln!(out, "element vertex {}", self.positions.len())?;
+        for (i, property) in properties.iter().enumerate() { writeln!(out, "property {} {}", kind(i).name(), property)?; }
+        writeln!(out, "element face {}", self.faces.len())?;
+        writeln!(out, "property list uchar int vertex_indices\nend_header")?;
+
+        for (i, p) in self.positions.iter().enumerate() {
+            let mut values: Vec<f64> = p.iter().map(|&x| f64::from(x)).collect();
+            if let Some(n) = self.normals.get(i) { values.extend(n.iter().map(|&x| f64::from(x))); }
+            if let Some(c) = self.colors.get(i) {
+                values.extend([c.red, c.green, c.blue].iter().map(|&x| (255.0 * f64::from(x)).round().min(255.0)));
+            }
+            if let Some(&(s, t)) = self.uvs.get(i) { values.extend(&[f64::from(s), f64::from(t)]); }
+            self.write_record(out, &values.into_iter().enumerate().map(|(i, x)| (x, kind(i))).collect::<Vec<_>>())?;
+        }
+
+        for face in &self.faces {
+            let values: Vec<(f64, Scalar)> = std::iter::once((face.len() as f64, Scalar::U8))
+                .chain(face.iter().map(|&index| (index as f64, Scalar::I32)))
+                .collect();
+            self.write_record(out, &values)?;
+        }
+        Ok(())
+    }
+
+    // Writes the values of a vertex or face, as a line of text or as bytes.
+    fn write_record(&self, out: &mut impl Write, values: &[(f64, Scalar)]) -> io::Result<()> {
+        if self.format == PlyFormat::Ascii {
+            let text: Vec<String> = values.iter()
+                .map(|&(x, kind)| if kind == Scalar::F32 { (x as f32).to_string() } else { x.to_string() })
+                .collect();
+            return writeln!(out, "{}", text.join(" "));
+        }
+
+        for &(x, kind) in values {
+            let mut bytes = match kind {
+                Scalar::U8 => vec![x as u8],
+                Scalar::I32 => (x as i32).to_le_bytes().to_vec(),
+                _ => (x as f32).to_le_bytes().to_vec()
+            };
+            if self.format == PlyFormat::BinaryBigEndian { bytes.reverse(); }
+            out.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+
+    // Builds a mesh out of the faces, splitting polygons into fans of triangles.
+    // The texture is tinted by the vertex colors, if there are any.
+    pub fn mesh(&self, texture: Texture) -> TriangleMesh {
+        let tris = self.faces.iter()
+            .flat_map(|face| (1..face.len() - 1).map(move |i| (face[0], face[i], face[i + 1])))
+            .collect();
+
+        let mut mesh = TriangleMesh::new_solid(self.positions.clone(), tris, texture);
+        if !self.normals.is_empty() { mesh = mesh.with_vertex_normals(self.normals.clone()); }
+        if !self.uvs.is_empty() { mesh = mesh.with_uvs(self.uvs.clone()); }
+        if !self.colors.is_empty() { mesh = mesh.with_vertex_colors(self.colors.clone()); }
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{Cylinder, Surface};
+
+    const HEADER: &str = "element vertex 4\n\
+                          property float x\nproperty float y\nproperty float z\n\
+                          property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                          element face 1\nproperty list uchar int vertex_indices\n\
+                          end_header\n";
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut data = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+
+        for &(x, y) in &[(0.0f32, 0.0f32), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            for &c in &[x, y, 0.0] {
+                data.extend_from_slice(&if big_endian { c.to_be_bytes() } else { c.to_le_bytes() });
+            }
+            data.extend_from_slice(&[255, 0, 0]);
+        }
+        data.push(4);
+        for i in 0..4i32 { data.extend_from_slice(&if big_endian { i.to_be_bytes() } else { i.to_le_bytes() }); }
+        data
+    }
+
+    #[test]
+    fn ply_ascii() {
+        let source = "ply\nformat ascii 1.0\ncomment scanned\n\
+                      element vertex 3\n\
+                      property float x\nproperty float y\nproperty float z\n\
+                      property float nx\nproperty float ny\nproperty float nz\n\
+                      property float s\nproperty float t\n\
+                      element face 1\nproperty list uchar uint vertex_index\nproperty uchar flags\n\
+                      end_header\n\
+                      0 0 0 0 0 1 0 0\n1 0 0 0 0 1 1 0\n0 1 0 0 0 1 0 1\n\
+                      3 0 1 2 7\n";
+        let ply = Ply::parse(source.as_bytes()).unwrap();
+
+        assert_eq!(ply.comments, vec!["scanned".to_string()]);
+        assert_eq!(ply.faces, vec![vec![0, 1, 2]]);
+        assert_eq!(ply.uvs[1], (1.0, 0.0));
+        assert!(ply.colors.is_empty());
+
+        let mesh = ply.mesh(Texture::white());
+        assert_eq!(mesh.normal(Point3::new(0.2, 0.2, 0.0)).into_inner(), Vector3::z());
+        let (u, v) = mesh.uv(Point3::new(0.2, 0.3, 0.0));
+        assert!((u - 0.2).abs() < 1e-5 && (v - 0.3).abs() < 1e-5);
+    }
+
+    #[test]
+    fn ply_binary() {
+        let little = Ply::parse(&binary(false)).unwrap();
+        let big = Ply::parse(&binary(true)).unwrap();
+
+        assert_eq!(little.format, PlyFormat::BinaryLittleEndian);
+        assert_eq!(little.positions, big.positions);
+        assert_eq!(little.positions[2], Point3::new(1.0, 1.0, 0.0));
+        assert_eq!(big.faces, vec![vec![0, 1, 2, 3]]);
+
+        // The quad is split in two, and the vertex colors tint the texture.
+        let mesh = big.mesh(Texture::new(0.5, 1.0, 1.0, 1.0));
+        assert_eq!(mesh.triangles.len(), 2);
+        assert_eq!(mesh.get_texture(Point3::new(0.3, 0.6, 0.0)).color, Color::new(0.5, 0.0, 0.0));
+    }
+
+    #[test]
+    fn ply_write() {
+        let cylinder = Cylinder::new(Point3::origin(), Point3::new(0.0, 2.0, 0.0), 1.0, |u, _| Texture::new(u, 0.0, 0.0, 1.0));
+        let mesh = cylinder.tessellate(8).unwrap();
+        let ply = Ply::from_mesh(&mesh);
+        // Every vertex of the tessellation has its own normal and texture coordinates
+        // already, so none are split. Those only used by collapsed triangles are dropped.
+        let mut used: Vec<usize> = mesh.faces().iter().flat_map(|face| vec![face.vertices.0, face.vertices.1, face.vertices.2]).collect();
+        used.sort();
+        used.dedup();
+        assert_eq!(ply.positions.len(), used.len());
+
+        for &format in &[PlyFormat::Ascii, PlyFormat::BinaryLittleEndian, PlyFormat::BinaryBigEndian] {
+            let mut data = Vec::new();
+            Ply { format, ..ply.clone() }.write_to(&mut data).unwrap();
+            let copy = Ply::parse(&data).unwrap();
+
+            assert_eq!(copy.format, format);
+            assert_eq!(copy.positions, ply.positions);
+            assert_eq!(copy.normals, ply.normals);
+            assert_eq!(copy.uvs, ply.uvs);
+            assert_eq!(copy.faces, ply.faces);
+            // Colors are stored as bytes.
+            assert!(copy.colors.iter().zip(&ply.colors).all(|(a, b)| (a.red - b.red).abs() < 0.51 / 255.0));
+        }
+
+        // A flat mesh gets no normals, and vertices stay shared.
+        let flat = Ply::from_mesh(&TriangleMesh::new_solid(ply.positions[..4].to_vec(), vec![(0, 1, 2), (0, 2, 3)], Texture::white()));
+        assert_eq!(flat.positions.len(), 4);
+        assert!(flat.normals.is_empty() && flat.uvs.is_empty());
+    }
+
+    #[test]
+    fn ply_errors() {
+        assert!(Ply::parse(b"obj\nformat ascii 1.0\nend_header\n").is_err());
+        assert!(Ply::parse(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n").is_err());
+        assert!(Ply::parse(b"ply\nformat binary 1.0\nend_header\n").is_err());
+        assert!(Ply::parse(b"ply\nformat ascii 1.0\nproperty float x\nend_header\n").is_err());
+        assert!(Ply::parse(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty half x\nend_header\n").is_err());
+
+        let mut data = binary(false);
+        data.pop();
+        assert_eq!(Ply::parse(&data).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        // Out of range, negative and fractional vertex indices.
+        for face in &["3 0 1 4", "3 0 1 -1", "3 0 1 1.5"] {
+            let source = format!("ply\nformat ascii 1.0\n{}0 0 0 0 0 0\n1 0 0 0 0 0\n0 1 0 0 0 0\n0 0 1 0 0 0\n{}\n", HEADER, face);
+            assert_eq!(Ply::parse(source.as_bytes()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use na::{Point3, Vector3};
+
+use crate::object::{Surface, TriangleMesh};
+use crate::types::*;
+use super::clean::weld;
+
+// The contents of an STL file: a soup of triangles, each with its own copy of its vertices.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stl {
+    pub name: String, // The solid's name, or the header of a binary file.
+    pub triangles: Vec<[Point3f; 3]>,
+    pub normals: Vec<Vector3f> // Facet normals as stored, which may be zero.
+}
+
+fn invalid(message: impl AsRef<str>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.as_ref())
+}
+
+#[allow(dead_code)]
+impl Stl {
+    pub fn read(filename: impl AsRef<Path>) -> io::Result<Self> {
+        Stl::parse(&fs::read(filename)?)
+    }
+
+    // Parses the contents of an STL file. Binary files are told apart by
+    // their size, since their headers may start with "solid" as well.
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() >= 84 {
+            let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+            if data.len() == 84 + 50 * count { return Ok(Stl::parse_binary(data, count)); }
+        }
+
+        if data.starts_with(b"solid") {
+            Stl::parse_ascii(std::str::from_utf8(data).map_err(|_| invalid("ASCII STL is not text"))?)
+        } else if data.len() >= 84 {
+            Err(io::Error::new(io::ErrorKind::UnexpectedEof, "binary STL is shorter or longer than its triangle count"))
+        } else {
+            Err(invalid("not an STL file"))
+        }
+    }
+
+    fn parse_binary(data: &[u8], count: usize) -> Self {
+        let name = String::from_utf8_lossy(&data[..80]).trim_end_matches(|c: char| c == '\0' || c.is_whitespace()).to_string();
+        let float = |offset: usize| f32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+        let vector = |offset: usize| Vector3::new(float(offset), float(offset + 4), float(offset + 8));
+
+        let mut stl = Stl { name, triangles: Vec::with_capacity(count), normals: Vec::with_capacity(count) };
+        for i in 0..count {
+            let offset = 84 + 50 * i;
+            stl.normals.push(vector(offset));
+            stl.triangles.push([Point3::from(vector(offset + 12)), Point3::from(vector(offset + 24)), Point3::from(vector(offset + 36))]);
+        }
+        stl
+    }
+
+    fn parse_ascii(source: &str) -> io::Result<Self> {
+        let mut stl = Stl::default();
+        let mut vertices = Vec::new();
+        let mut normal = Vector3::zeros();
+
+        for (number, line) in source.lines().enumerate() {
+            let error = |message: &str| invalid(format!("line {}: {}", number + 1, message));
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let floats = |start: usize| -> io::Result<Vector3f> {
+                if tokens.len() != start + 3 { return Err(error("expected 3 numbers")); }
+                let mut v = Vector3::zeros();
+                for i in 0..3 { v[i] = tokens[start + i].parse().map_err(|_| error(&format!("invalid number '{}'", tokens[start + i])))?; }
+                Ok(v)
+            };
+
+            match tokens.first() {
+                Some(&"solid") if number == 0 => stl.name = tokens[1..].join(" "),
+                Some(&"facet") => {
+                    if tokens.get(1) != Some(&"normal") { return Err(error("expected a facet normal")); }
+                    normal = floats(2)?;
+                    vertices.clear();
+                },
+                Some(&"vertex") => vertices.push(Point3::from(floats(1)?)),
+                Some(&"endfacet") => {
+                    if vertices.len() != 3 { return Err(error("facet without exactly 3 vertices")); }
+                    stl.triangles.push([vertices[0], vertices[1], vertices[2]]);
+                    stl.normals.push(normal);
+                },
+                Some(&"endsolid") => return Ok(stl),
+                Some(&"outer") | Some(&"endloop") | None => (),
+                Some(other) => return Err(error(&format!("unknown keyword '{}'", other)))
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::UnexpectedEof, "ASCII STL has no endsolid line"))
+    }
+
+    // Takes the triangles of a mesh, for writing it out.
+    pub fn from_mesh(mesh: &TriangleMesh) -> Self {
+        let triangles: Vec<[Point3f; 3]> = mesh.triangles.iter()
+            .map(|tri| [mesh.vertices[tri.v1], mesh.vertices[tri.v2], mesh.vertices[tri.v3]])
+            .collect();
+        let normals = triangles.iter().map(|[a, b, c]| (b - a).cross(&(c - a)).try_normalize(0.0).unwrap_or_else(Vector3::zeros)).collect();
+        Stl { name: String::new(), triangles, normals }
+    }
+
+    // Tessellates a surface with the given resolution (see Surface::tessellate) for writing it
+    // out. Surfaces that can't be tessellated, such as unbounded ones, give nothing.
+    pub fn from_surface(surface: &dyn Surface, resolution: usize) -> Option<Self> {
+        surface.tessellate(resolution).map(|mesh| Stl::from_mesh(&mesh))
+    }
+
+    // Builds a mesh out of the triangles, welding vertices closer together than the tolerance.
+    // Triangles that collapse in the process are dropped.
+    pub fn mesh(&self, tolerance: f32, texture: Texture) -> TriangleMesh {
+        let points: Vec<Point3f> = self.triangles.iter().flat_map(|tri| tri.iter().cloned()).collect();
+        let (vertices, indices) = weld(&points, tolerance);
+
+        let tris = indices.chunks_exact(3)
+            .map(|tri| (tri[0], tri[1], tri[2]))
+            .filter(|&(a, b, c)| a != b && b != c && c != a)
+            .collect();
+        TriangleMesh::new_solid(vertices, tris, texture)
+    }
+
+    pub fn write(&self, filename: impl AsRef<Path>, binary: bool) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(filename)?);
+        if binary { self.write_binary(&mut file) } else { self.write_ascii(&mut file) }
+    }
+
+    pub fn write_binary(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut header = [0; 80];
+        let name = self.name.as_bytes();
+        header[..name.len().min(80)].copy_from_slice(&name[..name.len().min(80)]);
+        out.write_all(&header)?;
+        out.write_all(&(self.triangles.len() as u32).to_le_bytes())?;
+
+        for (tri, normal) in self.triangles.iter().zip(&self.normals) {
+            for v in std::iter::once(normal).chain(tri.iter().map(|p| &p.coords)) {
+                for x in v.iter() { out.write_all(&x.to_le_bytes())?; }
+            }
+            out.write_all(&[0, 0])?; // No attributes.
+        }
+        Ok(())
+    }
+
+    pub fn write_ascii(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "solid {}", self.name)?;
+        for (tri, n) in self.triangles.iter().zip(&self.normals) {
+            writeln!(out, "  facet normal {:e} {:e} {:e}", n.x, n.y, n.z)?;
+            writeln!(out, "    outer loop")?;
+            for p in tri { writeln!(out, "      vertex {:e} {:e} {:e}", p.x, p.y, p.z)?; }
+            writeln!(out, "    endloop")?;
+            writeln!(out, "  endfacet")?;
+        }
+        writeln!(out, "endsolid {}", self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{Plane, Sphere};
+
+    // A tetrahedron, as separate triangles with slightly jittered copies of the vertices.
+    fn tetrahedron() -> Stl {
+        let p = [Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0), Point3::new(0.0, 0.0, 1.0)];
+        let jitter = Vector3::new(1e-5, -1e-5, 0.0);
+        let tris = [(0, 2, 1), (0, 1, 3), (0, 3, 2), (1, 2, 3)];
+        let triangles = tris.iter().enumerate()
+            .map(|(i, &(a, b, c))| [p[a] + jitter * i as f32, p[b], p[c] - jitter * i as f32])
+            .collect();
+        Stl { name: "tetrahedron".to_string(), triangles, normals: vec![Vector3::zeros(); 4] }
+    }
+
+    #[test]
+    fn stl_weld() {
+        let (points, indices) = weld(&[Point3::origin(), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 0.0, 0.009)], 0.01);
+        assert_eq!(points.len(), 2);
+        assert_eq!(indices, vec![0, 1, 0]);
+
+        let mesh = tetrahedron().mesh(1e-3, Texture::white());
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.triangles.len(), 4);
+        // Welded into a closed solid, the mesh has an inside.
+        assert_eq!(mesh.intervals(Ray::new(Point3::new(0.1, 0.1, -1.0), Vector3::z())).len(), 1);
+
+        let mesh = tetrahedron().mesh(2.0, Texture::white());
+        assert!(mesh.triangles.is_empty());
+    }
+
+    #[test]
+    fn stl_round_trip() {
+        let stl = Stl::from_mesh(&tetrahedron().mesh(1e-3, Texture::white()));
+        assert!((stl.normals[1] + Vector3::y()).norm() < 1e-4);
+
+        for &binary in &[false, true] {
+            let mut data = Vec::new();
+            if binary { stl.write_binary(&mut data).unwrap(); } else { stl.write_ascii(&mut data).unwrap(); }
+            assert_eq!(Stl::parse(&data).unwrap(), stl);
+        }
+
+        let named = Stl { name: "solid part".to_string(), ..stl };
+        let mut data = Vec::new();
+        named.write_binary(&mut data).unwrap();
+        assert_eq!(Stl::parse(&data).unwrap().name, "solid part");
+    }
+
+    #[test]
+    fn stl_from_surface() {
+        let sphere = Sphere::new_solid(0.0, 0.0, 0.0, 1.0, Texture::white());
+        let stl = Stl::from_surface(&sphere, 12).unwrap();
+        assert_eq!(stl.triangles.len(), sphere.tessellate(12).unwrap().triangles.len());
+        // Welded back together, the tessellation is a closed solid.
+        let mesh = stl.mesh(1e-4, Texture::white());
+        assert_eq!(mesh.intervals(Ray::new(Point3::new(0.1, 0.2, -5.0), Vector3::z())).len(), 1);
+
+        assert_eq!(Stl::from_surface(&Plane::xz(|_, _| Texture::white()), 12), None);
+    }
+
+    #[test]
+    fn stl_errors() {
+        assert!(Stl::parse(b"solid x\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nendloop\nendfacet\nendsolid\n").is_err());
+        assert!(Stl::parse(b"solid x\nfacet normal 0 0 1\n").is_err());
+        assert!(Stl::parse(b"solid x\nfacet normal 0 0 z\n").is_err());
+
+        let mut data = Vec::new();
+        tetrahedron().write_binary(&mut data).unwrap();
+        data.truncate(data.len() - 10);
+        assert_eq!(Stl::parse(&data).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
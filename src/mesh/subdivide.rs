@@ -0,0 +1,345 @@
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::PI;
+
+use na::{Point3, Unit};
+
+use crate::object::{Face, TriangleMesh};
+use crate::types::*;
+use super::{Obj, ObjFace};
+
+// Per-corner attributes (texture coordinates or normals), interpolated linearly onto a refined mesh.
+// Values made at the middle of an edge between two handles are shared by the faces on both sides,
+// so seams stay where they were.
+#[allow(dead_code)]
+pub(super) struct Corners<T> {
+    pub(super) values: Vec<T>,
+    edges: HashMap<(usize, usize), usize>,
+    average: fn(&[T]) -> T
+}
+
+#[allow(dead_code)]
+impl<T: Copy> Corners<T> {
+    pub(super) fn new(values: Vec<T>, average: fn(&[T]) -> T) -> Self { Corners { values, edges: HashMap::new(), average } }
+
+    pub(super) fn edge(&mut self, a: usize, b: usize) -> usize {
+        let (values, average) = (&mut self.values, self.average);
+        *self.edges.entry((a.min(b), a.max(b))).or_insert_with(|| {
+            values.push(average(&[values[a], values[b]]));
+            values.len() - 1
+        })
+    }
+
+    fn split(&mut self, (a, b, c): (usize, usize, usize)) -> [(usize, usize, usize); 4] {
+        let middle = (self.edge(a, b), self.edge(b, c), self.edge(c, a));
+        split((a, b, c), middle)
+    }
+
+    fn face(&mut self, handles: &[usize]) -> usize {
+        let corners: Vec<T> = handles.iter().map(|&h| self.values[h]).collect();
+        self.values.push((self.average)(&corners));
+        self.values.len() - 1
+    }
+}
+
+#[allow(dead_code)]
+pub(super) fn average_uv(uvs: &[(f32, f32)]) -> (f32, f32) {
+    let n = uvs.len() as f32;
+    (uvs.iter().map(|uv| uv.0).sum::<f32>() / n, uvs.iter().map(|uv| uv.1).sum::<f32>() / n)
+}
+
+#[allow(dead_code)]
+fn average_vector(vectors: &[Vector3f]) -> Vector3f { vectors.iter().sum::<Vector3f>() / vectors.len() as f32 }
+
+#[allow(dead_code)]
+pub(super) fn average_unit(normals: &[Unit3f]) -> Unit3f {
+    Unit::try_new(normals.iter().map(|n| n.into_inner()).sum(), 0.0).unwrap_or(normals[0])
+}
+
+#[allow(dead_code)]
+fn average_point(points: &[Point3f]) -> Point3f { Point3::from(points.iter().map(|p| p.coords).sum::<Vector3f>() / points.len() as f32) }
+
+// The four triangles a triangle is split into, given the middles of its edges.
+#[allow(dead_code)]
+pub(super) fn split((a, b, c): (usize, usize, usize), (ab, bc, ca): (usize, usize, usize)) -> [(usize, usize, usize); 4] {
+    [(a, ab, ca), (b, bc, ab), (c, ca, bc), (ab, bc, ca)]
+}
+
+#[allow(dead_code)]
+pub(super) fn key(a: usize, b: usize) -> (usize, usize) { (a.min(b), a.max(b)) }
+
+// Splits the crease edges into the two halves they become, given the new midpoint of each edge.
+#[allow(dead_code)]
+fn split_creases(sharp: &HashSet<(usize, usize)>, midpoints: &HashMap<(usize, usize), usize>) -> HashSet<(usize, usize)> {
+    sharp.iter()
+        .filter_map(|edge| midpoints.get(edge).map(|&m| (edge, m)))
+        .flat_map(|(&(a, b), m)| vec![key(a, m), key(m, b)])
+        .collect()
+}
+
+// Finds the edges of a mesh where faces meet at more than the given angle (in radians),
+// to be kept sharp when subdividing.
+#[allow(dead_code)]
+pub fn crease_edges(mesh: &TriangleMesh, angle: f32) -> Vec<(usize, usize)> {
+    let mut edges: HashMap<(usize, usize), Vec<Vector3f>> = HashMap::new();
+    for face in mesh.faces() {
+        let (a, b, c) = face.vertices;
+        let normal = (mesh.vertices[b] - mesh.vertices[a]).cross(&(mesh.vertices[c] - mesh.vertices[a]));
+        for &(from, to) in &[(a, b), (b, c), (c, a)] { edges.entry(key(from, to)).or_default().push(normal); }
+    }
+
+    let mut creases: Vec<(usize, usize)> = edges.into_iter()
+        .filter(|(_, normals)| normals.len() == 2 && normals[0].angle(&normals[1]) > angle)
+        .map(|(edge, _)| edge)
+        .collect();
+    creases.sort_unstable();
+    creases
+}
+
+// New position of an existing vertex, from the number of sharp edges (creases or boundaries) meeting at it:
+// with fewer than two it is smoothed with the given rule, with two it moves along the crease, and with more it's a corner.
+#[allow(dead_code)]
+fn vertex_rule(point: Point3f, sharp_neighbors: &[Point3f], smooth: impl FnOnce() -> Point3f) -> Point3f {
+    match sharp_neighbors.len() {
+        0 | 1 => smooth(),
+        2 => Point3::from(0.75 * point.coords + 0.125 * (sharp_neighbors[0].coords + sharp_neighbors[1].coords)),
+        _ => point
+    }
+}
+
+// Refines a triangle mesh with Loop subdivision, splitting every triangle into four per level.
+// Boundary edges and the given crease edges (pairs of vertices) stay sharp, following the
+// cubic B-spline through them. Texture coordinates, normals and vertex colors are interpolated linearly.
+#[allow(dead_code)]
+pub fn loop_subdivide(mesh: TriangleMesh, level: usize, creases: &[(usize, usize)]) -> TriangleMesh {
+    let mut faces = mesh.faces();
+    let TriangleMesh { mut vertices, normals, uvs, mut colors, materials, .. } = mesh;
+    let mut normals = Corners::new(normals, average_unit);
+    let mut uvs = Corners::new(uvs, average_uv);
+    let mut sharp: HashSet<(usize, usize)> = creases.iter().map(|&(a, b)| key(a, b)).collect();
+
+    for _ in 0..level {
+        // The vertices opposite each edge, one for every triangle along it.
+        let mut opposite: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for face in &faces {
+            let (a, b, c) = face.vertices;
+            for &(from, to, other) in &[(a, b, c), (b, c, a), (c, a, b)] { opposite.entry(key(from, to)).or_default().push(other); }
+        }
+        let mut edges: Vec<(usize, usize)> = opposite.keys().cloned().collect();
+        edges.sort_unstable();
+        let is_sharp = |edge: &(usize, usize)| sharp.contains(edge) || opposite[edge].len() != 2;
+
+        let mut neighbors = vec![Vec::new(); vertices.len()];
+        let mut sharp_neighbors = vec![Vec::new(); vertices.len()];
+        for edge in &edges {
+            let (a, b) = *edge;
+            neighbors[a].push(vertices[b]);
+            neighbors[b].push(vertices[a]);
+            if is_sharp(edge) { sharp_neighbors[a].push(vertices[b]); sharp_neighbors[b].push(vertices[a]); }
+        }
+
+        let mut refined: Vec<Point3f> = (0..vertices.len()).map(|v| vertex_rule(vertices[v], &sharp_neighbors[v], || {
+            let n = neighbors[v].len();
+            if n < 3 { return vertices[v]; }
+            let beta = (0.625 - (0.375 + 0.25 * (2.0 * PI / n as f32).cos()).powi(2)) / n as f32;
+            Point3::from((1.0 - n as f32 * beta) * vertices[v].coords + beta * neighbors[v].iter().map(|p| p.coords).sum::<Vector3f>())
+        })).collect();
+
+        let mut midpoints = HashMap::new();
+        for edge in &edges {
+            let (a, b) = *edge;
+            let point = if is_sharp(edge) { average_point(&[vertices[a], vertices[b]]) } else {
+                let (c, d) = (opposite[edge][0], opposite[edge][1]);
+                Point3::from(0.375 * (vertices[a].coords + vertices[b].coords) + 0.125 * (vertices[c].coords + vertices[d].coords))
+            };
+            refined.push(point);
+            if !colors.is_empty() { colors.push(0.5 * (colors[a] + colors[b])); }
+            midpoints.insert(*edge, refined.len() - 1);
+        }
+
+        faces = faces.into_iter().flat_map(|face| {
+            let (a, b, c) = face.vertices;
+            let (ab, bc, ca) = (midpoints[&key(a, b)], midpoints[&key(b, c)], midpoints[&key(c, a)]);
+            let face_normals = face.normals.map(|handles| normals.split(handles));
+            let face_uvs = face.uvs.map(|handles| uvs.split(handles));
+
+            let tris = split((a, b, c), (ab, bc, ca));
+            (0..4).map(|i| Face {
+                vertices: tris[i],
+                normals: face_normals.map(|n| n[i]),
+                uvs: face_uvs.map(|t| t[i]),
+                material: face.material
+            }).collect::<Vec<_>>()
+        }).collect();
+
+        sharp = split_creases(&sharp, &midpoints);
+        vertices = refined;
+    }
+
+    TriangleMesh::from_faces(vertices, normals.values, uvs.values, materials, faces).with_vertex_colors(colors)
+}
+
+// Refines the polygons of an OBJ file with Catmull-Clark subdivision, which turns every n-gon into
+// n quads per level. Boundary edges and the given crease edges (pairs of positions) stay sharp.
+// Texture coordinates and normals are interpolated linearly, and faces keep their material and group.
+#[allow(dead_code)]
+pub fn catmull_clark(obj: &Obj, level: usize, creases: &[(usize, usize)]) -> Obj {
+    let mut obj = obj.clone();
+    let mut normals = Corners::new(obj.normals.clone(), average_vector);
+    let mut uvs = Corners::new(obj.uvs.clone(), average_uv);
+    let mut sharp: HashSet<(usize, usize)> = creases.iter().map(|&(a, b)| key(a, b)).collect();
+
+    for _ in 0..level {
+        let positions = &obj.positions;
+        let corners = |face: &ObjFace| face.corners.iter().map(|c| c.0).collect::<Vec<usize>>();
+
+        let face_points: Vec<Point3f> = obj.faces.iter()
+            .map(|face| average_point(&corners(face).iter().map(|&p| positions[p]).collect::<Vec<_>>()))
+            .collect();
+
+        let mut edge_faces: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        let mut vertex_faces = vec![Vec::new(); positions.len()];
+        for (i, face) in obj.faces.iter().enumerate() {
+            let c = corners(face);
+            for k in 0..c.len() {
+                edge_faces.entry(key(c[k], c[(k + 1) % c.len()])).or_default().push(i);
+                vertex_faces[c[k]].push(i);
+            }
+        }
+        let mut edges: Vec<(usize, usize)> = edge_faces.keys().cloned().collect();
+        edges.sort_unstable();
+        let is_sharp = |edge: &(usize, usize)| sharp.contains(edge) || edge_faces[edge].len() != 2;
+
+        let mut vertex_edges = vec![Vec::new(); positions.len()];
+        for &(a, b) in &edges { vertex_edges[a].push((a, b)); vertex_edges[b].push((a, b)); }
+
+        let mut refined: Vec<Point3f> = (0..positions.len()).map(|v| {
+            let sharp_neighbors: Vec<Point3f> = vertex_edges[v].iter()
+                .filter(|edge| is_sharp(edge))
+                .map(|&(a, b)| positions[if a == v { b } else { a }])
+                .collect();
+            vertex_rule(positions[v], &sharp_neighbors, || {
+                let n = vertex_edges[v].len();
+                if n < 3 { return positions[v]; }
+                let q = average_point(&vertex_faces[v].iter().map(|&f| face_points[f]).collect::<Vec<_>>());
+                let r = average_point(&vertex_edges[v].iter().map(|&(a, b)| average_point(&[positions[a], positions[b]])).collect::<Vec<_>>());
+                Point3::from((q.coords + 2.0 * r.coords + (n as f32 - 3.0) * positions[v].coords) / n as f32)
+            })
+        }).collect();
+
+        let first_face_point = refined.len();
+        refined.extend(&face_points);
+
+        let mut midpoints = HashMap::new();
+        for edge in &edges {
+            let (a, b) = *edge;
+            let point = if is_sharp(edge) { average_point(&[positions[a], positions[b]]) } else {
+                let f = &edge_faces[edge];
+                average_point(&[positions[a], positions[b], face_points[f[0]], face_points[f[1]]])
+            };
+            refined.push(point);
+            midpoints.insert(*edge, refined.len() - 1);
+        }
+
+        obj.faces = obj.faces.iter().enumerate().flat_map(|(i, face)| {
+            let m = face.corners.len();
+            let uv_handles: Option<Vec<usize>> = face.corners.iter().map(|c| c.1).collect();
+            let normal_handles: Option<Vec<usize>> = face.corners.iter().map(|c| c.2).collect();
+            let face_uv = uv_handles.as_ref().map(|h| uvs.face(h));
+            let face_normal = normal_handles.as_ref().map(|h| normals.face(h));
+
+            (0..m).map(|k| {
+                let (prev, here, next) = ((k + m - 1) % m, k, (k + 1) % m);
+                let uv = |uvs: &mut Corners<_>, a: usize, b: usize| uv_handles.as_ref().map(|h| uvs.edge(h[a], h[b]));
+                let normal = |normals: &mut Corners<_>, a: usize, b: usize| normal_handles.as_ref().map(|h| normals.edge(h[a], h[b]));
+                let position = |k: usize| face.corners[k].0;
+                ObjFace {
+                    corners: vec![
+                        (position(here), uv_handles.as_ref().map(|h| h[here]), normal_handles.as_ref().map(|h| h[here])),
+                        (midpoints[&key(position(here), position(next))], uv(&mut uvs, here, next), normal(&mut normals, here, next)),
+                        (first_face_point + i, face_uv, face_normal),
+                        (midpoints[&key(position(prev), position(here))], uv(&mut uvs, prev, here), normal(&mut normals, prev, here))
+                    ],
+                    material: face.material,
+                    group: face.group
+                }
+            }).collect::<Vec<_>>()
+        }).collect();
+
+        sharp = split_creases(&sharp, &midpoints);
+        obj.positions = refined;
+    }
+
+    obj.uvs = uvs.values;
+    obj.normals = normals.values;
+    obj
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use na::Vector3;
+
+    use super::*;
+    use crate::mesh::{cube, edge_report, grid, weld_vertices};
+    use crate::object::Surface;
+
+    #[test]
+    fn subdivide_loop() {
+        let mesh = weld_vertices(cube(2.0, |_, _| Texture::white()), 1e-4);
+        let creases = crease_edges(&mesh, 0.5);
+        assert_eq!(creases.len(), 12);
+
+        // Smoothed, the corners are pulled in while the mesh stays closed.
+        let smooth = loop_subdivide(weld_vertices(cube(2.0, |_, _| Texture::white()), 1e-4), 2, &[]);
+        assert_eq!(smooth.triangles.len(), 12 * 16);
+        assert!(edge_report(&smooth).is_closed_manifold());
+        assert!(smooth.vertices.iter().all(|p| p.coords.amax() <= 1.0 && p.coords.norm() < 3f32.sqrt() - 0.1));
+
+        // With every edge of the cube creased, its corners and edges stay put.
+        let sharp = loop_subdivide(mesh, 2, &creases);
+        assert!(sharp.vertices.iter().any(|p| (p.coords - Vector3::new(1.0, 1.0, 1.0)).norm() < 1e-6));
+        assert!(sharp.vertices.iter().all(|p| (p.coords.amax() - 1.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn subdivide_loop_attributes() {
+        // A flat grid stays flat, and its texture coordinates and normals follow the grid's own.
+        let mesh = loop_subdivide(grid(4.0, 4.0, 4, 4, |u, v| Texture::new(u, v, 0.0, 1.0)), 1, &[]);
+        assert_eq!(mesh.triangles.len(), 4 * 4 * 2 * 4);
+        assert!(mesh.vertices.iter().all(|p| p.y == 0.0));
+        for &point in &[Point3::new(0.25, 0.0, 0.25), Point3::new(-1.3, 0.0, 0.6)] {
+            let (u, v) = mesh.uv(point);
+            assert!((u - (point.x / 4.0 + 0.5)).abs() < 1e-5 && (v - (point.z / 4.0 + 0.5)).abs() < 1e-5);
+            assert!((mesh.normal(point).into_inner() - Vector3::y()).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn subdivide_catmull_clark() {
+        let source = "v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\nv -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\n\
+                      vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                      f 1/1 4/2 3/3 2/4\nf 5/1 6/2 7/3 8/4\nf 1/1 2/2 6/3 5/4\nf 2/1 3/2 7/3 6/4\nf 3/1 4/2 8/3 7/4\nf 4/1 1/2 5/3 8/4\n";
+        let obj = Obj::parse(source, Path::new("")).unwrap();
+
+        let once = catmull_clark(&obj, 1, &[]);
+        assert_eq!(once.faces.len(), 24);
+        assert!(once.faces.iter().all(|face| face.corners.len() == 4));
+        // The corners move to 5/9 of the way out, and face points are at the centers of the faces.
+        assert!((once.positions[6].coords - Vector3::repeat(5.0 / 9.0)).norm() < 1e-6);
+        assert!((once.positions[8].coords - Vector3::new(0.0, 0.0, -1.0)).norm() < 1e-6);
+        assert_eq!(once.uvs[once.faces[0].corners[2].1.unwrap()], (0.5, 0.5));
+
+        let mut twice = catmull_clark(&obj, 2, &[]);
+        assert_eq!(twice.faces.len(), 96);
+        let mesh = twice.mesh();
+        assert_eq!(mesh.triangles.len(), 192);
+        assert!(edge_report(&weld_vertices(mesh, 1e-5)).is_closed_manifold());
+
+        // Creased edges keep the cube's shape.
+        let creases = [(0, 1), (1, 2), (2, 3), (3, 0), (4, 5), (5, 6), (6, 7), (7, 4), (0, 4), (1, 5), (2, 6), (3, 7)];
+        let sharp = catmull_clark(&obj, 2, &creases);
+        assert_eq!(sharp.positions[6], Point3::new(1.0, 1.0, 1.0));
+        assert!(sharp.positions.iter().all(|p| (p.coords.amax() - 1.0).abs() < 1e-6));
+    }
+}
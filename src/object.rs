@@ -1,10 +1,27 @@
 
-mod sphere; pub use sphere::*;
+mod sphere; #[allow(unused_imports)] pub use sphere::*;
 mod plane; pub use plane::*;
+mod parallelogram; #[allow(unused_imports)] pub use parallelogram::*;
+mod cuboid; #[allow(unused_imports)] pub use cuboid::*;
+mod cylinder; pub use cylinder::*;
+mod cone; pub use cone::*;
+mod disk; #[allow(unused_imports)] pub use disk::*;
+mod torus; pub use torus::*;
+mod heightfield; #[allow(unused_imports)] pub use heightfield::*;
 mod triangle; pub use triangle::*;
 mod bound; pub use bound::*;
-mod point_light; pub use point_light::*;
+mod csg; #[allow(unused_imports)] pub use csg::*;
+mod instance; pub use instance::*;
+mod sdf; #[allow(unused_imports)] pub use sdf::*;
+mod tessellate; pub use tessellate::*;
+mod point_light; #[allow(unused_imports)] pub use point_light::*;
 
+mod local_frame;
+mod roots;
+
+use na::Affine3;
+
+use crate::camera::Camera;
 use crate::types::*;
 
 // A trait for types that can be in Objects.
@@ -16,13 +33,43 @@ pub trait Surface {
     fn intersect(&self, ray: Ray) -> Option<f32>;
 
     // Takes in a point (assumed to be on the object's surface)
-    // and returns the normal vector off of that point.
+    // and returns the normal vector off of that point, used for shading.
     fn normal(&self, point: Point3f) -> Unit3f;
 
+    // Takes in a point (assumed to be on the object's surface) and returns
+    // the normal of the actual geometry there, which may differ from the
+    // smoothed shading normal. Used to tell which side of the surface lights are on.
+    fn geometric_normal(&self, point: Point3f) -> Unit3f { self.normal(point) }
+
     // Takes in a point (assumed to be on the object's surface)
     // and returns the texture information on that point.
     fn get_texture(&self, point: Point3f) -> Texture;
 
+    // Takes in a point (assumed to be on the object's surface)
+    // and returns the texture coordinates of that point.
+    fn uv(&self, _point: Point3f) -> (f32, f32) { (0.0, 0.0) }
+
+    // Takes in a point (assumed to be on the object's surface)
+    // and returns the index of the primitive it lies on,
+    // for objects made up of several primitives.
+    fn primitive(&self, _point: Point3f) -> usize { 0 }
+
+    // Takes in a ray and returns the spans of it that lie inside
+    // the object, as sorted (entry, exit) distance pairs. Spans may
+    // start behind the ray's origin or reach out to infinity.
+    // Only closed surfaces have an inside, so others return nothing.
+    fn intervals(&self, _ray: Ray) -> Vec<(f32, f32)> { Vec::new() }
+
+    // Takes in a point and returns its distance to the object's surface.
+    // Used to tell which part of a compound object a point lies on.
+    fn distance(&self, _point: Point3f) -> f32 { f32::INFINITY }
+
+    // Approximates the object's surface with a triangle mesh, using the given
+    // number of segments around curved directions. Surfaces that are unbounded
+    // or only implicitly defined can't be tessellated, and return nothing.
+    #[allow(dead_code)]
+    fn tessellate(&self, _resolution: usize) -> Option<TriangleMesh> { None }
+
     // Creates a bounding sphere around the object.
     fn bound(&self) -> Bound;
 }
@@ -49,12 +96,15 @@ impl Object {
         } else { None }
     }
     pub fn normal(&self, point: Point3f) -> Unit3f { self.surface.normal(point) }
+    pub fn geometric_normal(&self, point: Point3f) -> Unit3f { self.surface.geometric_normal(point) }
     pub fn get_texture(&self, point: Point3f) -> Texture { self.surface.get_texture(point) }
+    pub fn uv(&self, point: Point3f) -> (f32, f32) { self.surface.uv(point) }
+    pub fn primitive(&self, point: Point3f) -> usize { self.surface.primitive(point) }
 }
 
 pub trait Light {
     // Determine if the light is able to illuminate the point.
-    fn check_shadow(&self, point: Point3f, objects: &Vec<Object>) -> bool;
+    fn check_shadow(&self, point: Point3f, objects: &[Object]) -> bool;
 
     // Compute color on a point.
     fn get_color(&self, point: Point3f) -> Color;
@@ -64,10 +114,31 @@ pub trait Light {
 
     // Return the direction from the point to the light source.
     fn direction(&self, point: Point3f) -> Unit3f;
+
+    // Return a copy of the light moved by an affine transform.
+    #[allow(dead_code)]
+    fn transformed(&self, transform: &Affine3<f32>) -> Box<dyn Light>;
+}
+
+// Geometry that depends on the camera it's seen from, such as a mesh displaced
+// finely enough for the pixels it covers. It's built once the camera is known.
+pub trait Deferred {
+    fn build(self: Box<Self>, camera: &Camera) -> TriangleMesh;
 }
 
 pub struct Scene {
     pub objects: Vec<Object>,
     pub lights: Vec<Box<dyn Light>>,
+    pub deferred: Vec<Box<dyn Deferred>>, // Built into objects by prepare().
     pub background: Color
 }
+
+impl Scene {
+    // Builds the deferred geometry for the camera about to render the scene,
+    // adding it after the other objects. Scenes have to be prepared before rendering.
+    pub fn prepare(&mut self, camera: &Camera) {
+        for deferred in self.deferred.drain(..) {
+            self.objects.push(Object::new(deferred.build(camera)));
+        }
+    }
+}
@@ -7,7 +7,7 @@ use crate::types::*;
 
 // A bounding sphere, used for
 // intersection test optimization.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct Bound {
     pub center: Point3f,
     pub radius: f32,
@@ -20,11 +20,46 @@ impl Bound {
     pub fn is_intersected(&self, ray: Ray) -> bool {
         if self.bypass { return true; }
 
-        let l = ray.origin - self.center;
-        l.norm_squared() >= self.radius * self.radius
+        let l = self.center - ray.origin;
+        let r2 = self.radius * self.radius;
+
+        // Rays starting inside the sphere always hit it.
+        if l.norm_squared() <= r2 { return true; }
+
+        // Otherwise, the sphere has to be in front of the ray,
+        // and the ray has to pass within the radius of its center.
+        let tca = l.dot(&ray.direction);
+        tca >= 0.0 && l.norm_squared() - tca * tca <= r2
     }
 
     // pub fn contains(&self, point: &Point3f) -> bool { distance(&self.center, point) < self.radius }
 
     pub fn bypass() -> Self { Bound { center: Point3::origin(), radius: 0.0, bypass: true } }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector3;
+    use crate::object::{Object, Sphere};
+
+    #[test]
+    fn bound_is_intersected() {
+        let bound = Bound { center: Point3::new(0.0, 0.0, 5.0), radius: 1.0, bypass: false };
+
+        assert!(bound.is_intersected(Ray::new(Point3::origin(), Vector3::z())));
+        assert!(!bound.is_intersected(Ray::new(Point3::origin(), -Vector3::z())));
+        assert!(!bound.is_intersected(Ray::new(Point3::origin(), Vector3::x())));
+        assert!(bound.is_intersected(Ray::new(Point3::new(0.0, 0.0, 5.5), Vector3::x())));
+    }
+
+    #[test]
+    fn bound_object_from_inside() {
+        // Bounds used to reject every ray starting inside them, so objects
+        // could not be hit from within, such as by rays refracted into them.
+        let sphere = Object::new(Sphere::new_solid(0.0, 0.0, 5.0, 1.0, Texture::white()));
+        assert_eq!(sphere.intersect(Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::z())), Some(1.0));
+        assert_eq!(sphere.intersect(Ray::new(Point3::origin(), Vector3::z())), Some(4.0));
+        assert_eq!(sphere.intersect(Ray::new(Point3::origin(), Vector3::new(0.0, 1.0, 2.0).normalize())), None);
+    }
+}
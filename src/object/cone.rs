@@ -0,0 +1,215 @@
+extern crate nalgebra as na;
+
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use na::*;
+
+use crate::types::*;
+use super::{Surface, Tessellation, TriangleMesh, share, bound::*, csg::span, local_frame::*, roots::*};
+
+pub struct Cone {
+    pub radius: f32, // Radius of the base.
+    pub height: f32,
+    pub capped: bool, // If false, the base is left open.
+
+    frame: LocalFrame, // Local frame, with the base at the origin and the apex along y.
+
+    texture: Rc<dyn Fn(f32, f32) -> Texture> // Texture map.
+                                              // The side uses the angle around the axis and the height (from 0-1),
+                                              // while the base is mapped over 0-1 along the local x and z axes.
+}
+
+#[allow(dead_code)]
+impl Cone {
+    // Creates a new capped cone from the center of its base and its apex.
+    pub fn new<F>(base: Point3f, apex: Point3f, radius: f32, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+    {
+        Cone {
+            radius,
+            height: distance(&base, &apex),
+            capped: true,
+            frame: LocalFrame::new(base, apex - base),
+            texture: Rc::new(texture)
+        }
+    }
+
+    // Creates a new capped cone of a solid color.
+    pub fn new_solid(base: Point3f, apex: Point3f, radius: f32, texture: Texture) -> Self
+        { Cone::new(base, apex, radius, move |_, _| texture) }
+
+    // Creates a new cone with an open base.
+    pub fn new_open<F>(base: Point3f, apex: Point3f, radius: f32, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+        { Cone { capped: false, ..Cone::new(base, apex, radius, texture) } }
+
+    // Creates a new cone with an open base, of a solid color.
+    pub fn new_open_solid(base: Point3f, apex: Point3f, radius: f32, texture: Texture) -> Self
+        { Cone::new_open(base, apex, radius, move |_, _| texture) }
+
+    // Slope of the side: the radius shrinks by this much per unit of height.
+    fn slope(&self) -> f32 { self.radius / self.height }
+
+    // Takes in a point (assumed to be on the cone's surface) and
+    // returns the part it lies on: 0 for the side and 1 for the base.
+    pub fn part(&self, point: Point3f) -> usize {
+        if !self.capped { return 0; }
+
+        let p = self.frame.point_to_local(point);
+        let rho = (p.x * p.x + p.z * p.z).sqrt();
+        let side = (rho - self.slope() * (self.height - p.y)).abs() / (1.0 + self.slope() * self.slope()).sqrt();
+
+        if side <= p.y.abs() { 0 } else { 1 }
+    }
+
+    // Returns every distance at which the ray's line crosses the cone's surface.
+    fn hits(&self, ray: Ray) -> Vec<f64> {
+        let local = self.frame.ray_to_local(ray);
+        let (o, d) = (local.origin.coords.map(f64::from), local.direction.map(f64::from));
+        let (radius, height) = (self.radius as f64, self.height as f64);
+        let k2 = (radius / height) * (radius / height);
+
+        // x^2 + z^2 = k^2 (h - y)^2
+        let h = height - o.y;
+        let mut hits: Vec<f64> = solve_quadratic(d.x * d.x + d.z * d.z - k2 * d.y * d.y,
+                                                 2.0 * (o.x * d.x + o.z * d.z + k2 * h * d.y),
+                                                 o.x * o.x + o.z * o.z - k2 * h * h)
+            .into_iter()
+            .filter(|t| (0.0..=height).contains(&(o.y + t * d.y)))
+            .collect();
+
+        if self.capped && d.y.abs() > 1e-12 {
+            let t = -o.y / d.y;
+            let (x, z) = (o.x + t * d.x, o.z + t * d.z);
+            if x * x + z * z <= radius * radius { hits.push(t); }
+        }
+
+        hits
+    }
+}
+
+impl Surface for Cone {
+    fn intersect(&self, ray: Ray) -> Option<f32> {
+        self.hits(ray).into_iter().filter(|&t| t >= 0.0).fold(None, |min: Option<f64>, t| Some(min.map_or(t, |min| min.min(t))))
+            .map(|t| t as f32)
+    }
+
+    fn normal(&self, point: Point3f) -> Unit3f {
+        if self.part(point) == 1 { return -self.frame.axis(); }
+
+        let p = self.frame.point_to_local(point);
+        let rho = (p.x * p.x + p.z * p.z).sqrt().max(1e-6);
+        self.frame.normal_to_world(Vector3::new(p.x / rho, self.slope(), p.z / rho))
+    }
+
+    fn get_texture(&self, point: Point3f) -> Texture {
+        let (x, y) = self.uv(point);
+        (*self.texture)(x, y)
+    }
+
+    fn uv(&self, point: Point3f) -> (f32, f32) {
+        let p = self.frame.point_to_local(point);
+        match self.part(point) {
+            0 => (0.5 + p.z.atan2(p.x) / (2.0 * PI), p.y / self.height),
+            _ => (0.5 * (p.x / self.radius + 1.0), 0.5 * (p.z / self.radius + 1.0))
+        }
+    }
+
+    fn primitive(&self, point: Point3f) -> usize { self.part(point) }
+
+    // Only a capped cone is closed.
+    fn intervals(&self, ray: Ray) -> Vec<(f32, f32)> {
+        if !self.capped { return Vec::new(); }
+        span(self.hits(ray)).into_iter().collect()
+    }
+
+    fn distance(&self, point: Point3f) -> f32 {
+        let p = self.frame.point_to_local(point);
+        let q = Vector2::new((p.x * p.x + p.z * p.z).sqrt(), p.y);
+
+        // Distance to the slanted line from the rim of the base to the apex.
+        let (rim, apex) = (Vector2::new(self.radius, 0.0), Vector2::new(0.0, self.height));
+        let edge = apex - rim;
+        let s = ((q - rim).dot(&edge) / edge.norm_squared()).clamp(0.0, 1.0);
+        let side = (q - (rim + s * edge)).norm();
+
+        if self.capped { side.min(((q.x - self.radius).max(0.0).powi(2) + q.y * q.y).sqrt()) }
+        else { side }
+    }
+
+    // The side is a single row of quads from the base to the apex,
+    // which collapse into triangles there, and the cap is a fan.
+    fn tessellate(&self, resolution: usize) -> Option<TriangleMesh> {
+        let (frame, radius) = (&self.frame, self.radius);
+        let mut tessellation = Tessellation::new();
+        tessellation.patch(resolution, 1, |s, t| {
+            let phi = 2.0 * PI * (s - 0.5);
+            let rho = radius * (1.0 - t);
+            (frame.point_to_world(Point3::new(rho * phi.cos(), t * self.height, rho * phi.sin())),
+             frame.rotation * Vector3::new(phi.cos(), self.slope(), phi.sin()), (s, t))
+        });
+
+        if self.capped {
+            tessellation.patch(resolution, 1, |s, t| {
+                let phi = 2.0 * PI * (s - 0.5);
+                let (x, z) = (t * radius * phi.cos(), t * radius * phi.sin());
+                (frame.point_to_world(Point3::new(x, 0.0, z)), -frame.axis().into_inner(),
+                 (0.5 * (x / radius + 1.0), 0.5 * (z / radius + 1.0)))
+            });
+        }
+        Some(tessellation.textured(share(&self.texture)))
+    }
+
+    // The smallest sphere around the base circle and the apex.
+    fn bound(&self) -> Bound {
+        let (center, radius) = if self.height <= self.radius { (0.0, self.radius) } else {
+            let c = (self.height * self.height - self.radius * self.radius) / (2.0 * self.height);
+            (c, self.height - c)
+        };
+        Bound { center: self.frame.point_to_world(Point3::new(0.0, center, 0.0)), radius, bypass: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cone_intersect() {
+        let cone = Cone::new_solid(Point3::origin(), Point3::new(0.0, 2.0, 0.0), 1.0, Texture::white());
+
+        // Onto the side, halfway up where the radius is half as big.
+        let ray = Ray::new(Point3::new(-5.0, 1.0, 0.0), Vector3::x());
+        let t = cone.intersect(ray).unwrap();
+        assert!((t - 4.5).abs() < 1e-4);
+        assert_eq!(cone.part(ray.project(t)), 0);
+        assert!((cone.normal(ray.project(t)).into_inner() - Vector3::new(-2.0, 1.0, 0.0).normalize()).norm() < 1e-4);
+
+        // Onto the base.
+        let ray = Ray::new(Point3::new(0.2, -5.0, 0.0), Vector3::y());
+        let t = cone.intersect(ray).unwrap();
+        assert!((t - 5.0).abs() < 1e-4);
+        assert_eq!(cone.part(ray.project(t)), 1);
+        assert!((cone.normal(ray.project(t)).into_inner() + Vector3::y()).norm() < 1e-4);
+
+        // An open cone is seen into from below, up to where the side narrows to the ray.
+        let open = Cone::new_open_solid(Point3::origin(), Point3::new(0.0, 2.0, 0.0), 1.0, Texture::white());
+        assert!((open.intersect(ray).unwrap() - 6.6).abs() < 1e-4);
+    }
+
+    #[test]
+    fn cone_uv() {
+        let cone = Cone::new_solid(Point3::origin(), Point3::new(0.0, 2.0, 0.0), 1.0, Texture::white());
+
+        // Around the axis and up the height on the side.
+        let (u1, v1) = cone.uv(Point3::new(-0.5, 1.0, 0.0));
+        let (u2, v2) = cone.uv(Point3::new(0.25, 1.5, 0.0));
+        assert!((v1 - 0.5).abs() < 1e-4 && (v2 - 0.75).abs() < 1e-4);
+        assert!(((u1 - u2).abs() - 0.5).abs() < 1e-4);
+
+        // Projected straight down onto the base.
+        let (u, v) = cone.uv(Point3::new(0.0, 0.0, 0.0));
+        assert!((u - 0.5).abs() < 1e-4 && (v - 0.5).abs() < 1e-4);
+    }
+}
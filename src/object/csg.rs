@@ -0,0 +1,209 @@
+extern crate nalgebra as na;
+
+use std::cmp::Ordering;
+
+use na::*;
+
+use crate::types::*;
+use super::{Surface, bound::*};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsgOp {
+    Union,        // Inside either operand.
+    Intersection, // Inside both operands.
+    Difference    // Inside the left operand but not the right one.
+}
+
+impl CsgOp {
+    fn contains(self, left: bool, right: bool) -> bool {
+        match self {
+            CsgOp::Union => left || right,
+            CsgOp::Intersection => left && right,
+            CsgOp::Difference => left && !right
+        }
+    }
+}
+
+// Constructive solid geometry: a boolean combination of two closed surfaces.
+// Both operands must implement intervals() and distance() (as every closed
+// primitive does, and Csg itself), so that trees of operations can be built.
+pub struct Csg {
+    pub op: CsgOp,
+    pub left: Box<dyn Surface>,
+    pub right: Box<dyn Surface>
+}
+
+#[allow(dead_code)]
+impl Csg {
+    // Creates a new combination of two surfaces.
+    pub fn new(op: CsgOp, left: impl 'static + Surface, right: impl 'static + Surface) -> Self {
+        Csg { op, left: Box::new(left), right: Box::new(right) }
+    }
+
+    pub fn union(left: impl 'static + Surface, right: impl 'static + Surface) -> Self
+        { Csg::new(CsgOp::Union, left, right) }
+
+    pub fn intersection(left: impl 'static + Surface, right: impl 'static + Surface) -> Self
+        { Csg::new(CsgOp::Intersection, left, right) }
+
+    pub fn difference(left: impl 'static + Surface, right: impl 'static + Surface) -> Self
+        { Csg::new(CsgOp::Difference, left, right) }
+
+    // Takes in a point (assumed to be on the object's surface) and returns
+    // the operand whose boundary it lies on, and whether that operand's
+    // inside is carved away (so its normal has to be flipped).
+    fn operand(&self, point: Point3f) -> (&dyn Surface, bool) {
+        if self.left.distance(point) <= self.right.distance(point) { (&*self.left, false) }
+        else { (&*self.right, self.op == CsgOp::Difference) }
+    }
+}
+
+// Pairs up sorted crossings of a closed surface into (entry, exit) spans.
+// An unpaired crossing at the end (a grazing hit) is dropped.
+pub fn pairs(hits: Vec<f32>) -> Vec<(f32, f32)> {
+    hits.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+// Turns the crossings of a convex surface into the single span between them.
+pub fn span(hits: Vec<f64>) -> Option<(f32, f32)> {
+    if hits.len() < 2 { return None; }
+
+    let min = hits.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = hits.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    Some((min as f32, max as f32))
+}
+
+// Combines the sorted spans of two operands under a boolean operation,
+// sweeping over their endpoints and keeping track of which ones we're inside.
+fn combine(op: CsgOp, left: &[(f32, f32)], right: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    let mut events: Vec<(f32, bool, bool)> = Vec::new(); // Distance, left operand, entering.
+    for &(entry, exit) in left { events.push((entry, true, true)); events.push((exit, true, false)); }
+    for &(entry, exit) in right { events.push((entry, false, true)); events.push((exit, false, false)); }
+    events.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+    let (mut in_left, mut in_right) = (false, false);
+    let mut start = None;
+    let mut spans = Vec::new();
+
+    for (t, is_left, entering) in events {
+        if is_left { in_left = entering; } else { in_right = entering; }
+
+        match (start, op.contains(in_left, in_right)) {
+            (None, true) => start = Some(t),
+            (Some(s), false) => {
+                if t > s { spans.push((s, t)); }
+                start = None;
+            },
+            _ => ()
+        }
+    }
+
+    spans
+}
+
+// Creates the smallest bounding sphere around two others.
+fn enclose(a: Bound, b: Bound) -> Bound {
+    if a.bypass || b.bypass { return Bound::bypass(); }
+
+    let d = distance(&a.center, &b.center);
+    if d + b.radius <= a.radius { return a; }
+    if d + a.radius <= b.radius { return b; }
+
+    let radius = 0.5 * (d + a.radius + b.radius);
+    Bound { center: a.center + (b.center - a.center) * ((radius - a.radius) / d), radius, bypass: false }
+}
+
+impl Surface for Csg {
+    fn intersect(&self, ray: Ray) -> Option<f32> {
+        self.intervals(ray).into_iter()
+            .flat_map(|(entry, exit)| vec![entry, exit])
+            .find(|&t| t >= 0.0 && t.is_finite())
+    }
+
+    fn normal(&self, point: Point3f) -> Unit3f {
+        let (operand, flip) = self.operand(point);
+        let normal = operand.normal(point);
+        if flip { -normal } else { normal }
+    }
+
+    fn geometric_normal(&self, point: Point3f) -> Unit3f {
+        let (operand, flip) = self.operand(point);
+        let normal = operand.geometric_normal(point);
+        if flip { -normal } else { normal }
+    }
+
+    fn get_texture(&self, point: Point3f) -> Texture { self.operand(point).0.get_texture(point) }
+
+    fn uv(&self, point: Point3f) -> (f32, f32) { self.operand(point).0.uv(point) }
+
+    fn primitive(&self, point: Point3f) -> usize { self.operand(point).0.primitive(point) }
+
+    fn intervals(&self, ray: Ray) -> Vec<(f32, f32)> {
+        combine(self.op, &self.left.intervals(ray), &self.right.intervals(ray))
+    }
+
+    fn distance(&self, point: Point3f) -> f32 { self.left.distance(point).min(self.right.distance(point)) }
+
+    fn bound(&self) -> Bound {
+        let (left, right) = (self.left.bound(), self.right.bound());
+        match self.op {
+            CsgOp::Union => enclose(left, right),
+            // Either bound contains the whole intersection, so take the tighter one.
+            CsgOp::Intersection =>
+                if left.bypass || (!right.bypass && right.radius < left.radius) { right } else { left },
+            CsgOp::Difference => left
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Sphere;
+
+    fn spheres() -> (Sphere, Sphere) {
+        let texture = Texture::white();
+        (Sphere::new_solid(0.0, 0.0, 0.0, 1.0, texture), Sphere::new_solid(1.0, 0.0, 0.0, 1.0, texture))
+    }
+
+    #[test]
+    fn csg_union() {
+        let (a, b) = spheres();
+        let csg = Csg::union(a, b);
+
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::x());
+        assert_eq!(csg.intervals(ray), vec![(4.0, 7.0)]);
+        assert_eq!(csg.intersect(ray), Some(4.0));
+
+        // From inside, the far side of the other sphere is hit.
+        assert_eq!(csg.intersect(Ray::new(Point3::origin(), Vector3::x())), Some(2.0));
+    }
+
+    #[test]
+    fn csg_intersection() {
+        let (a, b) = spheres();
+        let csg = Csg::intersection(a, b);
+
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::x());
+        assert_eq!(csg.intersect(ray), Some(5.0));
+        // The boundary there belongs to the second sphere.
+        assert!((csg.normal(ray.project(5.0)).into_inner() + Vector3::x()).norm() < 1e-4);
+    }
+
+    #[test]
+    fn csg_difference() {
+        let (a, b) = spheres();
+        let csg = Csg::difference(a, b);
+
+        assert_eq!(csg.intersect(Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::x())), Some(4.0));
+
+        // From the other side, the ray passes through the carved out part
+        // and hits the inside of the second sphere, whose normal is flipped.
+        let ray = Ray::new(Point3::new(5.0, 0.0, 0.0), -Vector3::x());
+        assert_eq!(csg.intersect(ray), Some(5.0));
+        assert!((csg.normal(ray.project(5.0)).into_inner() - Vector3::x()).norm() < 1e-4);
+
+        // Nothing is left above the second sphere's center.
+        assert_eq!(csg.intersect(Ray::new(Point3::new(1.5, 5.0, 0.0), -Vector3::y())), None);
+    }
+}
@@ -0,0 +1,178 @@
+extern crate nalgebra as na;
+
+use std::rc::Rc;
+
+use na::*;
+
+use crate::types::*;
+use super::{Surface, Tessellation, TriangleMesh, share, bound::*};
+
+pub struct Cuboid {
+    pub center: Point3f,    // Center point of the box.
+    pub half_size: Vector3f, // Half of the box's extent along each of its axes.
+    pub rotation: UnitQuaternion<f32>, // Orientation of the box's axes.
+
+    texture: Rc<dyn Fn(f32, f32) -> Texture> // Texture map.
+                                              // Every face is mapped separately over 0-1.
+}
+
+#[allow(dead_code)]
+impl Cuboid {
+    // Creates a new axis-aligned box from its minimum and maximum corners.
+    pub fn new<F>(min: Point3f, max: Point3f, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+    {
+        Cuboid::oriented(na::center(&min, &max), 0.5 * (max - min), UnitQuaternion::identity(), texture)
+    }
+
+    // Creates a new axis-aligned box of a solid color.
+    pub fn new_solid(min: Point3f, max: Point3f, texture: Texture) -> Self
+        { Cuboid::new(min, max, move |_, _| texture) }
+
+    // Creates a new box with its axes rotated.
+    pub fn oriented<F>(center: Point3f, half_size: Vector3f, rotation: UnitQuaternion<f32>, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+    {
+        Cuboid { center, half_size, rotation, texture: Rc::new(texture) }
+    }
+
+    // Creates a new rotated box of a solid color.
+    pub fn oriented_solid(center: Point3f, half_size: Vector3f, rotation: UnitQuaternion<f32>, texture: Texture) -> Self
+        { Cuboid::oriented(center, half_size, rotation, move |_, _| texture) }
+
+    // Transforms a point into the box's local space,
+    // where it is centered at the origin and axis-aligned.
+    fn to_local(&self, point: Point3f) -> Vector3f { self.rotation.inverse() * (point - self.center) }
+
+    // Takes in a point (assumed to be on the box's surface) and returns
+    // the face it lies on: 0 to 5 for +x, -x, +y, -y, +z and -z.
+    pub fn face(&self, point: Point3f) -> usize {
+        let local = self.to_local(point).component_div(&self.half_size);
+        let axis = local.iamax();
+        2 * axis + if local[axis] < 0.0 { 1 } else { 0 }
+    }
+
+    // Slab test: clips the ray's line against each pair of parallel faces
+    // and returns the distances at which it enters and leaves the box.
+    fn slab(&self, ray: Ray) -> Option<(f32, f32)> {
+        let origin = self.to_local(ray.origin);
+        let direction = self.rotation.inverse() * ray.direction.into_inner();
+
+        let mut t_min = f32::NEG_INFINITY;
+        let mut t_max = f32::INFINITY;
+        for axis in 0..3 {
+            if direction[axis].abs() < 1e-8 {
+                if origin[axis].abs() > self.half_size[axis] { return None; }
+                continue;
+            }
+
+            let mut t0 = (-self.half_size[axis] - origin[axis]) / direction[axis];
+            let mut t1 = ( self.half_size[axis] - origin[axis]) / direction[axis];
+            if t0 > t1 { std::mem::swap(&mut t0, &mut t1); }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+        }
+
+        if t_max < t_min { None } else { Some((t_min, t_max)) }
+    }
+}
+
+impl Surface for Cuboid {
+    fn intersect(&self, ray: Ray) -> Option<f32> {
+        let (t_min, t_max) = self.slab(ray)?;
+
+        if t_max < 0.0 { None }
+        else if t_min >= 0.0 { Some(t_min) }
+        else { Some(t_max) }
+    }
+
+    fn normal(&self, point: Point3f) -> Unit3f {
+        let face = self.face(point);
+        let mut normal = Vector3::zeros();
+        normal[face / 2] = [1.0, -1.0][face % 2];
+        self.rotation * Unit::new_unchecked(normal)
+    }
+
+    fn get_texture(&self, point: Point3f) -> Texture {
+        let (x, y) = self.uv(point);
+        (*self.texture)(x, y)
+    }
+
+    // Each face is mapped so that the texture appears unmirrored when seen
+    // from outside, with the side faces upright along the box's y-axis.
+    fn uv(&self, point: Point3f) -> (f32, f32) {
+        let p = self.to_local(point).component_div(&self.half_size);
+        let (u, v) = match self.face(point) {
+            0 => (-p.z, -p.y),
+            1 => ( p.z, -p.y),
+            2 => ( p.x,  p.z),
+            3 => ( p.x, -p.z),
+            4 => ( p.x, -p.y),
+            _ => (-p.x, -p.y)
+        };
+        (0.5 * (u + 1.0), 0.5 * (v + 1.0))
+    }
+
+    fn primitive(&self, point: Point3f) -> usize { self.face(point) }
+
+    fn intervals(&self, ray: Ray) -> Vec<(f32, f32)> { self.slab(ray).into_iter().collect() }
+
+    fn distance(&self, point: Point3f) -> f32 {
+        let q = self.to_local(point).abs() - self.half_size;
+        let outside = q.map(|x| x.max(0.0)).norm();
+        let inside = q.max().min(0.0);
+        (outside + inside).abs()
+    }
+
+    // Every face is a single quad, with corners placed to match the texture coordinates.
+    fn tessellate(&self, _resolution: usize) -> Option<TriangleMesh> {
+        let mut tessellation = Tessellation::new();
+        for face in 0..6 {
+            tessellation.patch(1, 1, |s, t| {
+                let (u, v) = (2.0 * s - 1.0, 2.0 * t - 1.0);
+                let (p, normal) = match face {
+                    0 => (Vector3::new( 1.0,   -v,   -u),  Vector3::x()),
+                    1 => (Vector3::new(-1.0,   -v,    u), -Vector3::x()),
+                    2 => (Vector3::new(   u,  1.0,    v),  Vector3::y()),
+                    3 => (Vector3::new(   u, -1.0,   -v), -Vector3::y()),
+                    4 => (Vector3::new(   u,   -v,  1.0),  Vector3::z()),
+                    _ => (Vector3::new(  -u,   -v, -1.0), -Vector3::z())
+                };
+                (self.center + self.rotation * p.component_mul(&self.half_size), self.rotation * normal, (s, t))
+            });
+        }
+        Some(tessellation.textured(share(&self.texture)))
+    }
+
+    fn bound(&self) -> Bound { Bound { center: self.center, radius: self.half_size.norm(), bypass: false } }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuboid_intersect() {
+        let cuboid = Cuboid::new_solid(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0), Texture::white());
+
+        let ray = Ray::new(Point3::new(-5.0, 0.5, 0.0), Vector3::x());
+        assert_eq!(cuboid.intersect(ray), Some(4.0));
+        assert_eq!(cuboid.normal(ray.project(4.0)), -Vector3::x_axis());
+
+        // From inside, the exit point is hit.
+        assert_eq!(cuboid.intersect(Ray::new(Point3::origin(), Vector3::y())), Some(1.0));
+
+        assert_eq!(cuboid.intersect(Ray::new(Point3::new(-5.0, 2.0, 0.0), Vector3::x())), None);
+    }
+
+    #[test]
+    fn cuboid_oriented() {
+        let rotation = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), std::f32::consts::FRAC_PI_4);
+        let cuboid = Cuboid::oriented_solid(Point3::origin(), Vector3::new(1.0, 1.0, 1.0), rotation, Texture::white());
+
+        // The box is rotated onto its edge, so it now reaches out to sqrt(2).
+        let t = cuboid.intersect(Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::x())).unwrap();
+        assert!((t - (5.0 - 2.0f32.sqrt())).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,215 @@
+extern crate nalgebra as na;
+
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use na::*;
+
+use crate::types::*;
+use super::{Surface, Tessellation, TriangleMesh, share, bound::*, csg::span, local_frame::*, roots::*};
+
+pub struct Cylinder {
+    pub radius: f32,
+    pub height: f32,
+    pub capped: bool, // If false, the cylinder is an open tube.
+
+    frame: LocalFrame, // Local frame, with the base at the origin and the axis along y.
+
+    texture: Rc<dyn Fn(f32, f32) -> Texture> // Texture map.
+                                              // The side uses the angle around the axis and the height (from 0-1),
+                                              // while the caps are mapped over 0-1 along the local x and z axes.
+}
+
+#[allow(dead_code)]
+impl Cylinder {
+    // Creates a new capped cylinder between two points.
+    pub fn new<F>(base: Point3f, top: Point3f, radius: f32, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+    {
+        Cylinder {
+            radius,
+            height: distance(&base, &top),
+            capped: true,
+            frame: LocalFrame::new(base, top - base),
+            texture: Rc::new(texture)
+        }
+    }
+
+    // Creates a new capped cylinder of a solid color.
+    pub fn new_solid(base: Point3f, top: Point3f, radius: f32, texture: Texture) -> Self
+        { Cylinder::new(base, top, radius, move |_, _| texture) }
+
+    // Creates a new open cylinder (a tube without caps).
+    pub fn new_open<F>(base: Point3f, top: Point3f, radius: f32, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+        { Cylinder { capped: false, ..Cylinder::new(base, top, radius, texture) } }
+
+    // Creates a new open cylinder of a solid color.
+    pub fn new_open_solid(base: Point3f, top: Point3f, radius: f32, texture: Texture) -> Self
+        { Cylinder::new_open(base, top, radius, move |_, _| texture) }
+
+    // Takes in a point (assumed to be on the cylinder's surface) and returns
+    // the part it lies on: 0 for the side, 1 for the bottom cap and 2 for the top cap.
+    pub fn part(&self, point: Point3f) -> usize {
+        if !self.capped { return 0; }
+
+        let p = self.frame.point_to_local(point);
+        let side = ((p.x * p.x + p.z * p.z).sqrt() - self.radius).abs();
+        let bottom = p.y.abs();
+        let top = (p.y - self.height).abs();
+
+        if side <= bottom && side <= top { 0 }
+        else if bottom <= top { 1 }
+        else { 2 }
+    }
+
+    // Returns every distance at which the ray's line crosses the cylinder's surface.
+    fn hits(&self, ray: Ray) -> Vec<f64> {
+        let local = self.frame.ray_to_local(ray);
+        let (o, d) = (local.origin.coords.map(f64::from), local.direction.map(f64::from));
+        let (radius, height) = (self.radius as f64, self.height as f64);
+
+        let mut hits: Vec<f64> = solve_quadratic(d.x * d.x + d.z * d.z,
+                                                 2.0 * (o.x * d.x + o.z * d.z),
+                                                 o.x * o.x + o.z * o.z - radius * radius)
+            .into_iter()
+            .filter(|t| (0.0..=height).contains(&(o.y + t * d.y)))
+            .collect();
+
+        if self.capped && d.y.abs() > 1e-12 {
+            for &cap in &[0.0, height] {
+                let t = (cap - o.y) / d.y;
+                let (x, z) = (o.x + t * d.x, o.z + t * d.z);
+                if x * x + z * z <= radius * radius { hits.push(t); }
+            }
+        }
+
+        hits
+    }
+}
+
+impl Surface for Cylinder {
+    fn intersect(&self, ray: Ray) -> Option<f32> {
+        self.hits(ray).into_iter().filter(|&t| t >= 0.0).fold(None, |min: Option<f64>, t| Some(min.map_or(t, |min| min.min(t))))
+            .map(|t| t as f32)
+    }
+
+    fn normal(&self, point: Point3f) -> Unit3f {
+        let p = self.frame.point_to_local(point);
+        match self.part(point) {
+            0 => self.frame.normal_to_world(Vector3::new(p.x, 0.0, p.z)),
+            1 => -self.frame.axis(),
+            _ => self.frame.axis()
+        }
+    }
+
+    fn get_texture(&self, point: Point3f) -> Texture {
+        let (x, y) = self.uv(point);
+        (*self.texture)(x, y)
+    }
+
+    fn uv(&self, point: Point3f) -> (f32, f32) {
+        let p = self.frame.point_to_local(point);
+        match self.part(point) {
+            0 => (0.5 + p.z.atan2(p.x) / (2.0 * PI), p.y / self.height),
+            _ => (0.5 * (p.x / self.radius + 1.0), 0.5 * (p.z / self.radius + 1.0))
+        }
+    }
+
+    fn primitive(&self, point: Point3f) -> usize { self.part(point) }
+
+    // Only a capped cylinder is closed.
+    fn intervals(&self, ray: Ray) -> Vec<(f32, f32)> {
+        if !self.capped { return Vec::new(); }
+        span(self.hits(ray)).into_iter().collect()
+    }
+
+    fn distance(&self, point: Point3f) -> f32 {
+        let p = self.frame.point_to_local(point);
+        let rho = (p.x * p.x + p.z * p.z).sqrt();
+
+        if self.capped {
+            // Distance to the rectangle swept around the axis.
+            let (dx, dy) = (rho - self.radius, (p.y - 0.5 * self.height).abs() - 0.5 * self.height);
+            ((dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt() + dx.max(dy).min(0.0)).abs()
+        } else {
+            let dy = (-p.y).max(p.y - self.height).max(0.0);
+            ((rho - self.radius).powi(2) + dy * dy).sqrt()
+        }
+    }
+
+    fn tessellate(&self, resolution: usize) -> Option<TriangleMesh> {
+        let (frame, radius) = (&self.frame, self.radius);
+        let mut tessellation = Tessellation::new();
+        tessellation.patch(resolution, 1, |s, t| {
+            let phi = 2.0 * PI * (s - 0.5);
+            let normal = Vector3::new(phi.cos(), 0.0, phi.sin());
+            (frame.point_to_world(Point3::new(radius * normal.x, t * self.height, radius * normal.z)), frame.rotation * normal, (s, t))
+        });
+
+        if self.capped {
+            for &(y, side) in &[(0.0, -1.0), (self.height, 1.0)] {
+                tessellation.patch(resolution, 1, |s, t| {
+                    let phi = 2.0 * PI * (s - 0.5);
+                    let (x, z) = (t * radius * phi.cos(), t * radius * phi.sin());
+                    (frame.point_to_world(Point3::new(x, y, z)), side * frame.axis().into_inner(),
+                     (0.5 * (x / radius + 1.0), 0.5 * (z / radius + 1.0)))
+                });
+            }
+        }
+        Some(tessellation.textured(share(&self.texture)))
+    }
+
+    fn bound(&self) -> Bound {
+        let half = 0.5 * self.height;
+        Bound {
+            center: self.frame.point_to_world(Point3::new(0.0, half, 0.0)),
+            radius: (self.radius * self.radius + half * half).sqrt(),
+            bypass: false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cylinder_intersect() {
+        let cylinder = Cylinder::new_solid(Point3::origin(), Point3::new(0.0, 0.0, 2.0), 1.0, Texture::white());
+
+        // Onto the side.
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 1.0), Vector3::x());
+        let t = cylinder.intersect(ray).unwrap();
+        assert!((t - 4.0).abs() < 1e-4);
+        assert!((cylinder.normal(ray.project(t)).into_inner() + Vector3::x()).norm() < 1e-4);
+
+        // Onto the top cap.
+        let ray = Ray::new(Point3::new(0.5, 0.0, 5.0), -Vector3::z());
+        let t = cylinder.intersect(ray).unwrap();
+        assert!((t - 3.0).abs() < 1e-4);
+        assert_eq!(cylinder.part(ray.project(t)), 2);
+        assert!((cylinder.normal(ray.project(t)).into_inner() - Vector3::z()).norm() < 1e-4);
+
+        // An open cylinder is seen through from the end.
+        let open = Cylinder::new_open_solid(Point3::origin(), Point3::new(0.0, 0.0, 2.0), 1.0, Texture::white());
+        assert_eq!(open.intersect(ray), None);
+    }
+
+    #[test]
+    fn cylinder_uv() {
+        let cylinder = Cylinder::new_solid(Point3::origin(), Point3::new(0.0, 2.0, 0.0), 1.0, Texture::white());
+
+        // Around the axis and up the height on the side.
+        let (u1, v1) = cylinder.uv(Point3::new(-1.0, 1.0, 0.0));
+        let (u2, v2) = cylinder.uv(Point3::new(1.0, 1.5, 0.0));
+        assert!((v1 - 0.5).abs() < 1e-4 && (v2 - 0.75).abs() < 1e-4);
+        assert!(((u1 - u2).abs() - 0.5).abs() < 1e-4);
+
+        // Projected along the axis onto the caps.
+        for &y in &[0.0, 2.0] {
+            let (u, v) = cylinder.uv(Point3::new(0.0, y, 0.0));
+            assert!((u - 0.5).abs() < 1e-4 && (v - 0.5).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+extern crate nalgebra as na;
+
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use crate::types::*;
+use super::{Surface, Tessellation, TriangleMesh, share, bound::*, local_frame::*};
+
+// A flat disk, or an annulus if it has an inner radius.
+// Like planes, disks are one-sided and face along their normal.
+pub struct Disk {
+    pub radius: f32,
+    pub inner_radius: f32,
+
+    frame: LocalFrame, // Local frame, with the disk in the xz-plane and the normal along y.
+
+    texture: Rc<dyn Fn(f32, f32) -> Texture> // Texture map.
+                                              // Uses the angle around the center and the
+                                              // distance from the inner to the outer radius (from 0-1).
+}
+
+#[allow(dead_code)]
+impl Disk {
+    // Creates a new disk.
+    pub fn new<F>(center: Point3f, normal: Vector3f, radius: f32, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+        { Disk::annulus(center, normal, 0.0, radius, texture) }
+
+    // Creates a new disk of a solid color.
+    pub fn new_solid(center: Point3f, normal: Vector3f, radius: f32, texture: Texture) -> Self
+        { Disk::new(center, normal, radius, move |_, _| texture) }
+
+    // Creates a new annulus (a disk with a hole in the middle).
+    pub fn annulus<F>(center: Point3f, normal: Vector3f, inner_radius: f32, radius: f32, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+    {
+        Disk { radius, inner_radius, frame: LocalFrame::new(center, normal), texture: Rc::new(texture) }
+    }
+
+    // Creates a new annulus of a solid color.
+    pub fn annulus_solid(center: Point3f, normal: Vector3f, inner_radius: f32, radius: f32, texture: Texture) -> Self
+        { Disk::annulus(center, normal, inner_radius, radius, move |_, _| texture) }
+}
+
+impl Surface for Disk {
+    fn intersect(&self, ray: Ray) -> Option<f32> {
+        let local = self.frame.ray_to_local(ray);
+
+        if local.direction.y > -1e-3 { return None; }
+
+        let t = -local.origin.y / local.direction.y;
+        if t < 0.0 { return None; }
+
+        let p = local.project(t);
+        let rho2 = p.x * p.x + p.z * p.z;
+        if rho2 <= self.radius * self.radius && rho2 >= self.inner_radius * self.inner_radius { Some(t) }
+        else { None }
+    }
+
+    fn normal(&self, _point: Point3f) -> Unit3f { self.frame.axis() }
+
+    fn get_texture(&self, point: Point3f) -> Texture {
+        let (x, y) = self.uv(point);
+        (*self.texture)(x, y)
+    }
+
+    fn uv(&self, point: Point3f) -> (f32, f32) {
+        let p = self.frame.point_to_local(point);
+        let rho = (p.x * p.x + p.z * p.z).sqrt();
+        (0.5 + p.z.atan2(p.x) / (2.0 * PI), (rho - self.inner_radius) / (self.radius - self.inner_radius))
+    }
+
+    fn tessellate(&self, resolution: usize) -> Option<TriangleMesh> {
+        let mut tessellation = Tessellation::new();
+        tessellation.patch(resolution, 1, |s, t| {
+            let phi = 2.0 * PI * (s - 0.5);
+            let rho = self.inner_radius + t * (self.radius - self.inner_radius);
+            (self.frame.point_to_world(Point3f::new(rho * phi.cos(), 0.0, rho * phi.sin())), self.frame.axis().into_inner(), (s, t))
+        });
+        Some(tessellation.textured(share(&self.texture)))
+    }
+
+    fn bound(&self) -> Bound { Bound { center: self.frame.origin, radius: self.radius, bypass: false } }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::{Point3, Vector3};
+
+    #[test]
+    fn disk_intersect() {
+        let disk = Disk::new_solid(Point3::new(0.0, 1.0, 0.0), Vector3::y(), 2.0, Texture::white());
+
+        let ray = Ray::new(Point3::new(1.0, 5.0, 0.0), -Vector3::y());
+        let t = disk.intersect(ray).unwrap();
+        assert!((t - 4.0).abs() < 1e-4);
+        assert!((disk.normal(ray.project(t)).into_inner() - Vector3::y()).norm() < 1e-4);
+
+        // Outside the radius, and from behind.
+        assert_eq!(disk.intersect(Ray::new(Point3::new(3.0, 5.0, 0.0), -Vector3::y())), None);
+        assert_eq!(disk.intersect(Ray::new(Point3::new(1.0, -5.0, 0.0), Vector3::y())), None);
+
+        // An annulus has a hole in the middle.
+        let annulus = Disk::annulus_solid(Point3::new(0.0, 1.0, 0.0), Vector3::y(), 1.0, 2.0, Texture::white());
+        assert_eq!(annulus.intersect(Ray::new(Point3::new(0.5, 5.0, 0.0), -Vector3::y())), None);
+        assert!(annulus.intersect(Ray::new(Point3::new(1.5, 5.0, 0.0), -Vector3::y())).is_some());
+    }
+
+    #[test]
+    fn disk_uv() {
+        let annulus = Disk::annulus_solid(Point3::new(0.0, 1.0, 0.0), Vector3::y(), 1.0, 2.0, Texture::white());
+
+        // The distance from the inner to the outer radius goes from 0 to 1.
+        let (u1, v1) = annulus.uv(Point3::new(1.5, 1.0, 0.0));
+        let (u2, v2) = annulus.uv(Point3::new(-2.0, 1.0, 0.0));
+        assert!((v1 - 0.5).abs() < 1e-4 && (v2 - 1.0).abs() < 1e-4);
+        // Opposite sides are half a turn apart.
+        assert!(((u1 - u2).abs() - 0.5).abs() < 1e-4);
+    }
+}
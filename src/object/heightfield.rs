@@ -0,0 +1,291 @@
+extern crate nalgebra as na;
+
+use std::io;
+
+use na::*;
+
+use crate::image::Image;
+use crate::types::*;
+use super::{Surface, bound::*};
+
+// A terrain built from a regular grid of height samples. Every grid cell is
+// split into two triangles, and rays walk the cells they pass over (grid DDA),
+// skipping any cell whose heights they pass entirely above or below.
+pub struct Heightfield {
+    pub corner: Point3f, // Corner of the grid with the lowest x and z, at height zero.
+    pub size: Vector3f,  // Extent of the grid along x and z, and the scale of the heights.
+
+    width: usize, // Number of samples along x.
+    depth: usize, // Number of samples along z.
+    heights: Vec<f32>, // Row-major samples, with rows along x.
+    normals: Vec<Vector3f>, // Vertex normals, interpolated for smooth shading.
+    range: (f32, f32), // Lowest and highest sample.
+
+    texture: Box<dyn Fn(f32, f32) -> Texture> // Texture map.
+                                              // Spans 0-1 across the grid along x and z.
+}
+
+#[allow(dead_code)]
+impl Heightfield {
+    // Creates a new heightfield from width * depth samples. Returns None
+    // if the grid is smaller than 2 * 2 or the samples don't fill it.
+    pub fn new<F>(corner: Point3f, size: Vector3f, width: usize, depth: usize, heights: Vec<f32>, texture: F) -> Option<Self>
+        where F: 'static + Fn(f32, f32) -> Texture
+    {
+        if width < 2 || depth < 2 || heights.len() != width * depth { return None; }
+
+        let range = heights.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &h| (min.min(h), max.max(h)));
+        let mut heightfield = Heightfield {
+            corner, size, width, depth, heights,
+            normals: Vec::new(),
+            range,
+            texture: Box::new(texture)
+        };
+
+        heightfield.normals = (0..depth).flat_map(|j| (0..width).map(move |i| (i, j)))
+                                        .map(|(i, j)| heightfield.vertex_normal(i, j))
+                                        .collect();
+        Some(heightfield)
+    }
+
+    // Creates a new heightfield of a solid color.
+    pub fn new_solid(corner: Point3f, size: Vector3f, width: usize, depth: usize, heights: Vec<f32>, texture: Texture) -> Option<Self>
+        { Heightfield::new(corner, size, width, depth, heights, move |_, _| texture) }
+
+    // Creates a new heightfield from the luminance of an image, with its rows running along x.
+    // Images smaller than 2 * 2 pixels are rejected as invalid data.
+    pub fn from_image<F>(corner: Point3f, size: Vector3f, image: &Image, texture: F) -> io::Result<Self>
+        where F: 'static + Fn(f32, f32) -> Texture
+    {
+        let heights = image.pixels.iter().map(|color| color.luminance()).collect();
+        Heightfield::new(corner, size, image.width as usize, image.height as usize, heights, texture)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("heightfield image of {}x{} is smaller than 2x2", image.width, image.height)))
+    }
+
+    // Height of a sample, scaled into world space.
+    fn height(&self, i: usize, j: usize) -> f32 { self.heights[j * self.width + i] * self.size.y }
+
+    // Spacing between samples along x and z.
+    fn spacing(&self) -> (f32, f32) { (self.size.x / (self.width - 1) as f32, self.size.z / (self.depth - 1) as f32) }
+
+    // Normal at a sample, from the central differences of its neighbors.
+    fn vertex_normal(&self, i: usize, j: usize) -> Vector3f {
+        let (dx, dz) = self.spacing();
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.width - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.depth - 1));
+
+        let slope_x = (self.height(i1, j) - self.height(i0, j)) / ((i1 - i0) as f32 * dx);
+        let slope_z = (self.height(i, j1) - self.height(i, j0)) / ((j1 - j0) as f32 * dz);
+        Vector3::new(-slope_x, 1.0, -slope_z).normalize()
+    }
+
+    // Takes in a point and returns its position in grid coordinates,
+    // where samples lie at integers.
+    fn to_grid(&self, point: Point3f) -> (f32, f32) {
+        let (dx, dz) = self.spacing();
+        ((point.x - self.corner.x) / dx, (point.z - self.corner.z) / dz)
+    }
+
+    // Returns the cell containing a point in grid coordinates, and the position within it.
+    fn cell(&self, x: f32, z: f32) -> (usize, usize, f32, f32) {
+        let i = (x.floor().max(0.0) as usize).min(self.width - 2);
+        let j = (z.floor().max(0.0) as usize).min(self.depth - 2);
+        (i, j, x - i as f32, z - j as f32)
+    }
+
+    // Intersects the ray with the two triangles of a cell.
+    fn intersect_cell(&self, ray: Ray, i: usize, j: usize) -> Option<f32> {
+        let (dx, dz) = self.spacing();
+        let vertex = |i: usize, j: usize| Point3::new(self.corner.x + i as f32 * dx,
+                                                      self.corner.y + self.height(i, j),
+                                                      self.corner.z + j as f32 * dz);
+        let (p00, p10, p01, p11) = (vertex(i, j), vertex(i + 1, j), vertex(i, j + 1), vertex(i + 1, j + 1));
+
+        [(p00, p11, p10), (p00, p01, p11)].iter()
+            .filter_map(|&(a, b, c)| intersect_triangle(ray, a, b, c))
+            .fold(None, |min: Option<f32>, t| Some(min.map_or(t, |min| min.min(t))))
+    }
+}
+
+// Möller-Trumbore ray-triangle intersection, returning distances in front of the ray.
+fn intersect_triangle(ray: Ray, a: Point3f, b: Point3f, c: Point3f) -> Option<f32> {
+    let (e1, e2) = (b - a, c - a);
+    let p = ray.direction.cross(&e2);
+    let det = e1.dot(&p);
+    if det.abs() < 1e-12 { return None; }
+
+    let s = ray.origin - a;
+    let u = s.dot(&p) / det;
+    if !(0.0..=1.0).contains(&u) { return None; }
+
+    let q = s.cross(&e1);
+    let v = ray.direction.dot(&q) / det;
+    if v < 0.0 || u + v > 1.0 { return None; }
+
+    let t = e2.dot(&q) / det;
+    if t >= 0.0 { Some(t) } else { None }
+}
+
+impl Surface for Heightfield {
+    fn intersect(&self, ray: Ray) -> Option<f32> {
+        let (dx, dz) = self.spacing();
+        let (low, high) = (self.corner.y + self.range.0 * self.size.y, self.corner.y + self.range.1 * self.size.y);
+
+        // Clip the ray against the grid's bounding box.
+        let min = Point3::new(self.corner.x, low, self.corner.z);
+        let max = Point3::new(self.corner.x + self.size.x, high, self.corner.z + self.size.z);
+        let (mut t_enter, mut t_exit) = (0.0f32, f32::INFINITY);
+        for axis in 0..3 {
+            if ray.direction[axis].abs() < 1e-12 {
+                if ray.origin[axis] < min[axis] || ray.origin[axis] > max[axis] { return None; }
+                continue;
+            }
+            let t0 = (min[axis] - ray.origin[axis]) / ray.direction[axis];
+            let t1 = (max[axis] - ray.origin[axis]) / ray.direction[axis];
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+        if t_enter > t_exit { return None; }
+
+        // Walk the cells under the ray.
+        let (x, z) = self.to_grid(ray.project(t_enter));
+        let (mut i, mut j, fx, fz) = self.cell(x, z);
+
+        let (grid_dx, grid_dz) = (ray.direction.x / dx, ray.direction.z / dz);
+        let (step_i, step_j) = (grid_dx.signum() as isize, grid_dz.signum() as isize);
+        let delta_x = if grid_dx != 0.0 { 1.0 / grid_dx.abs() } else { f32::INFINITY };
+        let delta_z = if grid_dz != 0.0 { 1.0 / grid_dz.abs() } else { f32::INFINITY };
+        let next = |f: f32, grid_d: f32, delta: f32|
+            if grid_d > 0.0 { t_enter + (1.0 - f) * delta } else if grid_d < 0.0 { t_enter + f * delta } else { f32::INFINITY };
+        let mut next_x = next(fx, grid_dx, delta_x);
+        let mut next_z = next(fz, grid_dz, delta_z);
+        let mut t = t_enter;
+
+        loop {
+            let t_out = next_x.min(next_z).min(t_exit);
+
+            // Only test the cell if the ray passes through its range of heights.
+            let heights = [self.height(i, j), self.height(i + 1, j), self.height(i, j + 1), self.height(i + 1, j + 1)];
+            let cell_low = self.corner.y + heights.iter().cloned().fold(f32::INFINITY, f32::min);
+            let cell_high = self.corner.y + heights.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            let (y0, y1) = (ray.project(t).y, ray.project(t_out).y);
+            if y0.min(y1) <= cell_high && y0.max(y1) >= cell_low {
+                if let Some(hit) = self.intersect_cell(ray, i, j) { return Some(hit); }
+            }
+
+            if t_out >= t_exit { return None; }
+            t = t_out;
+            if next_x < next_z {
+                let next = i as isize + step_i;
+                if next < 0 || next > self.width as isize - 2 { return None; }
+                i = next as usize;
+                next_x += delta_x;
+            } else {
+                let next = j as isize + step_j;
+                if next < 0 || next > self.depth as isize - 2 { return None; }
+                j = next as usize;
+                next_z += delta_z;
+            }
+        }
+    }
+
+    // Bilinearly interpolates the vertex normals of the cell.
+    fn normal(&self, point: Point3f) -> Unit3f {
+        let (x, z) = self.to_grid(point);
+        let (i, j, fx, fz) = self.cell(x, z);
+        let (fx, fz) = (fx.clamp(0.0, 1.0), fz.clamp(0.0, 1.0));
+
+        let n = |i: usize, j: usize| self.normals[j * self.width + i];
+        Unit::new_normalize(n(i, j) * (1.0 - fx) * (1.0 - fz) + n(i + 1, j) * fx * (1.0 - fz)
+                          + n(i, j + 1) * (1.0 - fx) * fz + n(i + 1, j + 1) * fx * fz)
+    }
+
+    // The normal of the triangle the point lies on.
+    fn geometric_normal(&self, point: Point3f) -> Unit3f {
+        let (dx, dz) = self.spacing();
+        let (x, z) = self.to_grid(point);
+        let (i, j, fx, fz) = self.cell(x, z);
+
+        let h00 = self.height(i, j);
+        let h11 = self.height(i + 1, j + 1);
+        // The cell is split along the diagonal from (i, j) to (i + 1, j + 1).
+        let (slope_x, slope_z) = if fx >= fz { (self.height(i + 1, j) - h00, h11 - self.height(i + 1, j)) }
+                                 else { (h11 - self.height(i, j + 1), self.height(i, j + 1) - h00) };
+        Unit::new_normalize(Vector3::new(-slope_x / dx, 1.0, -slope_z / dz))
+    }
+
+    fn get_texture(&self, point: Point3f) -> Texture {
+        let (x, y) = self.uv(point);
+        (*self.texture)(x, y)
+    }
+
+    fn uv(&self, point: Point3f) -> (f32, f32) {
+        ((point.x - self.corner.x) / self.size.x, (point.z - self.corner.z) / self.size.z)
+    }
+
+    // The index of the grid cell.
+    fn primitive(&self, point: Point3f) -> usize {
+        let (x, z) = self.to_grid(point);
+        let (i, j, _, _) = self.cell(x, z);
+        j * (self.width - 1) + i
+    }
+
+    fn bound(&self) -> Bound {
+        let min = Vector3::new(0.0, self.range.0 * self.size.y, 0.0);
+        let max = Vector3::new(self.size.x, self.range.1 * self.size.y, self.size.z);
+        Bound { center: self.corner + 0.5 * (min + max), radius: 0.5 * (max - min).norm(), bypass: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heightfield_intersect() {
+        // A ramp rising along x, with a single spike in the middle.
+        let mut heights: Vec<f32> = (0..121).map(|k| (k % 11) as f32 / 10.0).collect();
+        heights[5 * 11 + 5] = 5.0;
+        let field = Heightfield::new_solid(Point3::origin(), Vector3::new(10.0, 1.0, 10.0), 11, 11, heights, Texture::white()).unwrap();
+
+        let ray = Ray::new(Point3::new(2.0, 5.0, 2.0), -Vector3::y());
+        let t = field.intersect(ray).unwrap();
+        assert!((t - 4.8).abs() < 1e-4);
+        let expected = Vector3::new(-0.1, 1.0, 0.0).normalize();
+        assert!((field.normal(ray.project(t)).into_inner() - expected).norm() < 1e-4);
+        assert_eq!(field.uv(ray.project(t)), (0.2, 0.2));
+
+        // A low ray skimming across the terrain runs into the spike.
+        let ray = Ray::new(Point3::new(-1.0, 2.0, 5.0), Vector3::x());
+        let t = field.intersect(ray).unwrap();
+        assert!(t > 5.0 && t < 6.0);
+
+        // Above the spike, it passes over everything.
+        assert_eq!(field.intersect(Ray::new(Point3::new(-1.0, 6.0, 5.0), Vector3::x())), None);
+    }
+
+    #[test]
+    fn heightfield_from_image() {
+        let path = std::env::temp_dir().join(format!("heightfield_from_image_{}.pgm", std::process::id()));
+        std::fs::write(&path, "P2\n# ramp\n3 2\n4\n0 2 4\n0 2 4\n").unwrap();
+        let image = Image::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let field = Heightfield::from_image(Point3::origin(), Vector3::new(2.0, 1.0, 1.0), &image, |_, _| Texture::white()).unwrap();
+        let t = field.intersect(Ray::new(Point3::new(1.5, 5.0, 0.5), -Vector3::y())).unwrap();
+        assert!((t - 4.25).abs() < 1e-4);
+
+        // A single row of samples has no cells to intersect.
+        let row = Image::new(3, 1);
+        let error = Heightfield::from_image(Point3::origin(), Vector3::new(2.0, 1.0, 1.0), &row, |_, _| Texture::white()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(Heightfield::new_solid(Point3::origin(), Vector3::new(1.0, 1.0, 1.0), 2, 2, vec![0.0; 3], Texture::white()).is_none());
+
+        // Sizes too large to address are rejected rather than wrapping around.
+        std::fs::write(&path, "P6\n4294967295 4294967295\n255\n").unwrap();
+        let error = Image::read(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,145 @@
+extern crate nalgebra as na;
+
+use std::rc::Rc;
+
+use na::*;
+
+use crate::types::*;
+use super::{Surface, TriangleMesh, bound::*};
+
+// A surface placed into the world by an affine transform, which may
+// translate, rotate, scale (non-uniformly) and shear it. The surface
+// itself is shared, so a mesh can be instanced many times without
+// duplicating its vertices.
+pub struct Instance {
+    pub surface: Rc<dyn Surface>,
+
+    transform: Affine3<f32>, // From object space to world space.
+    inverse: Affine3<f32>,   // From world space to object space.
+    normal_matrix: Matrix3<f32>, // Inverse transpose of the linear part, for transforming normals.
+    scale: f32 // The largest factor by which the transform stretches any vector.
+}
+
+#[allow(dead_code)]
+impl Instance {
+    // Creates a new instance of a surface. The transform has to be invertible.
+    pub fn new(surface: Rc<dyn Surface>, transform: Affine3<f32>) -> Self
+        { Instance::try_new(surface, transform).expect("instance transform is not invertible") }
+
+    // Creates a new instance of a surface, or nothing if the transform isn't
+    // invertible (such as one scaling by zero, which flattens the surface away).
+    pub fn try_new(surface: Rc<dyn Surface>, transform: Affine3<f32>) -> Option<Self> {
+        let inverse = transform.try_inverse()?;
+        let linear: Matrix3<f32> = transform.matrix().fixed_slice::<U3, U3>(0, 0).into();
+
+        Some(Instance {
+            surface,
+            transform,
+            inverse,
+            normal_matrix: inverse.matrix().fixed_slice::<U3, U3>(0, 0).transpose(),
+            scale: linear.svd(false, false).singular_values.max()
+        })
+    }
+
+    // Creates a new instance from a homogeneous matrix, whose last row is assumed to be (0, 0, 0, 1).
+    pub fn from_matrix(surface: Rc<dyn Surface>, matrix: Matrix4<f32>) -> Self
+        { Instance::new(surface, Affine3::from_matrix_unchecked(matrix)) }
+
+    pub fn transform(&self) -> &Affine3<f32> { &self.transform }
+
+    // Transforms a ray into object space. Also returns the length the
+    // ray's direction had there, which divides distances along it.
+    fn ray_to_local(&self, ray: Ray) -> (Ray, f32) {
+        let direction = self.inverse.transform_vector(&ray.direction);
+        let length = direction.norm();
+        (Ray::new(self.inverse.transform_point(&ray.origin), direction), length)
+    }
+
+    fn point_to_local(&self, point: Point3f) -> Point3f { self.inverse.transform_point(&point) }
+}
+
+impl Surface for Instance {
+    fn intersect(&self, ray: Ray) -> Option<f32> {
+        let (local, length) = self.ray_to_local(ray);
+        self.surface.intersect(local).map(|t| t / length)
+    }
+
+    fn normal(&self, point: Point3f) -> Unit3f {
+        Unit::new_normalize(self.normal_matrix * self.surface.normal(self.point_to_local(point)).into_inner())
+    }
+
+    fn geometric_normal(&self, point: Point3f) -> Unit3f {
+        Unit::new_normalize(self.normal_matrix * self.surface.geometric_normal(self.point_to_local(point)).into_inner())
+    }
+
+    fn get_texture(&self, point: Point3f) -> Texture { self.surface.get_texture(self.point_to_local(point)) }
+
+    fn uv(&self, point: Point3f) -> (f32, f32) { self.surface.uv(self.point_to_local(point)) }
+
+    fn primitive(&self, point: Point3f) -> usize { self.surface.primitive(self.point_to_local(point)) }
+
+    fn intervals(&self, ray: Ray) -> Vec<(f32, f32)> {
+        let (local, length) = self.ray_to_local(ray);
+        self.surface.intervals(local).into_iter().map(|(entry, exit)| (entry / length, exit / length)).collect()
+    }
+
+    // Only exact for transforms that scale uniformly, but good enough
+    // to tell which surface a point lies on.
+    fn distance(&self, point: Point3f) -> f32 { self.surface.distance(self.point_to_local(point)) * self.scale }
+
+    // Tessellates the surface and transforms the mesh. Mirroring
+    // transforms turn faces inside out, so their winding is reversed.
+    fn tessellate(&self, resolution: usize) -> Option<TriangleMesh> {
+        let mesh = self.surface.tessellate(resolution)?;
+        let mirrored = self.normal_matrix.determinant() < 0.0;
+
+        let vertices = mesh.vertices.iter().map(|p| self.transform.transform_point(p)).collect();
+        let normals = mesh.normals.iter().map(|n| Unit::new_normalize(self.normal_matrix * n.into_inner())).collect();
+        let faces = mesh.faces().into_iter().map(|face| if mirrored { face.reversed() } else { face }).collect();
+        Some(TriangleMesh::from_faces(vertices, normals, mesh.uvs, mesh.materials, faces).with_vertex_colors(mesh.colors))
+    }
+
+    fn bound(&self) -> Bound {
+        let bound = self.surface.bound();
+        if bound.bypass { return bound; }
+
+        Bound { center: self.transform.transform_point(&bound.center), radius: bound.radius * self.scale, bypass: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{Sphere, TriangleMesh};
+
+    #[test]
+    fn instance_ellipsoid() {
+        let sphere: Rc<dyn Surface> = Rc::new(Sphere::new_solid(0.0, 0.0, 0.0, 1.0, Texture::white()));
+        let ellipsoid = Instance::from_matrix(sphere, Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 1.0, 1.0)));
+
+        let t = ellipsoid.intersect(Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::x())).unwrap();
+        assert!((t - 3.0).abs() < 1e-4);
+        let t = ellipsoid.intersect(Ray::new(Point3::new(0.0, 5.0, 0.0), -Vector3::y())).unwrap();
+        assert!((t - 4.0).abs() < 1e-4);
+
+        // The normal follows the gradient of x^2 / 4 + y^2 = 1.
+        let point = Point3::new(2.0f32.sqrt(), 0.5f32.sqrt(), 0.0);
+        let expected = Vector3::new(point.x / 2.0, 2.0 * point.y, 0.0).normalize();
+        assert!((ellipsoid.normal(point).into_inner() - expected).norm() < 1e-4);
+
+        let bound = ellipsoid.bound();
+        assert!((bound.radius - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn instance_shared() {
+        let mesh: Rc<dyn Surface> = Rc::new(TriangleMesh::singleton_solid(Point3::new(-1.0, -1.0, 0.0), Point3::new(1.0, -1.0, 0.0),
+                                                                          Point3::new(0.0, 1.0, 0.0), Texture::white()));
+        let near = Instance::new(mesh.clone(), na::convert(Translation3::new(0.0, 0.0, 1.0)));
+        let far = Instance::new(mesh, na::convert(Translation3::new(0.0, 0.0, 3.0)));
+
+        let ray = Ray::new(Point3::origin(), Vector3::z());
+        assert!((near.intersect(ray).unwrap() - 1.0).abs() < 1e-4);
+        assert!((far.intersect(ray).unwrap() - 3.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,41 @@
+extern crate nalgebra as na;
+
+use std::f32::consts::PI;
+
+use na::*;
+
+use crate::types::*;
+
+// A local coordinate system for primitives that are defined
+// around an axis. The axis becomes the local y-axis.
+pub struct LocalFrame {
+    pub origin: Point3f,
+    pub rotation: UnitQuaternion<f32>
+}
+
+impl LocalFrame {
+    pub fn new(origin: Point3f, axis: Vector3f) -> Self {
+        let rotation = UnitQuaternion::rotation_between(&Vector3::y(), &axis)
+                                      .unwrap_or_else(|| UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI));
+        LocalFrame { origin, rotation }
+    }
+
+    pub fn axis(&self) -> Unit3f { self.rotation * Vector3::y_axis() }
+
+    pub fn point_to_local(&self, point: Point3f) -> Point3f {
+        Point3::from(self.rotation.inverse() * (point - self.origin))
+    }
+
+    pub fn point_to_world(&self, point: Point3f) -> Point3f {
+        self.origin + self.rotation * point.coords
+    }
+
+    // Since the frame is only rotated, distances along the ray are unchanged.
+    pub fn ray_to_local(&self, ray: Ray) -> Ray {
+        Ray::from_parts(self.point_to_local(ray.origin), self.rotation.inverse() * ray.direction)
+    }
+
+    pub fn normal_to_world(&self, normal: Vector3f) -> Unit3f {
+        self.rotation * Unit::new_normalize(normal)
+    }
+}
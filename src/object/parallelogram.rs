@@ -0,0 +1,120 @@
+extern crate nalgebra as na;
+
+use std::rc::Rc;
+
+use crate::types::*;
+use super::{Plane, Surface, Tessellation, TriangleMesh, share, bound::*};
+
+// A finite piece of a plane, spanned by two edges from a corner.
+// Uses the same axes as Plane, except that their lengths give the
+// extents of the surface. Like planes, parallelograms are one-sided.
+pub struct Parallelogram {
+    plane: Plane, // The plane it lies in, with its origin at the corner and
+                  // texture coordinates going from 0-1 along each edge.
+
+    x_axis: Vector3f, // First edge of the parallelogram.
+    y_axis: Vector3f  // Second edge of the parallelogram.
+}
+
+#[allow(dead_code)]
+impl Parallelogram {
+    // Creates a new parallelogram.
+    pub fn new<F>(corner: Point3f, x_axis: Vector3f, y_axis: Vector3f, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+        { Parallelogram { plane: Plane::spanned(corner, x_axis, y_axis, false, Rc::new(texture)), x_axis, y_axis } }
+
+    // Creates a new parallelogram with the normal flipped.
+    pub fn new_flip<F>(corner: Point3f, x_axis: Vector3f, y_axis: Vector3f, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+        { Parallelogram { plane: Plane::spanned(corner, x_axis, y_axis, true, Rc::new(texture)), x_axis, y_axis } }
+
+    // Creates a new parallelogram of a solid color.
+    pub fn new_solid(corner: Point3f, x_axis: Vector3f, y_axis: Vector3f, texture: Texture) -> Self
+        { Parallelogram::new(corner, x_axis, y_axis, move |_, _| texture) }
+
+    // Creates a new flipped parallelogram of a solid color.
+    pub fn new_solid_flip(corner: Point3f, x_axis: Vector3f, y_axis: Vector3f, texture: Texture) -> Self
+        { Parallelogram::new_flip(corner, x_axis, y_axis, move |_, _| texture) }
+
+    // Creates a new parallelogram around a center point rather than from a corner.
+    pub fn centered<F>(center: Point3f, x_axis: Vector3f, y_axis: Vector3f, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+        { Parallelogram::new(center - 0.5 * (x_axis + y_axis), x_axis, y_axis, texture) }
+
+    // Creates a new rectangle around a center point, with the given side lengths.
+    // The axes are normalized, and the y-axis is made perpendicular to the x-axis.
+    pub fn rectangle<F>(center: Point3f, x_axis: Vector3f, y_axis: Vector3f, width: f32, height: f32, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+    {
+        let x_axis = x_axis.normalize();
+        let y_axis = (y_axis - x_axis * x_axis.dot(&y_axis)).normalize();
+        Parallelogram::centered(center, x_axis * width, y_axis * height, texture)
+    }
+
+    // Creates a new rectangle of a solid color.
+    pub fn rectangle_solid(center: Point3f, x_axis: Vector3f, y_axis: Vector3f, width: f32, height: f32, texture: Texture) -> Self
+        { Parallelogram::rectangle(center, x_axis, y_axis, width, height, move |_, _| texture) }
+
+    pub fn corner(&self) -> Point3f { self.plane.center }
+    pub fn center(&self) -> Point3f { self.corner() + 0.5 * (self.x_axis + self.y_axis) }
+}
+
+impl Surface for Parallelogram {
+    fn intersect(&self, ray: Ray) -> Option<f32> {
+        let t = self.plane.intersect(ray)?;
+        let (x, y) = self.uv(ray.project(t));
+        if (0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y) { Some(t) }
+        else { None }
+    }
+
+    fn normal(&self, point: Point3f) -> Unit3f { self.plane.normal(point) }
+    fn get_texture(&self, point: Point3f) -> Texture { self.plane.get_texture(point) }
+    fn uv(&self, point: Point3f) -> (f32, f32) { self.plane.uv(point) }
+
+    // Being flat, a single quad covers it whatever the resolution.
+    fn tessellate(&self, _resolution: usize) -> Option<TriangleMesh> {
+        let mut tessellation = Tessellation::new();
+        let normal = *self.plane.normal;
+        tessellation.patch(1, 1, |s, t| (self.corner() + s * self.x_axis + t * self.y_axis, normal, (s, t)));
+        Some(tessellation.textured(share(self.plane.texture_map())))
+    }
+
+    fn bound(&self) -> Bound {
+        let radius = 0.5 * (self.x_axis + self.y_axis).norm().max((self.x_axis - self.y_axis).norm());
+        Bound { center: self.center(), radius, bypass: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::{Point3, Vector3};
+
+    #[test]
+    fn parallelogram_intersect() {
+        let rect = Parallelogram::rectangle_solid(Point3::origin(), Vector3::x(), Vector3::z(), 2.0, 1.0, Texture::white());
+
+        // The x- and z-axes give a normal pointing down.
+        let ray = Ray::new(Point3::new(0.9, -3.0, 0.4), Vector3::y());
+        let t = rect.intersect(ray).unwrap();
+        assert!((t - 3.0).abs() < 1e-5);
+        let (x, y) = rect.uv(ray.project(t));
+        assert!((x - 0.95).abs() < 1e-5 && (y - 0.9).abs() < 1e-5);
+
+        assert_eq!(rect.intersect(Ray::new(Point3::new(0.9, -3.0, 0.6), Vector3::y())), None);
+        assert_eq!(rect.intersect(Ray::new(Point3::new(0.0, 3.0, 0.0), -Vector3::y())), None);
+    }
+
+    #[test]
+    fn parallelogram_skewed_uv() {
+        let prlg = Parallelogram::new_solid(Point3::origin(), Vector3::new(2.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 1.0), Texture::white());
+        let (x, y) = prlg.uv(Point3::new(2.0, 0.0, 0.5));
+        assert!((x - 0.75).abs() < 1e-6 && (y - 0.5).abs() < 1e-6);
+
+        // Flipping only turns the normal around.
+        let flipped = Parallelogram::new_solid_flip(Point3::origin(), Vector3::new(2.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 1.0), Texture::white());
+        let (fx, fy) = flipped.uv(Point3::new(2.0, 0.0, 0.5));
+        assert!((fx - x).abs() < 1e-6 && (fy - y).abs() < 1e-6);
+        assert!((flipped.normal(Point3::origin()).into_inner() + prlg.normal(Point3::origin()).into_inner()).norm() < 1e-6);
+    }
+}
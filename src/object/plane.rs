@@ -1,5 +1,7 @@
 extern crate nalgebra as na;
 
+use std::rc::Rc;
+
 use na::*;
 use na::geometry::Point3;
 
@@ -13,38 +15,41 @@ pub struct Plane {
     x_axis: Vector3f, // Plane x-axis (The 3D direction that corresponds to the x-direction on the plane).
     y_axis: Vector3f, // Plane y-axis (The 3D direction that corresponds to the y-direction on the plane).
 
-    texture: Box<dyn Fn(f32, f32) -> Texture> // Texture map.
+    texture: Rc<dyn Fn(f32, f32) -> Texture> // Texture map.
                                               // Input coordinates are defined in terms of the axes above.
 }
 
 #[allow(dead_code)]
 impl Plane {
     // Creates a new plane.
-    pub fn new<F: 'static>(center: Point3f, x_axis: Vector3f, y_axis: Vector3f, texture: F) -> Self
-        where F: Fn(f32, f32) -> Texture
-    {
-        Plane {
-            center,
-            normal: Unit::new_normalize(x_axis.cross(&y_axis)),
-            x_axis: x_axis,
-            y_axis: y_axis,
-            texture: Box::new(texture)
-        }
-    }
+    pub fn new<F>(center: Point3f, x_axis: Vector3f, y_axis: Vector3f, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+        { Plane::from_parts(center, x_axis.cross(&y_axis), x_axis, y_axis, Rc::new(texture)) }
 
     // Creates a new plane with the normal flipped.
-    pub fn new_flip<F: 'static>(center: Point3f, x_axis: Vector3f, y_axis: Vector3f, texture: F) -> Self
-        where F: Fn(f32, f32) -> Texture
-    {
-        Plane {
-            center: center,
-            normal: Unit::new_normalize(y_axis.cross(&x_axis)),
-            x_axis: x_axis,
-            y_axis: y_axis,
-            texture: Box::new(texture)
-        }
+    pub fn new_flip<F>(center: Point3f, x_axis: Vector3f, y_axis: Vector3f, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+        { Plane::from_parts(center, y_axis.cross(&x_axis), x_axis, y_axis, Rc::new(texture)) }
+
+    // Creates a plane whose texture coordinates go from 0 at the origin to 1 at the end
+    // of each edge, even when the edges aren't perpendicular. The normal faces along
+    // x_edge cross y_edge, or the other way if flipped. Used for finite pieces of planes.
+    pub(super) fn spanned(origin: Point3f, x_edge: Vector3f, y_edge: Vector3f, flip: bool,
+                          texture: Rc<dyn Fn(f32, f32) -> Texture>) -> Self {
+        // The dual basis of the edges turns points into coordinates along them.
+        let normal = x_edge.cross(&y_edge);
+        let x_dual = y_edge.cross(&normal);
+        let y_dual = normal.cross(&x_edge);
+        Plane::from_parts(origin, if flip { -normal } else { normal },
+                          x_dual / x_edge.dot(&x_dual), y_dual / y_edge.dot(&y_dual), texture)
     }
 
+    fn from_parts(center: Point3f, normal: Vector3f, x_axis: Vector3f, y_axis: Vector3f, texture: Rc<dyn Fn(f32, f32) -> Texture>) -> Self {
+        Plane { center, normal: Unit::new_normalize(normal), x_axis, y_axis, texture }
+    }
+
+    pub(super) fn texture_map(&self) -> &Rc<dyn Fn(f32, f32) -> Texture> { &self.texture }
+
     // Creates a new plane of a solid color.
     pub fn new_solid(center: Point3f, x_axis: Vector3f, y_axis: Vector3f, texture: Texture) -> Self
         { Plane::new(center, x_axis, y_axis, move |_, _| texture) }
@@ -78,15 +83,36 @@ impl Surface for Plane {
     fn normal(&self, _point: Point3f) -> Unit3f { self.normal }
 
     fn get_texture(&self, point: Point3f) -> Texture {
+        let (x, y) = self.uv(point);
+        (*self.texture)(x, y)
+    }
+
+    fn uv(&self, point: Point3f) -> (f32, f32) {
         let rel_pos = point - self.center;
         let proj_point3 = rel_pos - (*self.normal * self.normal.dot(&rel_pos));
 
         let x = proj_point3.dot(&self.x_axis);
         let y = proj_point3.dot(&self.y_axis);
 
-        (*self.texture)(x, y)
+        (x, y)
+    }
+
+    // A plane bounds the half-space behind its normal.
+    fn intervals(&self, ray: Ray) -> Vec<(f32, f32)> {
+        let d = self.normal.dot(&ray.direction);
+        let s = self.normal.dot(&(ray.origin - self.center));
+
+        if d.abs() < 1e-8 {
+            if s < 0.0 { vec![(f32::NEG_INFINITY, f32::INFINITY)] } else { Vec::new() }
+        } else if d < 0.0 {
+            vec![(-s / d, f32::INFINITY)]
+        } else {
+            vec![(f32::NEG_INFINITY, -s / d)]
+        }
     }
 
+    fn distance(&self, point: Point3f) -> f32 { self.normal.dot(&(point - self.center)).abs() }
+
     // Planes are infinite, so no finite
     // bounding sphere could possibly contain one.
     fn bound(&self) -> Bound { Bound::bypass() }
@@ -16,10 +16,16 @@ impl PointLight {
     pub fn new(pos: Point3f, color: Color, intensity: f32) -> PointLight {
         PointLight { pos, color, intensity }
     }
+
+    // Creates a point light colored as a blackbody
+    // radiator at the given temperature in Kelvin.
+    pub fn blackbody(pos: Point3f, kelvin: f32, intensity: f32) -> PointLight {
+        PointLight::new(pos, Color::blackbody(kelvin), intensity)
+    }
 }
 
 impl Light for PointLight {
-    fn check_shadow(&self, point: Point3f, objects: &Vec<Object>) -> bool {
+    fn check_shadow(&self, point: Point3f, objects: &[Object]) -> bool {
         let max_d = distance(&self.pos, &point);
         objects.iter()
                .filter_map(|obj| obj.intersect(Ray::from_points(self.pos, point)))
@@ -33,6 +39,10 @@ impl Light for PointLight {
     fn direction(&self, point: Point3f) -> Unit3f {
         Unit::new_normalize(self.pos - point)
     }
+
+    fn transformed(&self, transform: &Affine3<f32>) -> Box<dyn Light> {
+        Box::new(PointLight::new(transform.transform_point(&self.pos), self.color, self.intensity))
+    }
 }
 
 #[cfg(test)]
@@ -45,6 +55,6 @@ mod tests {
         let block = Object::new(Sphere::new_solid(0.0, 0.5, 0.0, 0.1, Texture::new(0.0, 0.0, 0.0, 0.0)));
 
         assert!(light.check_shadow(Point3::origin(), &Vec::new()));
-        assert!(!light.check_shadow(Point3::origin(), &vec![block]));
+        assert!(!light.check_shadow(Point3::origin(), &[block]));
     }
 }
@@ -0,0 +1,133 @@
+// Polynomial root finding for the analytic primitives.
+// All solvers return the real roots in ascending order, leaving out
+// any that aren't finite (from NaN or infinite coefficients).
+
+// Solves a x^2 + b x + c = 0.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < 1e-12 {
+        return if b.abs() < 1e-12 { Vec::new() } else { vec![-c / b] };
+    }
+
+    let discr = b * b - 4.0 * a * c;
+    if discr < 0.0 { return Vec::new(); }
+
+    // Avoids cancellation between -b and the square root.
+    let q = -0.5 * (b + b.signum() * discr.sqrt());
+    let mut roots = if q == 0.0 { vec![0.0, 0.0] } else { vec![q / a, c / q] };
+    roots.retain(|x| x.is_finite());
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+// Solves x^3 + a x^2 + b x + c = 0.
+pub fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // Substitute x = y - a/3 to get y^3 + p y + q = 0.
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let shift = -a / 3.0;
+
+    let discr = q * q / 4.0 + p * p * p / 27.0;
+    let mut roots = if discr > 0.0 {
+        let s = discr.sqrt();
+        vec![(-q / 2.0 + s).cbrt() + (-q / 2.0 - s).cbrt()]
+    } else if p.abs() < 1e-12 {
+        vec![0.0]
+    } else {
+        // Three real roots, found with the trigonometric method.
+        let r = (-p / 3.0).sqrt();
+        let phi = (-q / (2.0 * r * r * r)).clamp(-1.0, 1.0).acos();
+        (0..3).map(|k| 2.0 * r * ((phi + 2.0 * std::f64::consts::PI * k as f64) / 3.0).cos()).collect()
+    };
+
+    for root in roots.iter_mut() { *root += shift; }
+    roots.retain(|x| x.is_finite());
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+// Solves x^4 + a x^3 + b x^2 + c x + d = 0 with Ferrari's method,
+// polishing the roots with a few Newton steps for robustness.
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // Substitute x = y - a/4 to get y^4 + p y^2 + q y + r = 0.
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = c - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * c / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+    let shift = -a / 4.0;
+
+    let mut roots = if q.abs() < 1e-12 {
+        // Biquadratic: solve for y^2.
+        solve_quadratic(1.0, p, r).into_iter()
+            .filter(|&z| z >= 0.0)
+            .flat_map(|z| vec![-z.sqrt(), z.sqrt()])
+            .collect()
+    } else {
+        // Find a positive root of the resolvent cubic, which
+        // splits the quartic into two quadratics.
+        let z = solve_cubic(2.0 * p, p * p - 4.0 * r, -q * q).into_iter().fold(0.0, f64::max);
+        if z <= 0.0 { return Vec::new(); }
+
+        let s = z.sqrt();
+        let mut roots = solve_quadratic(1.0,  s, (p + z) / 2.0 - q / (2.0 * s));
+        roots.extend(solve_quadratic(1.0, -s, (p + z) / 2.0 + q / (2.0 * s)));
+        roots
+    };
+
+    for root in roots.iter_mut() {
+        *root += shift;
+        for _ in 0..4 {
+            let x = *root;
+            let f = (((x + a) * x + b) * x + c) * x + d;
+            let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+            if df.abs() < 1e-12 { break; }
+            *root = x - f / df;
+        }
+    }
+    roots.retain(|x| x.is_finite());
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(roots: Vec<f64>, expected: &[f64]) {
+        assert_eq!(roots.len(), expected.len());
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-6, "{} != {}", root, expected);
+        }
+    }
+
+    #[test]
+    fn quadratic() {
+        check(solve_quadratic(1.0, -3.0, 2.0), &[1.0, 2.0]);
+        check(solve_quadratic(1.0, 0.0, 1.0), &[]);
+    }
+
+    #[test]
+    fn cubic() {
+        // (x - 1)(x - 2)(x - 3)
+        check(solve_cubic(-6.0, 11.0, -6.0), &[1.0, 2.0, 3.0]);
+        // (x - 2)(x^2 + 1)
+        check(solve_cubic(-2.0, 1.0, -2.0), &[2.0]);
+    }
+
+    #[test]
+    fn quartic() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        check(solve_quartic(-10.0, 35.0, -50.0, 24.0), &[1.0, 2.0, 3.0, 4.0]);
+        // (x^2 - 1)(x^2 - 4)
+        check(solve_quartic(0.0, -5.0, 0.0, 4.0), &[-2.0, -1.0, 1.0, 2.0]);
+        // (x^2 + 1)(x^2 + 4)
+        check(solve_quartic(0.0, 5.0, 0.0, 4.0), &[]);
+    }
+
+    #[test]
+    fn non_finite() {
+        // Such as from a ray with a NaN direction.
+        check(solve_quadratic(f64::NAN, 1.0, 1.0), &[]);
+        check(solve_cubic(f64::NAN, 1.0, 1.0), &[]);
+        check(solve_quartic(1.0, f64::NAN, 1.0, 1.0), &[]);
+    }
+}
@@ -0,0 +1,204 @@
+extern crate nalgebra as na;
+
+use na::*;
+
+use crate::types::*;
+use super::{Surface, bound::*};
+
+mod node; #[allow(unused_imports)] pub use node::*;
+
+// A surface defined implicitly by a signed distance function, which is
+// negative inside the object, positive outside and zero on its surface.
+// Rays are sphere-traced: since nothing lies closer than the distance
+// function says, the ray can safely advance by that much at every step.
+pub struct Sdf {
+    pub max_steps: u32, // Number of steps after which tracing gives up.
+    pub epsilon: f32,   // Distance to the surface at which it counts as hit.
+    pub step_scale: f32, // Factor on every step, to be lowered for functions that overestimate the distance.
+    pub max_distance: f32, // How far to trace when the bound is bypassed.
+
+    distance: Box<dyn Fn(Point3f) -> f32>, // Signed distance function.
+    bound: Bound, // The object is assumed to lie entirely inside.
+
+    texture: Box<dyn Fn(Point3f) -> Texture> // Texture map.
+                                             // Uses the point on the surface as input.
+}
+
+#[allow(dead_code)]
+impl Sdf {
+    // Creates a new surface from a distance function and a bounding sphere around it.
+    pub fn new<D, F>(distance: D, bound: Bound, texture: F) -> Self
+        where D: 'static + Fn(Point3f) -> f32, F: 'static + Fn(Point3f) -> Texture
+    {
+        Sdf {
+            max_steps: 512,
+            epsilon: 1e-4,
+            step_scale: 1.0,
+            max_distance: 1e3,
+            distance: Box::new(distance),
+            bound,
+            texture: Box::new(texture)
+        }
+    }
+
+    // Creates a new surface of a solid color.
+    pub fn new_solid<D>(distance: D, bound: Bound, texture: Texture) -> Self
+        where D: 'static + Fn(Point3f) -> f32
+        { Sdf::new(distance, bound, move |_| texture) }
+
+    // Creates a new surface from a tree of primitives and operators.
+    pub fn from_node<F>(node: SdfNode, bound: Bound, texture: F) -> Self
+        where F: 'static + Fn(Point3f) -> Texture
+        { Sdf::new(move |point| node.distance(point), bound, texture) }
+
+    // Creates a new surface from a tree of primitives and operators, of a solid color.
+    pub fn from_node_solid(node: SdfNode, bound: Bound, texture: Texture) -> Self
+        { Sdf::from_node(node, bound, move |_| texture) }
+
+    pub fn signed_distance(&self, point: Point3f) -> f32 { (*self.distance)(point) }
+
+    // Returns the range of distances along the ray's line that lie inside the bound.
+    fn range(&self, ray: Ray) -> Option<(f32, f32)> {
+        if self.bound.bypass { return Some((-self.max_distance, self.max_distance)); }
+
+        let l = ray.origin - self.bound.center;
+        let b = l.dot(&ray.direction);
+        let discr = b * b - l.norm_squared() + self.bound.radius * self.bound.radius;
+        if discr < 0.0 { return None; }
+
+        Some((-b - discr.sqrt(), -b + discr.sqrt()))
+    }
+
+    // Sphere-traces the ray's line from start to end and returns the distances at
+    // which it crosses the surface. Grazing hits, after which the ray stays on the
+    // same side, are left out. If only the first hit is wanted, it is returned
+    // without checking that it is a crossing.
+    fn trace(&self, ray: Ray, start: f32, end: f32, first: bool) -> Vec<f32> {
+        let mut hits = Vec::new();
+        let mut t = start;
+        let mut inside = self.signed_distance(ray.project(t)) < 0.0;
+        let mut shell = None; // Where the ray entered the thin shell around the surface.
+
+        for _ in 0..self.max_steps {
+            if t > end { break; }
+            let d = self.signed_distance(ray.project(t));
+
+            match shell {
+                None if d.abs() < self.epsilon => {
+                    if first { return vec![t]; }
+                    shell = Some(t);
+                    t += self.epsilon;
+                },
+                // Step through the shell to find out which side it is left on.
+                Some(_) if d.abs() < self.epsilon => t += self.epsilon,
+                Some(hit) => {
+                    if (d < 0.0) != inside {
+                        hits.push(hit);
+                        inside = !inside;
+                    }
+                    shell = None;
+                    t += d.abs() * self.step_scale;
+                },
+                None => t += d.abs() * self.step_scale
+            }
+        }
+
+        if inside && !first { hits.push(end); }
+        hits
+    }
+}
+
+impl Surface for Sdf {
+    fn intersect(&self, ray: Ray) -> Option<f32> {
+        let (start, end) = self.range(ray)?;
+        if end < 0.0 { return None; }
+
+        self.trace(ray, start.max(0.0), end, true).first().cloned()
+    }
+
+    // The normal is the gradient of the distance function,
+    // estimated by central differences.
+    fn normal(&self, point: Point3f) -> Unit3f {
+        let h = 10.0 * self.epsilon;
+        let gradient = Vector3::from_fn(|axis, _| {
+            let mut offset = Vector3::zeros();
+            offset[axis] = h;
+            self.signed_distance(point + offset) - self.signed_distance(point - offset)
+        });
+        Unit::new_normalize(gradient)
+    }
+
+    fn get_texture(&self, point: Point3f) -> Texture { (*self.texture)(point) }
+
+    fn intervals(&self, ray: Ray) -> Vec<(f32, f32)> {
+        let (start, end) = match self.range(ray) { Some(range) => range, None => return Vec::new() };
+
+        let mut hits = self.trace(ray, start, end, false);
+        // The ray may start inside when the bound is bypassed.
+        if self.signed_distance(ray.project(start)) < 0.0 { hits.insert(0, start); }
+
+        hits.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect()
+    }
+
+    fn distance(&self, point: Point3f) -> f32 { self.signed_distance(point).abs() }
+
+    fn bound(&self) -> Bound { self.bound }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sdf_sphere_trace() {
+        let bound = Bound { center: Point3::origin(), radius: 1.1, bypass: false };
+        let sphere = Sdf::new_solid(|p: Point3f| p.coords.norm() - 1.0, bound, Texture::white());
+
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::x());
+        let t = sphere.intersect(ray).unwrap();
+        assert!((t - 4.0).abs() < 1e-3);
+        assert!((sphere.normal(ray.project(t)).into_inner() + Vector3::x()).norm() < 1e-3);
+
+        let intervals = sphere.intervals(ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].1 - 6.0).abs() < 1e-3);
+
+        assert_eq!(sphere.intersect(Ray::new(Point3::new(-5.0, 2.0, 0.0), Vector3::x())), None);
+    }
+
+    #[test]
+    fn sdf_tree() {
+        // Two spheres blended together, and repeated along x.
+        let node = SdfNode::sphere(0.5).translate(Vector3::new(-0.4, 0.0, 0.0))
+                    .smooth_union(SdfNode::sphere(0.5).translate(Vector3::new(0.4, 0.0, 0.0)), 0.2)
+                    .repeat(Vector3::new(4.0, 0.0, 0.0));
+
+        // The blend fills in the gap between the spheres...
+        assert!(node.distance(Point3::new(0.0, 0.3, 0.0)) < 0.0);
+        // ...and the copies 4 units apart match.
+        assert!((node.distance(Point3::new(0.9, 0.0, 0.0)) - node.distance(Point3::new(4.9, 0.0, 0.0))).abs() < 1e-5);
+
+        let rounded = Sdf::from_node_solid(SdfNode::round_box(Vector3::new(1.0, 1.0, 1.0), 0.25),
+                                           Bound { center: Point3::origin(), radius: 2.0, bypass: false },
+                                           Texture::white());
+
+        // Straight onto a face the box is unchanged, but its corner is rounded off.
+        let t = rounded.intersect(Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::x())).unwrap();
+        assert!((t - 4.0).abs() < 1e-3);
+        let diagonal = Ray::new(Point3::new(-5.0, -5.0, -5.0), Vector3::new(1.0, 1.0, 1.0));
+        let t = rounded.intersect(diagonal).unwrap();
+        assert!(t > 75.0f32.sqrt() - 3.0f32.sqrt() + 0.05);
+    }
+
+    #[test]
+    fn sdf_mandelbulb() {
+        let bulb = Sdf::from_node_solid(SdfNode::mandelbulb(8.0, 12), Bound { center: Point3::origin(), radius: 1.5, bypass: false },
+                                        Texture::white());
+
+        let ray = Ray::new(Point3::new(-3.0, 0.1, 0.2), Vector3::x());
+        let t = bulb.intersect(ray).unwrap();
+        // The bulb lies within about 1.2 of its center.
+        assert!(t > 1.8 && t < 2.5);
+        assert!(bulb.normal(ray.project(t)).x < 0.0);
+    }
+}
@@ -0,0 +1,117 @@
+extern crate nalgebra as na;
+
+use na::*;
+
+use crate::types::*;
+
+// A tree of signed distance primitives and the operators combining them.
+// Primitives are centered at the origin; use translate() and rotate() to place them.
+pub enum SdfNode {
+    Sphere(f32),                 // Radius.
+    Cuboid(Vector3f),            // Half of the extent along each axis.
+    RoundBox(Vector3f, f32),     // Half extents and the radius of the rounded edges.
+    Torus(f32, f32),             // Major and minor radius, lying in the xz-plane.
+    Cylinder(f32, f32),          // Radius and half height, along the y-axis.
+    Mandelbulb(f32, u32),        // Power and number of iterations.
+
+    Translate(Vector3f, Box<SdfNode>),
+    Rotate(UnitQuaternion<f32>, Box<SdfNode>),
+    Scale(f32, Box<SdfNode>),
+    Round(f32, Box<SdfNode>),    // Grows the shape and rounds its edges.
+    Repeat(Vector3f, Box<SdfNode>), // Repeats the shape with a period along each axis (0 for none).
+
+    Union(Box<SdfNode>, Box<SdfNode>),
+    Intersection(Box<SdfNode>, Box<SdfNode>),
+    Difference(Box<SdfNode>, Box<SdfNode>),
+    SmoothUnion(Box<SdfNode>, Box<SdfNode>, f32), // The last value is the size of the blend.
+    SmoothIntersection(Box<SdfNode>, Box<SdfNode>, f32),
+    SmoothDifference(Box<SdfNode>, Box<SdfNode>, f32)
+}
+
+// Distance to a box, which is also exact outside of it.
+fn cuboid(p: Vector3f, half_size: Vector3f) -> f32 {
+    let q = p.abs() - half_size;
+    q.map(|x| x.max(0.0)).norm() + q.max().min(0.0)
+}
+
+// Polynomial smooth minimum (Quilez), blending over a distance of k.
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+// Distance estimate for the Mandelbulb fractal, from the
+// derivative of the iterated function (see Hart et al., 1989).
+fn mandelbulb(p: Vector3f, power: f32, iterations: u32) -> f32 {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = z.norm();
+
+    for _ in 0..iterations {
+        if r > 2.0 { break; }
+
+        let theta = if r > 0.0 { (z.z / r).clamp(-1.0, 1.0).acos() } else { 0.0 } * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+
+        z = r.powf(power) * Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) + p;
+        r = z.norm();
+    }
+
+    0.5 * r.max(1e-6).ln() * r / dr
+}
+
+#[allow(dead_code)]
+impl SdfNode {
+    pub fn sphere(radius: f32) -> Self { SdfNode::Sphere(radius) }
+    pub fn cuboid(half_size: Vector3f) -> Self { SdfNode::Cuboid(half_size) }
+    pub fn round_box(half_size: Vector3f, radius: f32) -> Self { SdfNode::RoundBox(half_size, radius) }
+    pub fn torus(major_radius: f32, minor_radius: f32) -> Self { SdfNode::Torus(major_radius, minor_radius) }
+    pub fn cylinder(radius: f32, half_height: f32) -> Self { SdfNode::Cylinder(radius, half_height) }
+    pub fn mandelbulb(power: f32, iterations: u32) -> Self { SdfNode::Mandelbulb(power, iterations) }
+
+    pub fn translate(self, offset: Vector3f) -> Self { SdfNode::Translate(offset, Box::new(self)) }
+    pub fn rotate(self, rotation: UnitQuaternion<f32>) -> Self { SdfNode::Rotate(rotation, Box::new(self)) }
+    pub fn scale(self, factor: f32) -> Self { SdfNode::Scale(factor, Box::new(self)) }
+    pub fn round(self, radius: f32) -> Self { SdfNode::Round(radius, Box::new(self)) }
+    pub fn repeat(self, period: Vector3f) -> Self { SdfNode::Repeat(period, Box::new(self)) }
+
+    pub fn union(self, other: SdfNode) -> Self { SdfNode::Union(Box::new(self), Box::new(other)) }
+    pub fn intersection(self, other: SdfNode) -> Self { SdfNode::Intersection(Box::new(self), Box::new(other)) }
+    pub fn difference(self, other: SdfNode) -> Self { SdfNode::Difference(Box::new(self), Box::new(other)) }
+    pub fn smooth_union(self, other: SdfNode, k: f32) -> Self { SdfNode::SmoothUnion(Box::new(self), Box::new(other), k) }
+    pub fn smooth_intersection(self, other: SdfNode, k: f32) -> Self { SdfNode::SmoothIntersection(Box::new(self), Box::new(other), k) }
+    pub fn smooth_difference(self, other: SdfNode, k: f32) -> Self { SdfNode::SmoothDifference(Box::new(self), Box::new(other), k) }
+
+    // Evaluates the signed distance from the point to the shape.
+    pub fn distance(&self, point: Point3f) -> f32 {
+        let p = point.coords;
+        match self {
+            SdfNode::Sphere(radius) => p.norm() - radius,
+            SdfNode::Cuboid(half_size) => cuboid(p, *half_size),
+            SdfNode::RoundBox(half_size, radius) => cuboid(p, half_size.map(|x| x - radius)) - radius,
+            SdfNode::Torus(major, minor) => Vector2::new(Vector2::new(p.x, p.z).norm() - major, p.y).norm() - minor,
+            SdfNode::Cylinder(radius, half_height) => {
+                let d = Vector2::new(Vector2::new(p.x, p.z).norm() - radius, p.y.abs() - half_height);
+                d.map(|x| x.max(0.0)).norm() + d.max().min(0.0)
+            },
+            SdfNode::Mandelbulb(power, iterations) => mandelbulb(p, *power, *iterations),
+
+            SdfNode::Translate(offset, node) => node.distance(point - offset),
+            SdfNode::Rotate(rotation, node) => node.distance(rotation.inverse() * point),
+            SdfNode::Scale(factor, node) => node.distance(Point3::from(p / *factor)) * factor,
+            SdfNode::Round(radius, node) => node.distance(point) - radius,
+            SdfNode::Repeat(period, node) => {
+                let q = Vector3::from_fn(|i, _| if period[i] > 0.0 { p[i] - period[i] * (p[i] / period[i]).round() } else { p[i] });
+                node.distance(Point3::from(q))
+            },
+
+            SdfNode::Union(a, b) => a.distance(point).min(b.distance(point)),
+            SdfNode::Intersection(a, b) => a.distance(point).max(b.distance(point)),
+            SdfNode::Difference(a, b) => a.distance(point).max(-b.distance(point)),
+            SdfNode::SmoothUnion(a, b, k) => smooth_min(a.distance(point), b.distance(point), *k),
+            SdfNode::SmoothIntersection(a, b, k) => -smooth_min(-a.distance(point), -b.distance(point), *k),
+            SdfNode::SmoothDifference(a, b, k) => -smooth_min(-a.distance(point), b.distance(point), *k)
+        }
+    }
+}
@@ -1,40 +1,40 @@
 extern crate nalgebra as na;
 
 use std::f32::consts::PI;
+use std::rc::Rc;
 
 use na::*;
 use na::geometry::Point3;
 
 use crate::types::*;
-use super::{Surface, bound::*};
+use super::{Surface, Tessellation, TriangleMesh, share, bound::*};
 
 pub struct Sphere {
     pub center: Point3f, // Center point of the sphere.
     pub radius: f32,         // Radius of the sphere.
 
-    texture: Box<dyn Fn(f32, f32) -> Texture> // Texture map.
+    texture: Rc<dyn Fn(f32, f32) -> Texture> // Texture map.
                                               // Uses spherical coordinates (normalized from 0-1) as input.
 }
 
 #[allow(dead_code)]
 impl Sphere {
     // Creates a new sphere.
-    pub fn new<F: 'static>(x: f32, y: f32, z: f32, radius: f32, texture: F) -> Self
-        where F: Fn(f32, f32) -> Texture
+    pub fn new<F>(x: f32, y: f32, z: f32, radius: f32, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
     {
         Sphere {
             center: Point3::new(x, y, z), radius,
-            texture: Box::new(texture)
+            texture: Rc::new(texture)
         }
     }
 
     // Creates a new sphere of a solid color.
     pub fn new_solid(x: f32, y: f32, z: f32, radius: f32, texture: Texture) -> Self
         { Sphere::new(x, y, z, radius, move |_, _| texture) }
-}
 
-impl Surface for Sphere {
-    fn intersect(&self, ray: Ray) -> Option<f32> {
+    // Returns both distances at which the ray's line crosses the sphere, in order.
+    fn roots(&self, ray: Ray) -> Option<(f32, f32)> {
         fn solve_quadratic(b: f32, c: f32) -> Option<(f32, f32)> {
             let discr = b * b - 4.0 * c;
 
@@ -56,6 +56,14 @@ impl Surface for Sphere {
 
         if t0 > t1 { std::mem::swap(&mut t0, &mut t1); }
 
+        Some((t0, t1))
+    }
+}
+
+impl Surface for Sphere {
+    fn intersect(&self, ray: Ray) -> Option<f32> {
+        let (t0, t1) = self.roots(ray)?;
+
         if t0 >= 0.0 { Some(t0) }
         else if t1 >= 0.0 { Some(t1) }
         else { None }
@@ -66,6 +74,11 @@ impl Surface for Sphere {
     }
 
     fn get_texture(&self, point: Point3f) -> Texture {
+        let (x, y) = self.uv(point);
+        (*self.texture)(x, y)
+    }
+
+    fn uv(&self, point: Point3f) -> (f32, f32) {
         let normal = self.normal(point);
 
         // In this particular case, the normal is similar to a point on a unit sphere
@@ -74,7 +87,22 @@ impl Surface for Sphere {
         let x = 0.5 + normal.z.atan2(normal.x) / (2.0 * PI);
         let y = normal.y.acos() / PI;
 
-        (*self.texture)(x, y)
+        (x, y)
+    }
+
+    fn intervals(&self, ray: Ray) -> Vec<(f32, f32)> { self.roots(ray).into_iter().collect() }
+
+    fn distance(&self, point: Point3f) -> f32 { (distance(&point, &self.center) - self.radius).abs() }
+
+    // Longitude runs along the columns and latitude along the rows, as in the texture coordinates.
+    fn tessellate(&self, resolution: usize) -> Option<TriangleMesh> {
+        let mut tessellation = Tessellation::new();
+        tessellation.patch(resolution, resolution / 2, |s, t| {
+            let (phi, theta) = (2.0 * PI * (s - 0.5), PI * t);
+            let normal = Vector3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+            (self.center + self.radius * normal, normal, (s, t))
+        });
+        Some(tessellation.textured(share(&self.texture)))
     }
 
     fn bound(&self) -> Bound { Bound { center: self.center, radius: self.radius, bypass: false } }
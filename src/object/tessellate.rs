@@ -0,0 +1,154 @@
+extern crate nalgebra as na;
+
+use std::rc::Rc;
+
+use na::*;
+
+use crate::types::*;
+use super::{Face, Material, TriangleMesh};
+
+// Builds up a triangle mesh out of parametric patches,
+// used to tessellate analytic surfaces.
+#[derive(Default)]
+pub struct Tessellation {
+    vertices: Vec<Point3f>,
+    normals: Vec<Unit3f>,
+    uvs: Vec<(f32, f32)>,
+    faces: Vec<Face>
+}
+
+#[allow(dead_code)]
+impl Tessellation {
+    pub fn new() -> Self { Tessellation::default() }
+
+    // Adds a grid of (columns x rows) quads over the parameters (s, t) in 0-1, each split into two triangles.
+    // The function maps the parameters to a point, its normal and its texture coordinates.
+    // Triangles are wound to face along the normals, and those that collapse (such as at a pole) are left out.
+    pub fn patch(&mut self, columns: usize, rows: usize, f: impl Fn(f32, f32) -> (Point3f, Vector3f, (f32, f32))) -> &mut Self {
+        let (columns, rows) = (columns.max(1), rows.max(1));
+        let start = self.vertices.len();
+
+        for j in 0..=rows {
+            for i in 0..=columns {
+                let (point, normal, uv) = f(i as f32 / columns as f32, j as f32 / rows as f32);
+                self.vertices.push(point);
+                self.normals.push(Unit::new_normalize(normal));
+                self.uvs.push(uv);
+            }
+        }
+
+        let index = |i: usize, j: usize| start + j * (columns + 1) + i;
+        for j in 0..rows {
+            for i in 0..columns {
+                let (a, b, c, d) = (index(i, j), index(i + 1, j), index(i + 1, j + 1), index(i, j + 1));
+                for &(v1, v2, v3) in &[(a, b, c), (a, c, d)] {
+                    let cross = (self.vertices[v2] - self.vertices[v1]).cross(&(self.vertices[v3] - self.vertices[v1]));
+                    if cross.norm_squared() < 1e-12 { continue; }
+
+                    let normal = *self.normals[v1] + *self.normals[v2] + *self.normals[v3];
+                    let vertices = if cross.dot(&normal) < 0.0 { (v1, v3, v2) } else { (v1, v2, v3) };
+                    self.faces.push(Face { vertices, normals: Some(vertices), uvs: Some(vertices), material: 0 });
+                }
+            }
+        }
+
+        self
+    }
+
+    // Turns the patches into a smooth shaded mesh, with the texture map as its material.
+    pub fn textured(self, material: Material) -> TriangleMesh {
+        TriangleMesh::from_faces(self.vertices, self.normals, self.uvs, vec![material], self.faces)
+    }
+
+    // Turns the patches into a smooth shaded mesh with the texture map baked into vertex colors,
+    // for formats that can't carry a texture. The material keeps the texture's average albedo.
+    pub fn baked(self, texture: &dyn Fn(f32, f32) -> Texture) -> TriangleMesh {
+        let textures: Vec<Texture> = self.uvs.iter().map(|&(u, v)| texture(u, v)).collect();
+        let albedo = textures.iter().map(|texture| texture.albedo).sum::<f32>() / textures.len().max(1) as f32;
+        let colors = textures.iter().map(|texture| texture.color).collect();
+
+        self.textured(Box::new(move |_, _| Texture { color: Color::white(), albedo }))
+            .with_vertex_colors(colors)
+    }
+}
+
+// Turns a surface's texture map into a mesh material that shares it.
+pub fn share(texture: &Rc<dyn Fn(f32, f32) -> Texture>) -> Material {
+    let texture = Rc::clone(texture);
+    Box::new(move |u, v| texture(u, v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{Cone, Cuboid, Cylinder, Instance, Sphere, Surface, Torus};
+
+    // Checks that every vertex lies on the surface, and that the faces point the same way as its normals.
+    fn check(surface: &dyn Surface, mesh: &TriangleMesh) {
+        for tri in mesh.faces() {
+            let (a, b, c) = tri.vertices;
+            let (a, b, c) = (mesh.vertices[a], mesh.vertices[b], mesh.vertices[c]);
+            let center = Point3::from((a.coords + b.coords + c.coords) / 3.0);
+            let face_normal = (b - a).cross(&(c - a));
+            assert!(face_normal.dot(&surface.normal(center)) > 0.0);
+            assert!(surface.distance(a).abs() < 1e-4);
+            assert!(surface.distance(center) < 0.1);
+        }
+    }
+
+    #[test]
+    fn tessellate_primitives() {
+        let sphere = Sphere::new(1.0, 0.0, 0.0, 2.0, |u, v| Texture::new(u, v, 0.0, 0.5));
+        let mesh = sphere.tessellate(16).unwrap();
+        check(&sphere, &mesh);
+        // The poles are single points, so the top and bottom rows have one triangle per quad.
+        assert_eq!(mesh.triangles.len(), 2 * 16 * 8 - 2 * 16);
+        // Texture coordinates and colors follow the sphere's own.
+        let point = Point3::new(1.0, 0.0, 2.0);
+        let (u, v) = mesh.uv(point);
+        let (su, sv) = sphere.uv(point);
+        assert!((u - su).abs() < 1e-3 && (v - sv).abs() < 1e-3);
+        assert!((mesh.get_texture(point).color.red - u).abs() < 1e-3);
+        assert!(mesh.colors.is_empty());
+
+        let cuboid = Cuboid::new_solid(Point3::new(-1.0, -2.0, -3.0), Point3::new(1.0, 2.0, 3.0), Texture::white());
+        let mesh = cuboid.tessellate(8).unwrap();
+        check(&cuboid, &mesh);
+        assert_eq!(mesh.triangles.len(), 12);
+        for &point in &[Point3::new(1.0, 0.5, -1.0), Point3::new(0.2, -2.0, 1.0), Point3::new(-0.5, 1.0, -3.0)] {
+            let (u, v) = mesh.uv(point);
+            let (cu, cv) = cuboid.uv(point);
+            assert!((u - cu).abs() < 1e-5 && (v - cv).abs() < 1e-5);
+        }
+
+        let cylinder = Cylinder::new_solid(Point3::origin(), Point3::new(0.0, 0.0, 2.0), 1.0, Texture::white());
+        check(&cylinder, &cylinder.tessellate(12).unwrap());
+        let cone = Cone::new_solid(Point3::origin(), Point3::new(0.0, 3.0, 0.0), 1.0, Texture::white());
+        check(&cone, &cone.tessellate(12).unwrap());
+
+        let torus = Torus::new_solid(Point3::origin(), Vector3::z(), 2.0, 0.5, Texture::white());
+        let mesh = torus.tessellate(24).unwrap();
+        check(&torus, &mesh);
+        // Closed, so a ray through the tube enters and leaves it twice.
+        assert_eq!(mesh.intervals(Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::x())).len(), 2);
+    }
+
+    #[test]
+    fn tessellate_baked() {
+        let mut tessellation = Tessellation::new();
+        tessellation.patch(1, 1, |s, t| (Point3::new(s, 0.0, t), -Vector3::y(), (s, t)));
+        let mesh = tessellation.baked(&|u, _| Texture::new(u, 0.0, 0.0, 0.5));
+        assert_eq!(mesh.colors.len(), 4);
+        let texture = mesh.get_texture(Point3::new(0.75, 0.0, 0.5));
+        assert!((texture.color.red - 0.75).abs() < 1e-5 && texture.albedo == 0.5);
+    }
+
+    #[test]
+    fn tessellate_instance() {
+        let sphere: Rc<dyn Surface> = Rc::new(Sphere::new_solid(0.0, 0.0, 0.0, 1.0, Texture::white()));
+        let mirror = Instance::from_matrix(sphere, Matrix4::new_nonuniform_scaling(&Vector3::new(-2.0, 1.0, 1.0)));
+        let mesh = mirror.tessellate(16).unwrap();
+        check(&mirror, &mesh);
+        assert!(mesh.vertices.iter().any(|p| (p.x - 2.0).abs() < 1e-5));
+    }
+}
@@ -0,0 +1,143 @@
+extern crate nalgebra as na;
+
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use na::*;
+
+use crate::types::*;
+use super::{Surface, Tessellation, TriangleMesh, share, bound::*, csg::pairs, local_frame::*, roots::*};
+
+pub struct Torus {
+    pub major_radius: f32, // Distance from the center to the middle of the tube.
+    pub minor_radius: f32, // Radius of the tube.
+
+    frame: LocalFrame, // Local frame, with the torus lying in the xz-plane.
+
+    texture: Rc<dyn Fn(f32, f32) -> Texture> // Texture map.
+                                              // Uses the angle around the axis and the angle
+                                              // around the tube (both normalized from 0-1).
+}
+
+#[allow(dead_code)]
+impl Torus {
+    // Creates a new torus around the given axis.
+    pub fn new<F>(center: Point3f, axis: Vector3f, major_radius: f32, minor_radius: f32, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+    {
+        Torus { major_radius, minor_radius, frame: LocalFrame::new(center, axis), texture: Rc::new(texture) }
+    }
+
+    // Creates a new torus of a solid color.
+    pub fn new_solid(center: Point3f, axis: Vector3f, major_radius: f32, minor_radius: f32, texture: Texture) -> Self
+        { Torus::new(center, axis, major_radius, minor_radius, move |_, _| texture) }
+
+    // Tessellates the torus with the given number of segments around the axis and sides
+    // around the tube, which follow the texture coordinates.
+    pub fn tessellate_grid(&self, segments: usize, sides: usize) -> TriangleMesh {
+        let mut tessellation = Tessellation::new();
+        tessellation.patch(segments, sides, |s, t| {
+            let (phi, psi) = (2.0 * PI * (s - 0.5), 2.0 * PI * (t - 0.5));
+            let normal = Vector3::new(psi.cos() * phi.cos(), psi.sin(), psi.cos() * phi.sin());
+            let ring = Vector3::new(phi.cos(), 0.0, phi.sin()) * self.major_radius;
+            (self.frame.point_to_world(Point3::from(ring + normal * self.minor_radius)), self.frame.rotation * normal, (s, t))
+        });
+        tessellation.textured(share(&self.texture))
+    }
+
+    // Returns every distance at which the ray's line crosses the torus, in order.
+    fn roots(&self, ray: Ray) -> Vec<f64> {
+        let local = self.frame.ray_to_local(ray);
+        let (o, d) = (local.origin.coords.map(f64::from), local.direction.map(f64::from));
+        let (major, minor) = (self.major_radius as f64, self.minor_radius as f64);
+
+        // Substituting the ray into (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2)
+        // gives a quartic in t (the direction has unit length).
+        let e = o.norm_squared() - major * major - minor * minor;
+        let f = o.dot(&d);
+        let four_r2 = 4.0 * major * major;
+
+        solve_quartic(4.0 * f,
+                      2.0 * e + 4.0 * f * f + four_r2 * d.y * d.y,
+                      4.0 * f * e + 2.0 * four_r2 * o.y * d.y,
+                      e * e - four_r2 * (minor * minor - o.y * o.y))
+    }
+}
+
+impl Surface for Torus {
+    fn intersect(&self, ray: Ray) -> Option<f32> {
+        self.roots(ray).into_iter().find(|&t| t >= 0.0).map(|t| t as f32)
+    }
+
+    fn normal(&self, point: Point3f) -> Unit3f {
+        let p = self.frame.point_to_local(point).coords;
+        let ring = Vector3::new(p.x, 0.0, p.z).normalize() * self.major_radius;
+        self.frame.normal_to_world(p - ring)
+    }
+
+    fn get_texture(&self, point: Point3f) -> Texture {
+        let (x, y) = self.uv(point);
+        (*self.texture)(x, y)
+    }
+
+    fn uv(&self, point: Point3f) -> (f32, f32) {
+        let p = self.frame.point_to_local(point);
+        let rho = (p.x * p.x + p.z * p.z).sqrt();
+        (0.5 + p.z.atan2(p.x) / (2.0 * PI), 0.5 + p.y.atan2(rho - self.major_radius) / (2.0 * PI))
+    }
+
+    fn intervals(&self, ray: Ray) -> Vec<(f32, f32)> {
+        pairs(self.roots(ray).into_iter().map(|t| t as f32).collect())
+    }
+
+    fn distance(&self, point: Point3f) -> f32 {
+        let p = self.frame.point_to_local(point);
+        let rho = (p.x * p.x + p.z * p.z).sqrt();
+        (((rho - self.major_radius).powi(2) + p.y * p.y).sqrt() - self.minor_radius).abs()
+    }
+
+    // The tube is thinner than the ring, so it gets half as many sides as there are segments.
+    fn tessellate(&self, resolution: usize) -> Option<TriangleMesh> { Some(self.tessellate_grid(resolution, resolution / 2)) }
+
+    fn bound(&self) -> Bound {
+        Bound { center: self.frame.origin, radius: self.major_radius + self.minor_radius, bypass: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn torus_intersect() {
+        let torus = Torus::new_solid(Point3::origin(), Vector3::y(), 2.0, 0.5, Texture::white());
+
+        // Straight through the tube.
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::x());
+        let t = torus.intersect(ray).unwrap();
+        assert!((t - 2.5).abs() < 1e-4);
+        assert!((torus.normal(ray.project(t)).into_inner() + Vector3::x()).norm() < 1e-4);
+
+        // Through the hole in the middle.
+        assert_eq!(torus.intersect(Ray::new(Point3::new(0.0, 5.0, 0.0), -Vector3::y())), None);
+
+        // From the top, onto the tube.
+        let t = torus.intersect(Ray::new(Point3::new(2.0, 5.0, 0.0), -Vector3::y())).unwrap();
+        assert!((t - 4.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn torus_uv() {
+        let torus = Torus::new_solid(Point3::origin(), Vector3::y(), 2.0, 0.5, Texture::white());
+
+        // Around the axis, opposite sides are half a turn apart.
+        let (u1, v1) = torus.uv(Point3::new(2.5, 0.0, 0.0));
+        let (u2, v2) = torus.uv(Point3::new(-2.5, 0.0, 0.0));
+        assert!(((u1 - u2).abs() - 0.5).abs() < 1e-4);
+
+        // Around the tube, from the outer equator to the top.
+        assert!((v1 - 0.5).abs() < 1e-4 && (v2 - 0.5).abs() < 1e-4);
+        let (_, v) = torus.uv(Point3::new(2.0, 0.5, 0.0));
+        assert!((v - 0.75).abs() < 1e-4);
+    }
+}
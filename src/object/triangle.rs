@@ -6,43 +6,98 @@ use na::*;
 use na::geometry::Point3;
 
 use crate::types::*;
-use super::{Surface, bound::*};
+use super::{Surface, bound::*, csg::pairs};
+
+// A texture map over a mesh's texture coordinates,
+// shared by all the triangles referencing it.
+pub type Material = Box<dyn Fn(f32, f32) -> Texture>;
 
 pub struct Triangle {
     pub v1: usize, // Handles to 3 vertices.
     pub v2: usize,
     pub v3: usize,
 
-    normal: Unit3f, // Precalculated normal vector.
+    normal: Unit3f, // Precalculated (geometric) normal vector.
     area: f32, // Precalculated area for barycentric calculations.
 
-    texture: Box<dyn Fn(f32, f32, f32) -> Texture> // Texture map.
-                                                   // Uses barycentric coordinates as input.
+    pub normals: Option<(usize, usize, usize)>, // Handles to 3 vertex normals, if the triangle is smooth shaded.
+    pub uvs: Option<(usize, usize, usize)>, // Handles to 3 texture coordinates. Without them,
+                                            // barycentric coordinates are used instead.
+    pub material: usize // Index into the mesh's materials.
+}
+
+// The handles making up a triangle, used to build meshes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Face {
+    pub vertices: (usize, usize, usize),
+    pub normals: Option<(usize, usize, usize)>,
+    pub uvs: Option<(usize, usize, usize)>,
+    pub material: usize
 }
 
 pub struct TriangleMesh {
     pub vertices: Vec<Point3f>,
+    pub normals: Vec<Unit3f>, // Vertex normals, referenced by smooth shaded triangles.
+    pub uvs: Vec<(f32, f32)>, // Texture coordinates, referenced by textured triangles.
+    pub colors: Vec<Color>, // Vertex colors tinting the materials, either one for each vertex or none.
+    pub materials: Vec<Material>,
     pub triangles: Vec<Triangle>
 }
 
+// How the face normals around a vertex are weighted when averaging them.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalWeighting {
+    Area,  // By the area of each face, so large faces dominate.
+    Angle  // By the angle of each face at the vertex, which doesn't depend on how faces are split up.
+}
+
 fn tri_area(a: &Point3f, b: &Point3f, c: &Point3f) -> f32 {
     let prlg_area: f32 = (b - a).cross(&(c - a)).norm();
     prlg_area / 2.0
 }
 
+#[allow(dead_code)]
+impl Face {
+    // Creates a new face without normals or texture coordinates.
+    pub fn new(v1: usize, v2: usize, v3: usize, material: usize) -> Self
+        { Face { vertices: (v1, v2, v3), normals: None, uvs: None, material } }
+
+    // Returns the face with its winding (and so its facing) reversed.
+    pub fn reversed(self) -> Self {
+        let swap = |(a, b, c)| (a, c, b);
+        Face { vertices: swap(self.vertices), normals: self.normals.map(swap), uvs: self.uvs.map(swap), material: self.material }
+    }
+}
+
 impl Triangle {
-    fn vertex1<'a>(&self, vertices: &'a Vec<Point3f>) -> &'a Point3f { &vertices[self.v1] }
-    fn vertex2<'a>(&self, vertices: &'a Vec<Point3f>) -> &'a Point3f { &vertices[self.v2] }
-    fn vertex3<'a>(&self, vertices: &'a Vec<Point3f>) -> &'a Point3f { &vertices[self.v3] }
+    fn new(vertices: &[Point3f], face: Face) -> Self {
+        let (v1, v2, v3) = face.vertices;
+        Triangle {
+            v1, v2, v3,
+            // Degenerate triangles have no normal of their own, so they get an arbitrary one rather than NaNs.
+            normal: Unit::try_new((vertices[v2] - vertices[v1]).cross(&(vertices[v3] - vertices[v1])), 0.0)
+                .unwrap_or_else(Vector3::y_axis),
+            area: tri_area(&vertices[v1], &vertices[v2], &vertices[v3]),
+            normals: face.normals,
+            uvs: face.uvs,
+            material: face.material
+        }
+    }
+
+    fn vertex1<'a>(&self, vertices: &'a [Point3f]) -> &'a Point3f { &vertices[self.v1] }
+    fn vertex2<'a>(&self, vertices: &'a [Point3f]) -> &'a Point3f { &vertices[self.v2] }
+    fn vertex3<'a>(&self, vertices: &'a [Point3f]) -> &'a Point3f { &vertices[self.v3] }
 
     // Conversion of barycentric coordinates to
     // a point on the triangle.
-    fn from_bary(&self, vertices: &Vec<Point3f>, t: f32, u: f32, v: f32) -> Point3f {
+    #[allow(clippy::wrong_self_convention)]
+    fn from_bary(&self, vertices: &[Point3f], t: f32, u: f32, v: f32) -> Point3f {
         Point::from(t * self.vertex1(vertices).coords + u * self.vertex2(vertices).coords + v * self.vertex3(vertices).coords)
     }
 
     // Conversion of a point to barycentric coordinates.
-    fn to_bary(&self, vertices: &Vec<Point3f>, point: Point3f) -> (f32, f32, f32) {
+    fn to_bary(&self, vertices: &[Point3f], point: Point3f) -> (f32, f32, f32) {
         let t = tri_area(self.vertex2(vertices), self.vertex3(vertices), &point) / self.area;
         let u = tri_area(self.vertex1(vertices), self.vertex3(vertices), &point) / self.area;
         let v = tri_area(self.vertex1(vertices), self.vertex2(vertices), &point) / self.area;
@@ -50,7 +105,7 @@ impl Triangle {
         (t, u, v)
     }
 
-    fn intersect_(&self, vertices: &Vec<Point3f>, ray: Ray) -> Option<(f32, f32, f32)> {
+    fn intersect_(&self, vertices: &[Point3f], ray: Ray) -> Option<(f32, f32, f32)> {
         let vect2_1 = self.vertex2(vertices) - self.vertex1(vertices);
         let vect3_1 = self.vertex3(vertices) - self.vertex1(vertices);
 
@@ -62,7 +117,7 @@ impl Triangle {
         let t_vect = ray.origin - self.vertex1(vertices);
         let u = t_vect.dot(&p_vect) / det;
 
-        if u < 0.0 || u > 1.0 { return None; }
+        if !(0.0..=1.0).contains(&u) { return None; }
 
         let q_vect = t_vect.cross(&vect2_1);
         let v = ray.direction.dot(&q_vect) / det;
@@ -74,61 +129,136 @@ impl Triangle {
         Some((t, u, v))
     }
 
-    fn intersect(&self, vertices: &Vec<Point3f>, ray: Ray) -> Option<f32> {
-        self.intersect_(vertices, ray).map(|(t, u, v)| distance(&ray.origin, &self.from_bary(vertices, t, u, v)))
+    // Returns the signed distance along the ray's line to the triangle,
+    // which is negative if the triangle lies behind the ray's origin.
+    fn hit(&self, vertices: &[Point3f], ray: Ray) -> Option<f32> {
+        self.intersect_(vertices, ray).map(|(t, u, v)| (self.from_bary(vertices, t, u, v) - ray.origin).dot(&ray.direction))
+    }
+
+    fn intersect(&self, vertices: &[Point3f], ray: Ray) -> Option<f32> {
+        self.hit(vertices, ray).filter(|&t| t >= 0.0)
+    }
+
+    // Returns the corner (0, 1 or 2) of the triangle at a vertex.
+    fn corner(&self, vertex: usize) -> usize {
+        if vertex == self.v1 { 0 } else if vertex == self.v2 { 1 } else { 2 }
     }
 
-    fn get_texture(&self, vertices: &Vec<Point3f>, point: Point3f) -> Texture {
-        let (t, u, v) = self.to_bary(vertices, point);
-        (*self.texture)(t, u, v)
+    // Angle between the two edges meeting at a corner.
+    fn angle(&self, vertices: &[Point3f], corner: usize) -> f32 {
+        let (a, b, c) = match corner {
+            0 => (self.vertex1(vertices), self.vertex2(vertices), self.vertex3(vertices)),
+            1 => (self.vertex2(vertices), self.vertex3(vertices), self.vertex1(vertices)),
+            _ => (self.vertex3(vertices), self.vertex1(vertices), self.vertex2(vertices))
+        };
+        (b - a).angle(&(c - a))
     }
 }
 
 #[allow(dead_code)]
 impl TriangleMesh {
-    pub fn new(vertices: Vec<Point3f>, tris: Vec<(usize, usize, usize, Box<dyn Fn(f32, f32, f32) -> Texture>)>) -> Self {
-        let triangles = tris.into_iter()
-                            .map(|(v1, v2, v3, f)| Triangle {
-                                v1, v2, v3,
-                                normal: Unit::new_normalize((&vertices[v2] - &vertices[v1]).cross(&(&vertices[v3] - &vertices[v1]))),
-                                area: tri_area(&vertices[v1], &vertices[v2], &vertices[v3]),
-                                texture: f
-                            }).collect();
-        TriangleMesh {
-            vertices, triangles
-        }
+    // Creates a new mesh from its parts. Faces hold handles into the other vectors.
+    pub fn from_faces(vertices: Vec<Point3f>, normals: Vec<Unit3f>, uvs: Vec<(f32, f32)>,
+                      materials: Vec<Material>, faces: Vec<Face>) -> Self {
+        let triangles = faces.into_iter().map(|face| Triangle::new(&vertices, face)).collect();
+        TriangleMesh { vertices, normals, uvs, colors: Vec::new(), materials, triangles }
+    }
+
+    // Creates a new mesh, where each triangle references one of the materials by index.
+    pub fn new(vertices: Vec<Point3f>, tris: Vec<(usize, usize, usize, usize)>, materials: Vec<Material>) -> Self {
+        let faces = tris.into_iter().map(|(v1, v2, v3, material)| Face::new(v1, v2, v3, material)).collect();
+        TriangleMesh::from_faces(vertices, Vec::new(), Vec::new(), materials, faces)
     }
 
     pub fn new_solid(vertices: Vec<Point3f>, tris: Vec<(usize, usize, usize)>, texture: Texture) -> Self {
-        let triangles = tris.into_iter()
-                            .map(|(v1, v2, v3)| Triangle {
-                                v1, v2, v3,
-                                normal: Unit::new_normalize((&vertices[v2] - &vertices[v1]).cross(&(&vertices[v3] - &vertices[v1]))),
-                                area: tri_area(&vertices[v1], &vertices[v2], &vertices[v3]),
-                                texture: Box::new(move |_, _, _| texture)
-                            }).collect();
-        TriangleMesh {
-            vertices,
-            triangles
-        }
+        let tris = tris.into_iter().map(|(v1, v2, v3)| (v1, v2, v3, 0)).collect();
+        TriangleMesh::new(vertices, tris, vec![Box::new(move |_, _| texture)])
     }
 
-    pub fn singleton<F: 'static>(vertex1: Point3f, vertex2: Point3f, vertex3: Point3f, texture: F) -> Self
-        where F: Fn(f32, f32, f32) -> Texture
-        { TriangleMesh::new(vec![vertex1, vertex2, vertex3], vec![(0, 1, 2, Box::new(texture))]) }
+    pub fn singleton<F>(vertex1: Point3f, vertex2: Point3f, vertex3: Point3f, texture: F) -> Self
+        where F: 'static + Fn(f32, f32) -> Texture
+        { TriangleMesh::new(vec![vertex1, vertex2, vertex3], vec![(0, 1, 2, 0)], vec![Box::new(texture)]) }
 
     pub fn singleton_solid(vertex1: Point3f, vertex2: Point3f, vertex3: Point3f, texture: Texture) -> Self
-        { TriangleMesh::singleton(vertex1, vertex2, vertex3, move |_, _, _| texture) }
+        { TriangleMesh::singleton(vertex1, vertex2, vertex3, move |_, _| texture) }
 
+    // Textures the mesh with the given texture coordinates, one for each vertex.
+    pub fn with_uvs(mut self, uvs: Vec<(f32, f32)>) -> Self {
+        self.uvs = uvs;
+        for tri in self.triangles.iter_mut() { tri.uvs = Some((tri.v1, tri.v2, tri.v3)); }
+        self
+    }
 
-    fn closest_tri(&self, point: Point3f) -> &Triangle {
+    // Tints the materials with the given colors, one for each vertex.
+    // No colors leaves the materials as they are.
+    pub fn with_vertex_colors(mut self, colors: Vec<Color>) -> Self {
+        assert!(colors.is_empty() || colors.len() == self.vertices.len(),
+                "{} vertex colors for {} vertices", colors.len(), self.vertices.len());
+        self.colors = colors;
+        self
+    }
+
+    // Smooth shades the mesh with the given normals, one for each vertex.
+    pub fn with_vertex_normals(mut self, normals: Vec<Vector3f>) -> Self {
+        assert!(normals.len() == self.vertices.len(),
+                "{} vertex normals for {} vertices", normals.len(), self.vertices.len());
+        self.normals = normals.into_iter().map(Unit::new_normalize).collect();
+        for tri in self.triangles.iter_mut() { tri.normals = Some((tri.v1, tri.v2, tri.v3)); }
+        self
+    }
+
+    // Smooth shades the mesh with normals averaged from the faces around each vertex.
+    // Faces meeting at an angle (in radians) above the crease angle don't share
+    // normals, so hard edges stay sharp.
+    pub fn smooth(mut self, weighting: NormalWeighting, crease_angle: f32) -> Self {
+        let mut faces: Vec<Vec<usize>> = vec![Vec::new(); self.vertices.len()];
+        for (i, tri) in self.triangles.iter().enumerate() {
+            for &v in &[tri.v1, tri.v2, tri.v3] { faces[v].push(i); }
+        }
+
+        let cos_crease = crease_angle.cos();
+        let mut normals = Vec::with_capacity(3 * self.triangles.len());
+        for tri in self.triangles.iter() {
+            for &v in &[tri.v1, tri.v2, tri.v3] {
+                let normal: Vector3f = faces[v].iter()
+                    .map(|&i| &self.triangles[i])
+                    .filter(|other| other.area > 0.0 && other.normal.dot(&tri.normal) >= cos_crease)
+                    .map(|other| *other.normal * match weighting {
+                        NormalWeighting::Area => other.area,
+                        NormalWeighting::Angle => other.angle(&self.vertices, other.corner(v))
+                    })
+                    .sum();
+                normals.push(if normal.norm_squared() > 0.0 { Unit::new_normalize(normal) } else { tri.normal });
+            }
+        }
+
+        for (i, tri) in self.triangles.iter_mut().enumerate() { tri.normals = Some((3 * i, 3 * i + 1, 3 * i + 2)); }
+        self.normals = normals;
+        self
+    }
+
+    // Returns the handles making up each triangle.
+    pub fn faces(&self) -> Vec<Face> {
         self.triangles.iter()
-            .map(move |tri| {
+            .map(|tri| Face { vertices: (tri.v1, tri.v2, tri.v3), normals: tri.normals, uvs: tri.uvs, material: tri.material })
+            .collect()
+    }
+
+    fn closest_tri(&self, point: Point3f) -> &Triangle {
+        &self.triangles[self.closest_tri_index(point)]
+    }
+
+    fn closest_tri_index(&self, point: Point3f) -> usize { self.closest_tri_distance(point).0 }
+
+    // Returns the index of the triangle closest to the point, along with its distance.
+    fn closest_tri_distance(&self, point: Point3f) -> (usize, f32) {
+        self.triangles.iter().enumerate()
+            .map(move |(i, tri)| {
 
                 let rel_pos = point - tri.vertex1(&self.vertices);
                 let proj_point3 = rel_pos - (*tri.normal * tri.normal.dot(&rel_pos));
 
-                let (t, u, v) = tri.to_bary(&self.vertices, Point3::from(proj_point3));
+                let (t, u, v) = tri.to_bary(&self.vertices, tri.vertex1(&self.vertices) + proj_point3);
 
                 let t = clamp(t, 0.0, 1.0);
                 let u = clamp(u, 0.0, 1.0);
@@ -136,10 +266,10 @@ impl TriangleMesh {
 
                 let point_new = tri.from_bary(&self.vertices, t, u, v);
 
-                (tri, distance(&point, &point_new))
+                (i, distance(&point, &point_new))
             })
             .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
-            .unwrap().0
+            .unwrap()
     }
 }
 
@@ -147,21 +277,69 @@ impl Surface for TriangleMesh {
     fn intersect(&self, ray: Ray) -> Option<f32> {
         self.triangles.iter()
              .filter_map(|tri| tri.intersect(&self.vertices, ray))
-             .min_by(|a, b| a.partial_cmp(&b).unwrap_or(Ordering::Equal))
+             .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
     }
 
+    // Interpolates the vertex normals of smooth shaded triangles.
     fn normal(&self, point: Point3f) -> Unit3f {
+        let tri = self.closest_tri(point);
+        match tri.normals {
+            Some((n1, n2, n3)) => {
+                let (t, u, v) = tri.to_bary(&self.vertices, point);
+                Unit::new_normalize(*self.normals[n1] * t + *self.normals[n2] * u + *self.normals[n3] * v)
+            },
+            None => tri.normal
+        }
+    }
+
+    fn geometric_normal(&self, point: Point3f) -> Unit3f {
         self.closest_tri(point).normal
     }
 
     fn get_texture(&self, point: Point3f) -> Texture {
-        self.closest_tri(point).get_texture(&self.vertices, point)
+        let tri = self.closest_tri(point);
+        let (x, y) = self.uv(point);
+        let mut texture = (*self.materials[tri.material])(x, y);
+
+        if !self.colors.is_empty() {
+            let (t, u, v) = tri.to_bary(&self.vertices, point);
+            texture.color *= t * self.colors[tri.v1] + u * self.colors[tri.v2] + v * self.colors[tri.v3];
+        }
+        texture
+    }
+
+    // Interpolates the texture coordinates of textured triangles. Others
+    // have none of their own, so their barycentric coordinates are used instead.
+    fn uv(&self, point: Point3f) -> (f32, f32) {
+        let tri = self.closest_tri(point);
+        let (t, u, v) = tri.to_bary(&self.vertices, point);
+        match tri.uvs {
+            Some((uv1, uv2, uv3)) => {
+                let (a, b, c) = (self.uvs[uv1], self.uvs[uv2], self.uvs[uv3]);
+                (t * a.0 + u * b.0 + v * c.0, t * a.1 + u * b.1 + v * c.1)
+            },
+            None => (u, v)
+        }
+    }
+
+    fn primitive(&self, point: Point3f) -> usize { self.closest_tri_index(point) }
+
+    // The mesh is assumed to be closed, so the ray's line
+    // alternates between entering and leaving it.
+    fn intervals(&self, ray: Ray) -> Vec<(f32, f32)> {
+        let mut hits: Vec<f32> = self.triangles.iter().filter_map(|tri| tri.hit(&self.vertices, ray)).collect();
+        hits.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        // A line through a shared edge or vertex hits several triangles at once.
+        hits.dedup_by(|a, b| (*a - *b).abs() < 1e-5);
+        pairs(hits)
     }
 
+    fn distance(&self, point: Point3f) -> f32 { self.closest_tri_distance(point).1 }
+
     // Uses Welzl's algorithm to solve the bounding sphere problem
     fn bound(&self) -> Bound {
         fn smallest_sphere_plane(points: Vec<&Point3f>, boundary: Vec<&Point3f>) -> (Point3f, f32) {
-            if points.len() == 0 || boundary.len() == 3 {
+            if points.is_empty() || boundary.len() == 3 {
                 match boundary.len() {
                     0 => (Point3::new(0.0, 0.0, 0.0), 0.0),
                     1 => (*boundary[0], 0.0),
@@ -206,8 +384,8 @@ impl Surface for TriangleMesh {
 
             let a = matrix.determinant() * 2.0;
 
-            if (a != 0.0) {
-                let mut matrix_mut = matrix.clone();
+            if a != 0.0 {
+                let mut matrix_mut = matrix;
 
                 let squares = Vector4::new(point1.coords.norm_squared(), point2.coords.norm_squared(), point3.coords.norm_squared(), point4.coords.norm_squared());
                 matrix_mut.set_column(0, &squares);
@@ -232,7 +410,7 @@ impl Surface for TriangleMesh {
         }
 
         fn smallest_sphere(points: Vec<&Point3f>, boundary: Vec<&Point3f>) -> (Point3f, f32) {
-            if points.len() == 0 || boundary.len() == 4 {
+            if points.is_empty() || boundary.len() == 4 {
                 match boundary.len() {
                     0 => (Point3::new(0.0, 0.0, 0.0), 0.0),
                     1 => (*boundary[0], 0.0),
@@ -268,3 +446,88 @@ impl Surface for TriangleMesh {
         Bound { center, radius: radius + 1e-3, bypass: false }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two faces folded along the z-axis like a roof, at 45 degrees each.
+    fn roof() -> TriangleMesh {
+        TriangleMesh::new_solid(vec![Point3::new(0.0, 1.0, 0.0), Point3::new(0.0, 1.0, 1.0),
+                                     Point3::new(1.0, 0.0, 0.0), Point3::new(1.0, 0.0, 1.0),
+                                     Point3::new(-1.0, 0.0, 0.0), Point3::new(-1.0, 0.0, 1.0)],
+                                vec![(0, 1, 2), (1, 3, 2), (0, 4, 1), (1, 4, 5)],
+                                Texture::white())
+    }
+
+    #[test]
+    fn mesh_materials() {
+        // A quad with a checkerboard on one triangle and a solid material on the other.
+        let checker: Material = Box::new(|u, v| {
+            let c = if ((4.0 * u) as i32 + (4.0 * v) as i32) % 2 == 0 { 1.0 } else { 0.0 };
+            Texture::new(c, c, c, 1.0)
+        });
+        let red: Material = Box::new(|_, _| Texture::new(1.0, 0.0, 0.0, 1.0));
+        let quad = TriangleMesh::new(vec![Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0),
+                                          Point3::new(1.0, 1.0, 0.0), Point3::new(0.0, 1.0, 0.0)],
+                                     vec![(0, 1, 2, 0), (0, 2, 3, 1)], vec![checker, red])
+                     .with_uvs(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+
+        let (u, v) = quad.uv(Point3::new(0.8, 0.3, 0.0));
+        assert!((u - 0.8).abs() < 1e-4 && (v - 0.3).abs() < 1e-4);
+        assert_eq!(quad.get_texture(Point3::new(0.8, 0.1, 0.0)).color, Color::black());
+        assert_eq!(quad.get_texture(Point3::new(0.8, 0.3, 0.0)).color, Color::white());
+        assert_eq!(quad.get_texture(Point3::new(0.2, 0.8, 0.0)).color, Color::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn mesh_away_from_origin() {
+        // Points have to be measured from the triangles, not the origin,
+        // to find which triangle of a mesh they lie on.
+        let offset = Vector3::new(10.0, 5.0, -3.0);
+        let red: Material = Box::new(|_, _| Texture::new(1.0, 0.0, 0.0, 1.0));
+        let blue: Material = Box::new(|_, _| Texture::new(0.0, 0.0, 1.0, 1.0));
+        let quad = TriangleMesh::new(vec![Point3::new(0.0, 0.0, 0.0) + offset, Point3::new(1.0, 0.0, 0.0) + offset,
+                                          Point3::new(1.0, 1.0, 0.0) + offset, Point3::new(0.0, 1.0, 0.0) + offset],
+                                     vec![(0, 1, 2, 0), (0, 2, 3, 1)], vec![red, blue])
+                     .with_uvs(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+
+        assert_eq!(quad.get_texture(Point3::new(0.8, 0.3, 0.0) + offset).color, Color::new(1.0, 0.0, 0.0));
+        assert_eq!(quad.get_texture(Point3::new(0.2, 0.8, 0.0) + offset).color, Color::new(0.0, 0.0, 1.0));
+        let (u, v) = quad.uv(Point3::new(0.2, 0.8, 0.0) + offset);
+        assert!((u - 0.2).abs() < 1e-4 && (v - 0.8).abs() < 1e-4);
+    }
+
+    #[test]
+    #[should_panic(expected = "2 vertex colors for 6 vertices")]
+    fn mesh_vertex_colors_length() {
+        roof().with_vertex_colors(vec![Color::white(); 2]);
+    }
+
+    #[test]
+    fn mesh_smooth_normals() {
+        let ridge = Point3::new(0.0, 1.0, 0.5);
+        let slope = Point3::new(0.5, 0.5, 0.5);
+        let face = Vector3::new(1.0, 1.0, 0.0).normalize();
+
+        let flat = roof();
+        assert!((flat.normal(slope).into_inner() - face).norm() < 1e-4);
+
+        // Smoothed, the normal bends towards the other face, and is vertical on the ridge.
+        let smooth = roof().smooth(NormalWeighting::Angle, 1.6);
+        assert!((smooth.normal(ridge).into_inner() - Vector3::y()).norm() < 1e-4);
+        assert!(smooth.normal(slope).x < face.x);
+        assert!((smooth.geometric_normal(slope).into_inner() - face).norm() < 1e-4);
+
+        // Past the crease angle the faces stay flat.
+        let creased = roof().smooth(NormalWeighting::Area, 1.0);
+        assert!((creased.normal(Point3::new(0.05, 0.95, 0.5)).into_inner() - face).norm() < 1e-4);
+        assert!((creased.normal(Point3::new(-0.05, 0.95, 0.5)).into_inner() - Vector3::new(-1.0, 1.0, 0.0).normalize()).norm() < 1e-4);
+    }
+
+    #[test]
+    #[should_panic(expected = "5 vertex normals for 6 vertices")]
+    fn mesh_vertex_normals_length() {
+        roof().with_vertex_normals(vec![Vector3::y(); 5]);
+    }
+}
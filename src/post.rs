@@ -0,0 +1,186 @@
+mod bloom; #[allow(unused_imports)] pub use bloom::*;
+mod vignette; #[allow(unused_imports)] pub use vignette::*;
+mod chromatic_aberration; #[allow(unused_imports)] pub use chromatic_aberration::*;
+mod film_grain; #[allow(unused_imports)] pub use film_grain::*;
+mod sharpen; #[allow(unused_imports)] pub use sharpen::*;
+
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+
+use crate::image::Image;
+use crate::types::*;
+
+// A trait for image effects applied after rendering.
+// Effects work on the linear float framebuffer, before it is
+// tone mapped and quantized for output, so they see HDR values above 1.0.
+// The image is premultiplied, so effects that move color around move alpha with it.
+pub trait PostProcess {
+    fn apply(&self, image: &mut Image) -> io::Result<()>;
+}
+
+// A sequence of effects, applied in order.
+#[derive(Clone, Default)]
+pub struct PostChain {
+    pub effects: Vec<Rc<dyn PostProcess>>
+}
+
+#[allow(dead_code)]
+impl PostChain {
+    pub fn new() -> Self { PostChain { effects: Vec::new() } }
+
+    // Adds an effect to the end of the chain.
+    pub fn then(mut self, effect: impl 'static + PostProcess) -> Self {
+        self.effects.push(Rc::new(effect));
+        self
+    }
+}
+
+impl fmt::Debug for PostChain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PostChain {{ {} effects }}", self.effects.len())
+    }
+}
+
+impl PostProcess for PostChain {
+    fn apply(&self, image: &mut Image) -> io::Result<()> {
+        for effect in self.effects.iter() {
+            effect.apply(image)?;
+        }
+        Ok(())
+    }
+}
+
+// Maps the HDR framebuffer into the displayable range. Runs after the post-process chain.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    Linear,       // Leaves values as they are, so 8-bit output clips them at 1.
+    Reinhard(f32) // Scales by the exposure and compresses luminance with L / (1 + L), keeping hues.
+}
+
+impl ToneMap {
+    pub fn apply(self, image: &mut Image) {
+        if let ToneMap::Reinhard(exposure) = self {
+            for (pixel, &alpha) in image.pixels.iter_mut().zip(image.alpha.iter()) {
+                // Compress the straight color, so partly covered pixels map like opaque ones.
+                let luminance = exposure * if alpha > 0.0 { pixel.luminance() / alpha } else { pixel.luminance() };
+                *pixel *= exposure / (1.0 + luminance);
+            }
+        }
+    }
+}
+
+// Creates a normalized 1D Gaussian kernel, extending three deviations out.
+// A deviation of zero (or less) gives a kernel that leaves the image as it is.
+pub fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    if sigma <= 0.0 || sigma.is_nan() { return vec![1.0]; }
+    let radius = (3.0 * sigma).ceil().max(1.0) as i32;
+    let kernel: Vec<f32> = (-radius..=radius).map(|x| (-(x * x) as f32 / (2.0 * sigma * sigma)).exp()).collect();
+    let total: f32 = kernel.iter().sum();
+    kernel.into_iter().map(|w| w / total).collect()
+}
+
+// Convolves a buffer of colors with a separable kernel, applied
+// horizontally and then vertically. Edges are clamped.
+pub fn convolve_separable(pixels: &[Color], width: u32, height: u32, kernel: &[f32]) -> Vec<Color> {
+    let (width, height) = (width as i32, height as i32);
+    let radius = kernel.len() as i32 / 2;
+
+    let pass = |pixels: &[Color], dx: i32, dy: i32| -> Vec<Color> {
+        (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| kernel.iter().enumerate().map(|(k, &w)| {
+                let offset = k as i32 - radius;
+                let sx = (x + offset * dx).max(0).min(width - 1);
+                let sy = (y + offset * dy).max(0).min(height - 1);
+                pixels[(sy * width + sx) as usize] * w
+            }).sum())
+            .collect()
+    };
+
+    let horizontal = pass(pixels, 1, 0);
+    pass(&horizontal, 0, 1)
+}
+
+// Convolves a buffer of colors with a square 2D kernel, given in
+// row-major order. Edges are clamped. Fails if the kernel isn't square.
+pub fn convolve(pixels: &[Color], width: u32, height: u32, kernel: &[f32]) -> io::Result<Vec<Color>> {
+    let (width, height) = (width as i32, height as i32);
+    let size = (kernel.len() as f32).sqrt().round() as i32;
+    if size == 0 || (size * size) as usize != kernel.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("convolution kernel of {} weights is not square", kernel.len())));
+    }
+    let radius = size / 2;
+
+    Ok((0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| kernel.iter().enumerate().map(|(k, &w)| {
+            let sx = (x + k as i32 % size - radius).max(0).min(width - 1);
+            let sy = (y + k as i32 / size - radius).max(0).min(height - 1);
+            pixels[(sy * width + sx) as usize] * w
+        }).sum())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gaussian_kernel_normalized() {
+        let kernel = gaussian_kernel(2.0);
+        assert_eq!(kernel.len(), 13);
+        assert!((kernel.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert_eq!(gaussian_kernel(0.0), vec![1.0]);
+    }
+
+    #[test]
+    fn convolve_preserves_flat() {
+        let pixels = vec![Color::gray(2.0); 16];
+        let blurred = convolve_separable(&pixels, 4, 4, &gaussian_kernel(1.0));
+        assert!(blurred.iter().all(|c| (c.red - 2.0).abs() < 1e-5));
+
+        let blurred = convolve(&pixels, 4, 4, &[1.0 / 9.0; 9]).unwrap();
+        assert!(blurred.iter().all(|c| (c.red - 2.0).abs() < 1e-5));
+        assert!(convolve(&pixels, 4, 4, &[0.5; 8]).is_err());
+    }
+
+    #[test]
+    fn chain_order() {
+        let mut image = Image::new(8, 8);
+        image.pixels = vec![Color::gray(4.0); 64];
+
+        PostChain::new().then(Vignette { strength: 0.5, falloff: 2.0 })
+                        .then(Sharpen { sigma: 1.0, amount: 0.5 })
+                        .apply(&mut image).unwrap();
+
+        // The center is untouched by the vignette and HDR values are kept.
+        assert!(image.get(4, 4).red > 3.0);
+        assert!(image.get(0, 0).red < image.get(4, 4).red);
+    }
+
+    #[test]
+    fn effects_keep_alpha() {
+        // A bright opaque pixel in the middle of a transparent image.
+        let mut image = Image::new(9, 9);
+        image.set(4, 4, Color::gray(10.0), 1.0);
+
+        Bloom::new(1.0, 1.0, 1.0).apply(&mut image).unwrap();
+        // The glow spreads into the transparent pixels around it, and shows up in the straight color.
+        assert!(image.get_alpha(5, 4) > 0.0 && image.get_alpha(5, 4) <= 1.0);
+        assert!(image.get_straight(5, 4).red > 0.0);
+        assert_eq!(image.get_alpha(4, 4), 1.0);
+
+        let mut image = Image::new(9, 9);
+        image.set(8, 4, Color::white(), 1.0);
+        ChromaticAberration { amount: 0.25 }.apply(&mut image).unwrap();
+        // The red fringe moves inwards, along with its coverage.
+        let fringe = (0..8).find(|&x| image.get(x, 4).red > 0.0).unwrap();
+        assert!(image.get_alpha(fringe, 4) > 0.0);
+
+        let mut image = Image::new(1, 1);
+        image.set(0, 0, Color::gray(3.0), 1.0);
+        ToneMap::Reinhard(1.0).apply(&mut image);
+        assert!((image.get(0, 0).red - 0.75).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,52 @@
+use std::io;
+
+use crate::image::Image;
+use crate::types::*;
+use super::*;
+
+// The blur used to spread the bright parts of an image.
+#[allow(dead_code)]
+pub enum BloomKernel {
+    Gaussian(f32),   // Gaussian blur with the given standard deviation in pixels.
+    Custom(Vec<f32>) // Square 2D kernel in row-major order, for shaped glare such as streaks.
+}
+
+// Threshold bloom: pixels brighter than the threshold
+// are blurred and added back onto the image. The glow is light of its own,
+// so it also covers transparent pixels it spreads into.
+pub struct Bloom {
+    pub threshold: f32, // Luminance above which pixels start to glow.
+    pub intensity: f32, // Strength of the glow added onto the image.
+    pub kernel: BloomKernel
+}
+
+#[allow(dead_code)]
+impl Bloom {
+    // Creates a new bloom effect with a Gaussian kernel.
+    pub fn new(threshold: f32, intensity: f32, sigma: f32) -> Self {
+        Bloom { threshold, intensity, kernel: BloomKernel::Gaussian(sigma) }
+    }
+}
+
+impl PostProcess for Bloom {
+    fn apply(&self, image: &mut Image) -> io::Result<()> {
+        // Keep only the part of each pixel above the threshold.
+        let bright: Vec<Color> = image.pixels.iter().map(|&color| {
+            let luminance = color.luminance();
+            if luminance > self.threshold { color * ((luminance - self.threshold) / luminance) }
+            else { Color::black() }
+        }).collect();
+
+        let glow = match &self.kernel {
+            BloomKernel::Gaussian(sigma) => convolve_separable(&bright, image.width, image.height, &gaussian_kernel(*sigma)),
+            BloomKernel::Custom(kernel) => convolve(&bright, image.width, image.height, kernel)?
+        };
+
+        for ((pixel, alpha), glow) in image.pixels.iter_mut().zip(image.alpha.iter_mut()).zip(glow) {
+            let glow = glow * self.intensity;
+            *pixel += glow;
+            *alpha += glow.max_component().min(1.0) * (1.0 - *alpha);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+use std::io;
+
+use crate::image::Image;
+use crate::types::*;
+use super::*;
+
+// Lateral chromatic aberration: the red and blue channels are scaled
+// radially around the image center in opposite directions, producing
+// colored fringes towards the edges. Each channel brings its coverage
+// along, so fringes reaching past an object's edge stay visible.
+#[allow(dead_code)]
+pub struct ChromaticAberration {
+    pub amount: f32 // Relative scale difference of the red and blue channels (e.g. 0.005).
+}
+
+impl PostProcess for ChromaticAberration {
+    fn apply(&self, image: &mut Image) -> io::Result<()> {
+        let source = image.clone();
+        let center_x = image.width as f32 * 0.5;
+        let center_y = image.height as f32 * 0.5;
+
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let dx = x as f32 + 0.5 - center_x;
+                let dy = y as f32 + 0.5 - center_y;
+
+                let sample = |scale: f32| {
+                    let (x, y) = (center_x + dx * scale, center_y + dy * scale);
+                    (source.sample(x, y), source.sample_alpha(x, y))
+                };
+                let (red, red_alpha) = sample(1.0 + self.amount);
+                let (blue, blue_alpha) = sample(1.0 - self.amount);
+
+                let index = (y * image.width + x) as usize;
+                let green = image.pixels[index].green;
+                image.pixels[index] = Color { red: red.red, green, blue: blue.blue };
+                image.alpha[index] = image.alpha[index].max(red_alpha).max(blue_alpha);
+            }
+        }
+        Ok(())
+    }
+}
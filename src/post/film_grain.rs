@@ -0,0 +1,29 @@
+use std::io;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::image::Image;
+use super::*;
+
+// Adds random film grain, scaled by the brightness of each pixel.
+#[allow(dead_code)]
+pub struct FilmGrain {
+    pub strength: f32, // Standard deviation of the grain, relative to the pixel value.
+    pub seed: u64      // Seed for the grain pattern, so frames can be reproduced.
+}
+
+impl PostProcess for FilmGrain {
+    fn apply(&self, image: &mut Image) -> io::Result<()> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        for pixel in image.pixels.iter_mut() {
+            // Sum of uniform variables as a cheap approximation to a unit Gaussian.
+            let noise = (0..4).map(|_| rng.gen::<f32>()).sum::<f32>() - 2.0;
+            let noise = noise * 3.0f32.sqrt();
+
+            *pixel = pixel.map(|x| (x * (1.0 + self.strength * noise)).max(0.0));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+use std::io;
+
+use crate::image::Image;
+use super::*;
+
+// Unsharp masking: the difference between the image and a
+// blurred copy of it is added back to emphasize edges.
+#[allow(dead_code)]
+pub struct Sharpen {
+    pub sigma: f32, // Standard deviation of the blur in pixels.
+    pub amount: f32 // Strength of the sharpening.
+}
+
+impl PostProcess for Sharpen {
+    fn apply(&self, image: &mut Image) -> io::Result<()> {
+        let blurred = convolve_separable(&image.pixels, image.width, image.height, &gaussian_kernel(self.sigma));
+
+        for (pixel, blurred) in image.pixels.iter_mut().zip(blurred) {
+            *pixel = (*pixel + (*pixel - blurred) * self.amount).map(|x| x.max(0.0));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use std::io;
+
+use crate::image::Image;
+use super::*;
+
+// Darkens the image towards its corners.
+#[allow(dead_code)]
+pub struct Vignette {
+    pub strength: f32, // Darkening at the corners, from 0 (none) to 1 (black).
+    pub falloff: f32   // Exponent of the distance from the center; higher values
+                       // keep more of the image at full brightness.
+}
+
+impl PostProcess for Vignette {
+    fn apply(&self, image: &mut Image) -> io::Result<()> {
+        let center_x = image.width as f32 * 0.5;
+        let center_y = image.height as f32 * 0.5;
+        let half_diagonal = (center_x * center_x + center_y * center_y).sqrt();
+
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let dx = x as f32 + 0.5 - center_x;
+                let dy = y as f32 + 0.5 - center_y;
+                let r = (dx * dx + dy * dy).sqrt() / half_diagonal;
+
+                let factor = (1.0 - self.strength * r.powf(self.falloff)).max(0.0);
+                let index = (y * image.width + x) as usize;
+                image.pixels[index] *= factor;
+            }
+        }
+        Ok(())
+    }
+}
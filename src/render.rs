@@ -2,38 +2,380 @@ extern crate nalgebra as na;
 
 use std::cmp::Ordering;
 use std::f32::consts::PI;
+use std::io;
 
-use na::*;
-use na::geometry::Point3;
+use na::{Point3, Vector3};
+use rand::{thread_rng, Rng};
 
+use crate::camera::Camera;
+use crate::denoise::Denoiser;
+use crate::image::Image;
 use crate::object::*;
+use crate::post::{PostChain, PostProcess, ToneMap};
 use crate::types::*;
 
-fn trace(ray: Ray, objects: &Vec<Object>) -> Option<(&Object, f32)> {
-    objects.iter()
-        .filter_map(|obj| obj.intersect(ray)
-            .map(|x| (obj, x)))
-        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+// Returns the closest object hit by the ray,
+// along with its index and the distance to it.
+fn trace(ray: Ray, objects: &[Object]) -> Option<(usize, &Object, f32)> {
+    objects.iter().enumerate()
+        .filter_map(|(i, obj)| obj.intersect(ray)
+            .map(|x| (i, obj, x)))
+        .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal))
 }
 
-fn light_point(objects: &Vec<Object>, obj: &Object, point: Point3f, light: &dyn Light) -> Color {
-    if light.check_shadow(point, objects) {
+fn light_point(objects: &[Object], obj: &Object, point: Point3f, light: &dyn Light) -> Color {
+    let direction = light.direction(point);
+
+    // A light behind the actual surface can't reach the point, even
+    // where a smoothed shading normal would still face towards it.
+    if obj.geometric_normal(point).dot(&*direction) > 0.0 && light.check_shadow(point, objects) {
         let texture = obj.get_texture(point);
 
-        light.get_color(point) * (texture.albedo / PI) * light.intensity(point) * obj.normal(point).dot(&*light.direction(point))
+        light.get_color(point) * (texture.albedo / PI) * light.intensity(point) * obj.normal(point).dot(&*direction).max(0.0)
     } else {
         // Point is in shadow
         Color::black()
     }
 }
 
-pub fn cast_ray(ray: Ray, scene: &Scene) -> Color {
-    if let Some((obj, dist)) = trace(ray, &scene.objects) {
+// Information about the first surface a ray hits,
+// used to fill the auxiliary buffers.
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceInfo {
+    pub albedo: Color,  // Diffuse reflectance of the surface.
+    pub normal: Unit3f,
+    pub depth: f32,     // Distance along the ray.
+    pub position: Point3f,
+    pub uv: (f32, f32),
+    pub object: usize,  // Index of the object in the scene.
+    pub primitive: usize
+}
+
+// The result of casting a single ray into the scene.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub color: Color,
+    pub alpha: f32, // 1 if the ray hit an object, 0 if it hit the background.
+
+    pub surface: Option<SurfaceInfo>
+}
+
+// Casts a ray into the scene. The contribution of each light is written into
+// the lights buffer, which holds one color per light in the scene and is
+// set to black if the ray hits the background.
+pub fn cast_ray(ray: Ray, scene: &Scene, lights: &mut [Color]) -> Sample {
+    if let Some((index, obj, dist)) = trace(ray, &scene.objects) {
         let point = ray.project(dist);
-        let surface_color = obj.get_texture(point).color;
+        let texture = obj.get_texture(point);
+
+        for (contribution, light) in lights.iter_mut().zip(scene.lights.iter()) {
+            *contribution = light_point(&scene.objects, obj, point, &**light) * texture.color;
+        }
+        let color = lights.iter().sum();
+
+        let surface = SurfaceInfo {
+            albedo: texture.color * texture.albedo,
+            normal: obj.normal(point),
+            depth: dist,
+            position: point,
+            uv: obj.uv(point),
+            object: index,
+            primitive: obj.primitive(point)
+        };
+        Sample { color, alpha: 1.0, surface: Some(surface) }
+    } else {
+        lights.iter_mut().for_each(|contribution| *contribution = Color::black());
+        Sample { color: scene.background, alpha: 0.0, surface: None }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    pub samples: u32, // Number of samples along each side of a pixel,
+                      // so every pixel takes samples² rays.
+
+    pub transparent_background: bool, // If true, the background is left out of the image
+                                      // and only shows up as transparency in the alpha channel.
+
+    pub light_groups: Vec<(String, Vec<usize>)>, // Named groups of lights (as indices into the scene's lights)
+                                                 // whose contributions get their own buffers.
+                                                 // Each light can be in one group at most.
+
+    pub denoise: Option<Denoiser>, // Denoises the finished image, guided by the auxiliary buffers.
+    pub post: PostChain,           // Effects applied to the finished image, after denoising, in linear HDR.
+    pub tone_map: ToneMap // Applied after the effects, to bring the image into display range.
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            samples: 1,
+            transparent_background: false,
+            light_groups: Vec::new(),
+            denoise: None,
+            post: PostChain::new(),
+            tone_map: ToneMap::Linear
+        }
+    }
+}
+
+// The contribution of a group of lights to the image.
+#[derive(Clone, Debug)]
+pub struct LightGroup {
+    pub name: String,
+    pub pixels: Vec<Color> // Premultiplied, like the beauty image.
+}
+
+// Per-pixel buffers describing the surfaces seen in each pixel.
+// Used to guide denoising, and written out as AOVs.
+// Continuous values are averaged over the samples that hit an object,
+// while indices are taken from the first sample that hit one. Albedo is the
+// exception: it is averaged over all samples like the beauty image, with the
+// background counting as black, since the denoiser divides the image by it.
+#[derive(Clone, Debug)]
+pub struct AuxBuffers {
+    pub albedo: Vec<Color>,
+    pub normal: Vec<Vector3f>, // Zero where no object was hit.
+    pub depth: Vec<f32>,       // Infinite where no object was hit.
+    pub position: Vec<Point3f>,
+    pub uv: Vec<(f32, f32)>,
+    pub object: Vec<Option<usize>>,
+    pub primitive: Vec<Option<usize>>
+}
+
+impl AuxBuffers {
+    pub fn with_capacity(size: usize) -> Self {
+        AuxBuffers {
+            albedo:    Vec::with_capacity(size),
+            normal:    Vec::with_capacity(size),
+            depth:     Vec::with_capacity(size),
+            position:  Vec::with_capacity(size),
+            uv:        Vec::with_capacity(size),
+            object:    Vec::with_capacity(size),
+            primitive: Vec::with_capacity(size)
+        }
+    }
+}
+
+// The output of a render: the beauty image and its auxiliary buffers.
+#[allow(dead_code)]
+pub struct Frame {
+    pub image: Image, // The finished image, denoised, post-processed and tone mapped.
+    pub raw: Image,   // The beauty image as rendered, before any of that.
+    pub aux: AuxBuffers,
+
+    // Per-light contribution buffers. The raw beauty image is the sum of these, in order.
+    // Lights outside of any named group go into a "default" group, and an opaque
+    // background gets a "background" group of its own.
+    pub light_groups: Vec<LightGroup>
+}
+
+#[allow(dead_code)]
+impl Frame {
+    // Returns a light group's buffer as an image,
+    // sharing the alpha channel of the raw beauty image.
+    pub fn light_group(&self, name: &str) -> Option<Image> {
+        let group = self.light_groups.iter().find(|group| group.name == name)?;
+        Some(Image { pixels: group.pixels.clone(), ..self.raw.clone() })
+    }
+}
+
+// Sets up the light groups for a render, returning them along with the group
+// each light in the scene belongs to. Fails if a group lists a light that isn't
+// in the scene, or a light that's already in another group.
+fn light_groups(scene: &Scene, settings: &RenderSettings, size: usize) -> io::Result<(Vec<LightGroup>, Vec<usize>)> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
 
-        scene.lights.iter()
-            .map(|light| light_point(&scene.objects, obj, point, &**light))
-            .fold(Color::black(), |acc, c| acc + c) * surface_color
-    } else { scene.background }
+    let mut names: Vec<String> = settings.light_groups.iter().map(|group| group.0.clone()).collect();
+    let mut group_of: Vec<Option<usize>> = vec![None; scene.lights.len()];
+    for (g, (name, lights)) in settings.light_groups.iter().enumerate() {
+        for &light in lights {
+            match group_of.get(light) {
+                None => return Err(invalid(format!("light group '{}' lists light {}, but the scene has {} lights",
+                                                   name, light, scene.lights.len()))),
+                Some(Some(other)) => return Err(invalid(format!("light {} is in both '{}' and '{}'",
+                                                                light, names[*other], name))),
+                Some(None) => group_of[light] = Some(g)
+            }
+        }
+    }
+
+    let default = names.len();
+    if group_of.iter().any(Option::is_none) { names.push("default".to_string()); }
+    let group_of = group_of.into_iter().map(|g| g.unwrap_or(default)).collect();
+
+    if !settings.transparent_background { names.push("background".to_string()); }
+
+    let groups = names.into_iter().map(|name| LightGroup { name, pixels: Vec::with_capacity(size) }).collect();
+    Ok((groups, group_of))
+}
+
+// Renders the scene into a premultiplied RGBA image. Pixels are
+// sampled on a jittered grid, so the alpha channel holds the fraction
+// of each pixel covered by objects. The denoiser, the post-process chain
+// and then the tone map run on the finished image. Fails if the scene
+// hasn't been prepared for the camera (see Scene::prepare).
+pub fn render(camera: &Camera, scene: &Scene, settings: &RenderSettings) -> io::Result<Frame> {
+    if !scene.deferred.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "scene has deferred geometry that hasn't been prepared for the camera"));
+    }
+
+    let width  = camera.image_size.x;
+    let height = camera.image_size.y;
+    let samples = settings.samples.max(1);
+    let weight = 1.0 / (samples * samples) as f32;
+
+    let mut rng = thread_rng();
+    let mut image = Image::new(width, height);
+    let mut aux = AuxBuffers::with_capacity((width * height) as usize);
+    let (mut groups, group_of) = light_groups(scene, settings, (width * height) as usize)?;
+
+    // Buffers for the light contributions of a ray, and their sums over a pixel.
+    let mut lights = vec![Color::black(); scene.lights.len()];
+    let mut group_colors = vec![Color::black(); groups.len()];
+
+    for j in 0..height {
+        for i in 0..width {
+            group_colors.iter_mut().for_each(|color| *color = Color::black());
+            let mut alpha = 0.0;
+
+            let mut albedo = Color::black();
+            let mut normal = Vector3::zeros();
+            let mut depth = 0.0;
+            let mut position = Vector3::zeros();
+            let mut uv = (0.0, 0.0);
+            let mut ids = None;
+            let mut hits = 0;
+
+            for sy in 0..samples {
+                for sx in 0..samples {
+                    let ray = if samples == 1 { camera.raycast(i, j) } else {
+                        camera.raycast_offset(i, j, (sx as f32 + rng.gen::<f32>()) / samples as f32,
+                                                    (sy as f32 + rng.gen::<f32>()) / samples as f32)
+                    };
+
+                    let sample = cast_ray(ray, scene, &mut lights);
+                    for (light, &contribution) in lights.iter().enumerate() {
+                        group_colors[group_of[light]] += contribution * weight;
+                    }
+                    if sample.alpha == 0.0 && !settings.transparent_background {
+                        group_colors[groups.len() - 1] += sample.color * weight;
+                    }
+                    alpha += sample.alpha * weight;
+
+                    if let Some(surface) = sample.surface {
+                        albedo += surface.albedo * weight;
+                        normal += surface.normal.into_inner();
+                        depth += surface.depth;
+                        position += surface.position.coords;
+                        uv = (uv.0 + surface.uv.0, uv.1 + surface.uv.1);
+                        ids = ids.or(Some((surface.object, surface.primitive)));
+                        hits += 1;
+                    }
+                }
+            }
+
+            // Build the beauty pixel from the light groups,
+            // so that they always add up to it exactly.
+            let color = group_colors.iter().sum();
+            for (group, &color) in groups.iter_mut().zip(group_colors.iter()) {
+                group.pixels.push(color);
+            }
+
+            image.set(i, j, color, if settings.transparent_background { alpha } else { 1.0 });
+
+            aux.albedo.push(albedo);
+            aux.normal.push(normal.try_normalize(0.0).unwrap_or_else(Vector3::zeros));
+            aux.depth.push(if hits > 0 { depth / hits as f32 } else { f32::INFINITY });
+
+            let hits = hits.max(1) as f32;
+            aux.position.push(Point3::from(position / hits));
+            aux.uv.push((uv.0 / hits, uv.1 / hits));
+            aux.object.push(ids.map(|ids| ids.0));
+            aux.primitive.push(ids.map(|ids| ids.1));
+        }
+    }
+
+    let raw = image.clone();
+    if let Some(denoiser) = &settings.denoise { denoiser.apply(&mut image, &aux)?; }
+    settings.post.apply(&mut image)?;
+    settings.tone_map.apply(&mut image);
+
+    Ok(Frame { image, raw, aux, light_groups: groups })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::post::{Bloom, Vignette};
+
+    #[test]
+    fn light_point_faces_surface() {
+        // A triangle facing up, smooth shaded with normals leaning far over towards +x.
+        let normal = Vector3::new(1.0, 0.2, 0.0);
+        let mesh = TriangleMesh::new_solid(vec![Point3::origin(), Point3::new(0.0, 0.0, 1.0), Point3::new(1.0, 0.0, 0.0)],
+                                           vec![(0, 1, 2)], Texture::white())
+            .with_vertex_normals(vec![normal; 3]);
+        let objects = vec![Object::new(mesh)];
+        let point = Point3::new(0.2, 0.0, 0.2);
+        let shade = |position: Point3f| light_point(&objects, &objects[0], point, &PointLight::new(position, Color::white(), 10.0));
+
+        // Lit from above, along both normals.
+        assert!(shade(Point3::new(3.0, 3.0, 0.2)).red > 0.0);
+        // Under the surface, so unlit even though the shading normal faces the light.
+        assert_eq!(shade(Point3::new(5.0, -0.5, 0.2)), Color::black());
+        // Over the surface but behind the shading normal, which gives no light rather than negative light.
+        assert_eq!(shade(Point3::new(-5.0, 0.5, 0.2)), Color::black());
+    }
+
+    #[test]
+    fn light_groups_sum_to_beauty() {
+        let camera = Camera::new(Point3::new(0.0, 0.0, -5.0), Vector3::z(), 1.0, 1.0, 2.0, 16);
+        let scene = Scene {
+            objects: vec![Object::new(Sphere::new_solid(0.0, 0.0, 0.0, 1.0, Texture::new(1.0, 0.5, 0.2, 0.8)))],
+            lights: vec![Box::new(PointLight::new(Point3::new(3.0, 3.0, -3.0), Color::white(), 20.0)),
+                         Box::new(PointLight::new(Point3::new(-3.0, 1.0, -3.0), Color::new(0.2, 0.4, 1.0), 10.0)),
+                         Box::new(PointLight::new(Point3::new(0.0, -3.0, -3.0), Color::white(), 5.0))],
+            deferred: Vec::new(),
+            background: Color::gray(0.5)
+        };
+        let settings = RenderSettings {
+            samples: 2,
+            light_groups: vec![("key".to_string(), vec![0]), ("fill".to_string(), vec![1])],
+            ..RenderSettings::default()
+        };
+
+        let frame = render(&camera, &scene, &settings).unwrap();
+        let names: Vec<&str> = frame.light_groups.iter().map(|group| group.name.as_str()).collect();
+        assert_eq!(names, vec!["key", "fill", "default", "background"]);
+
+        assert_eq!(frame.image.pixels, frame.raw.pixels);
+        for (index, &pixel) in frame.raw.pixels.iter().enumerate() {
+            let sum: Color = frame.light_groups.iter().map(|group| group.pixels[index]).sum();
+            assert_eq!(sum, pixel);
+        }
+
+        // With denoising, post-processing and tone mapping the finished image moves away,
+        // but the groups still add up to the raw beauty.
+        let processed = RenderSettings {
+            denoise: Some(Denoiser::default()),
+            post: PostChain::new().then(Bloom::new(1.0, 0.5, 4.0)).then(Vignette { strength: 0.3, falloff: 2.0 }),
+            tone_map: ToneMap::Reinhard(1.0),
+            ..settings
+        };
+        let frame = render(&camera, &scene, &processed).unwrap();
+        assert_ne!(frame.image.pixels, frame.raw.pixels);
+        for (index, &pixel) in frame.raw.pixels.iter().enumerate() {
+            let sum: Color = frame.light_groups.iter().map(|group| group.pixels[index]).sum();
+            assert_eq!(sum, pixel);
+        }
+        assert_eq!(frame.light_group("key").unwrap().alpha, frame.raw.alpha);
+
+        // Lights that aren't in the scene, or are in two groups, are turned down before rendering.
+        for groups in &[vec![("key".to_string(), vec![3])],
+                        vec![("key".to_string(), vec![0]), ("fill".to_string(), vec![1, 0])]] {
+            let settings = RenderSettings { light_groups: groups.clone(), ..RenderSettings::default() };
+            assert_eq!(render(&camera, &scene, &settings).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+        }
+    }
 }
@@ -0,0 +1,245 @@
+use std::rc::Rc;
+
+use na::Affine3;
+
+use crate::object::*;
+use crate::types::*;
+
+// A node of the scene graph.
+#[allow(dead_code)]
+pub enum Node {
+    Object(Rc<dyn Surface>),
+    Light(Box<dyn Light>),
+    Group(Group)
+}
+
+// A group of named child nodes sharing a local transform,
+// which applies on top of the transforms of the groups above it.
+#[allow(dead_code)]
+pub struct Group {
+    pub transform: Affine3<f32>,
+    pub children: Vec<(String, Node)>
+}
+
+// A hierarchical scene. Nodes are addressed by the names of the
+// groups leading to them and their own, separated by '/'.
+#[allow(dead_code)]
+pub struct SceneGraph {
+    pub root: Group,
+    pub background: Color
+}
+
+impl Default for Group {
+    fn default() -> Self { Group::new(Affine3::identity()) }
+}
+
+#[allow(dead_code)]
+impl Group {
+    // Creates a new empty group.
+    pub fn new(transform: Affine3<f32>) -> Self { Group { transform, children: Vec::new() } }
+
+    // Adds a child node. Children are named uniquely, so a previous child
+    // with the same name is replaced. Panics if the name contains a '/',
+    // since the node couldn't be told apart from one further down.
+    pub fn add(&mut self, name: &str, node: Node) -> &mut Self {
+        assert!(!name.contains('/'), "node name {:?} contains '/'", name);
+        match self.children.iter_mut().find(|(child, _)| child == name) {
+            Some(child) => child.1 = node,
+            None => self.children.push((name.to_string(), node))
+        }
+        self
+    }
+
+    pub fn add_object(&mut self, name: &str, surface: impl 'static + Surface) -> &mut Self
+        { self.add(name, Node::Object(Rc::new(surface))) }
+
+    pub fn add_light(&mut self, name: &str, light: impl 'static + Light) -> &mut Self
+        { self.add(name, Node::Light(Box::new(light))) }
+
+    pub fn add_group(&mut self, name: &str, group: Group) -> &mut Self
+        { self.add(name, Node::Group(group)) }
+
+    // Returns the node at the given path below this group.
+    pub fn get(&self, path: &str) -> Option<&Node> {
+        let (name, rest) = split_path(path);
+        let node = &self.children.iter().find(|(child, _)| child == name)?.1;
+        match (rest, node) {
+            (None, node) => Some(node),
+            (Some(rest), Node::Group(group)) => group.get(rest),
+            _ => None
+        }
+    }
+
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut Node> {
+        let (name, rest) = split_path(path);
+        let node = &mut self.children.iter_mut().find(|(child, _)| child == name)?.1;
+        match (rest, node) {
+            (None, node) => Some(node),
+            (Some(rest), Node::Group(group)) => group.get_mut(rest),
+            _ => None
+        }
+    }
+
+    // Removes the node at the given path and returns it.
+    pub fn remove(&mut self, path: &str) -> Option<Node> {
+        match path.rsplit_once('/') {
+            Some((parent, name)) => match self.get_mut(parent)? {
+                Node::Group(group) => group.remove(name),
+                _ => None
+            },
+            None => {
+                let index = self.children.iter().position(|(child, _)| child == path)?;
+                Some(self.children.remove(index).1)
+            }
+        }
+    }
+
+    // Walks the tree, calling the closure on every object and light
+    // along with its path and its transform into world space.
+    fn visit<F>(&self, prefix: &str, parent: &Affine3<f32>, f: &mut F)
+        where F: FnMut(&str, &Affine3<f32>, &Node)
+    {
+        let transform = parent * self.transform;
+        for (name, node) in &self.children {
+            let path = if prefix.is_empty() { name.clone() } else { format!("{}/{}", prefix, name) };
+            match node {
+                Node::Group(group) => group.visit(&path, &transform, f),
+                node => f(&path, &transform, node)
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    match path.split_once('/') {
+        Some((name, rest)) => (name, Some(rest)),
+        None => (path, None)
+    }
+}
+
+#[allow(dead_code)]
+impl SceneGraph {
+    pub fn new(background: Color) -> Self { SceneGraph { root: Group::default(), background } }
+
+    pub fn get(&self, path: &str) -> Option<&Node> { self.root.get(path) }
+
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut Node> { self.root.get_mut(path) }
+
+    // Returns the world space transform of the node at the given path,
+    // including the node's own transform if it is a group.
+    pub fn world_transform(&self, path: &str) -> Option<Affine3<f32>> {
+        let mut transform = self.root.transform;
+        let mut group = &self.root;
+        let mut rest = Some(path);
+
+        while let Some(path) = rest {
+            let (name, next) = split_path(path);
+            match &group.children.iter().find(|(child, _)| child == name)?.1 {
+                Node::Group(child) => {
+                    transform *= child.transform;
+                    group = child;
+                },
+                _ if next.is_none() => (),
+                _ => return None
+            }
+            rest = next;
+        }
+
+        Some(transform)
+    }
+
+    // Flattens the graph into a scene, with every object placed as an
+    // instance of its surface under the transforms of its groups. Objects
+    // under transforms that can't be inverted (such as a scale of zero)
+    // have no extent and are left out. Objects appear in the same order
+    // as in object_paths().
+    pub fn flatten(&self) -> Scene {
+        let mut objects = Vec::new();
+        let mut lights = Vec::new();
+
+        self.root.visit("", &Affine3::identity(), &mut |_, transform, node| match node {
+            Node::Object(surface) => objects.extend(Instance::try_new(surface.clone(), *transform).map(Object::new)),
+            Node::Light(light) => lights.push(light.transformed(transform)),
+            Node::Group(_) => unreachable!()
+        });
+
+        Scene { objects, lights, deferred: Vec::new(), background: self.background }
+    }
+
+    // Returns the paths of the objects flatten() places, in the same order.
+    // The object index of a pixel (see AuxBuffers) can be looked up in here.
+    pub fn object_paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        self.root.visit("", &Affine3::identity(), &mut |path, transform, node| {
+            if let Node::Object(_) = node {
+                if transform.try_inverse().is_some() { paths.push(path.to_string()); }
+            }
+        });
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::{Point3, Translation3, Vector3};
+
+    #[test]
+    fn scene_graph_flatten() {
+        let texture = Texture::white();
+
+        let mut wheel = Group::new(na::convert(Translation3::new(1.0, 0.0, 0.0)));
+        wheel.add_object("hub", Sphere::new_solid(0.0, 0.0, 0.0, 0.5, texture));
+
+        let mut car = Group::new(na::convert(Translation3::new(0.0, 0.0, 10.0)));
+        car.add_group("wheel", wheel)
+           .add_light("lamp", PointLight::new(Point3::new(0.0, 1.0, 0.0), Color::white(), 1.0));
+
+        let mut graph = SceneGraph::new(Color::black());
+        graph.root.add_group("car", car);
+
+        assert_eq!(graph.object_paths(), vec!["car/wheel/hub".to_string()]);
+        assert!(matches!(graph.get("car/wheel/hub"), Some(Node::Object(_))));
+        assert!(graph.get("car/hub").is_none());
+
+        // The hub ends up at (1, 0, 10).
+        let scene = graph.flatten();
+        let t = scene.objects[0].intersect(Ray::new(Point3::new(1.0, 0.0, 0.0), Vector3::z())).unwrap();
+        assert!((t - 9.5).abs() < 1e-4);
+        assert!((scene.lights[0].direction(Point3::new(0.0, 0.0, 10.0)).into_inner() - Vector3::y()).norm() < 1e-4);
+
+        // Moving a group moves everything in it.
+        if let Some(Node::Group(car)) = graph.get_mut("car") {
+            car.transform = na::convert(Translation3::new(0.0, 0.0, 20.0));
+        }
+        let t = graph.flatten().objects[0].intersect(Ray::new(Point3::new(1.0, 0.0, 0.0), Vector3::z())).unwrap();
+        assert!((t - 19.5).abs() < 1e-4);
+        assert!((graph.world_transform("car/wheel").unwrap().transform_point(&Point3::origin()) - Point3::new(1.0, 0.0, 20.0)).norm() < 1e-4);
+
+        assert!(graph.root.remove("car/wheel/hub").is_some());
+        assert!(graph.flatten().objects.is_empty());
+    }
+
+    #[test]
+    fn scene_graph_collapsed() {
+        let texture = Texture::white();
+
+        // A group scaled down to nothing hides what's in it.
+        let mut hidden = Group::new(Affine3::from_matrix_unchecked(na::Matrix4::new_scaling(0.0)));
+        hidden.add_object("ball", Sphere::new_solid(0.0, 0.0, 0.0, 1.0, texture));
+
+        let mut graph = SceneGraph::new(Color::black());
+        graph.root.add_group("hidden", hidden)
+                  .add_object("ball", Sphere::new_solid(0.0, 0.0, 5.0, 1.0, texture));
+
+        assert_eq!(graph.flatten().objects.len(), 1);
+        assert_eq!(graph.object_paths(), vec!["ball".to_string()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "contains '/'")]
+    fn scene_graph_slash_in_name() {
+        Group::default().add_object("car/hub", Sphere::new_solid(0.0, 0.0, 0.0, 1.0, Texture::white()));
+    }
+}
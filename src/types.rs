@@ -1,10 +1,13 @@
 extern crate nalgebra as na;
 
-use std::ops::{Add, Mul};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
 use na::*;
 use na::geometry::Point3;
 
+mod color_space; #[allow(unused_imports)] pub use color_space::*;
+
 pub type Point3f = Point3<f32>;
 pub type Vector3f = Vector3<f32>;
 pub type Unit3f = Unit<Vector3<f32>>;
@@ -25,28 +28,36 @@ impl Ray {
     pub fn project(&self, t: f32) -> Point3f { self.origin + t * self.direction.into_inner() }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Color {
     pub red: f32,
     pub green: f32,
-    pub blue: f32,
+    pub blue: f32
+}
+
+// Converts a single sRGB-encoded component to linear.
+fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.04045 { x / 12.92 } else { ((x + 0.055) / 1.055).powf(2.4) }
+}
 
-    _private: () // Private field prevents direct construction
+// Converts a single linear component to sRGB encoding.
+fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.003_130_8 { x * 12.92 } else { 1.055 * x.powf(1.0 / 2.4) - 0.055 }
 }
 
 #[allow(dead_code)]
 impl Color {
+    // Creates a new color, clamping negative components to zero.
+    // Use a struct literal to create a color with negative components.
     pub fn new(red: f32, green: f32, blue: f32) -> Self {
         Color {
             red:   if red   < 0.0 { 0.0 } else { red   },
             green: if green < 0.0 { 0.0 } else { green },
-            blue:  if blue  < 0.0 { 0.0 } else { blue  },
-
-            _private: ()
+            blue:  if blue  < 0.0 { 0.0 } else { blue  }
         }
     }
 
-    pub fn to_byte_array(&self) -> [u8; 3] {
+    pub fn to_byte_array(self) -> [u8; 3] {
         let red   = (255.0 * self.red)   as u8;
         let green = (255.0 * self.green) as u8;
         let blue  = (255.0 * self.blue)  as u8;
@@ -57,6 +68,84 @@ impl Color {
 
     pub fn black() -> Self { Color::gray(0.0) }
     pub fn white() -> Self { Color::gray(1.0) }
+
+    // Applies a function to each component.
+    pub fn map(self, f: impl Fn(f32) -> f32) -> Self {
+        Color { red: f(self.red), green: f(self.green), blue: f(self.blue) }
+    }
+
+    // Combines two colors component-wise.
+    pub fn zip(self, other: Color, f: impl Fn(f32, f32) -> f32) -> Self {
+        Color { red: f(self.red, other.red), green: f(self.green, other.green), blue: f(self.blue, other.blue) }
+    }
+
+    // Relative luminance, using the Rec.709 weights of linear sRGB.
+    pub fn luminance(self) -> f32 { 0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue }
+
+    pub fn max_component(self) -> f32 { self.red.max(self.green).max(self.blue) }
+    pub fn min_component(self) -> f32 { self.red.min(self.green).min(self.blue) }
+
+    // Linearly interpolates between two colors, where t = 0 gives self.
+    pub fn lerp(self, other: Color, t: f32) -> Self { self * (1.0 - t) + other * t }
+
+    // Clamps each component to the range [min, max].
+    pub fn clamp(self, min: f32, max: f32) -> Self { self.map(|x| x.max(min).min(max)) }
+
+    pub fn is_black(self) -> bool { self.red == 0.0 && self.green == 0.0 && self.blue == 0.0 }
+    pub fn has_nan(self) -> bool { self.red.is_nan() || self.green.is_nan() || self.blue.is_nan() }
+    pub fn is_finite(self) -> bool { self.red.is_finite() && self.green.is_finite() && self.blue.is_finite() }
+
+    // Creates a color from a hex code such as 0xFF8000.
+    // Hex codes are taken to be sRGB-encoded, as they are in most tools.
+    pub fn from_hex(hex: u32) -> Self {
+        let channel = |shift: u32| srgb_to_linear(((hex >> shift) & 0xFF) as f32 / 255.0);
+        Color::new(channel(16), channel(8), channel(0))
+    }
+
+    // Parses a hex code string such as "#FF8000" or "ff8000".
+    pub fn from_hex_str(hex: &str) -> Option<Self> {
+        let hex = hex.trim_start_matches('#');
+        if hex.len() != 6 { return None; }
+        u32::from_str_radix(hex, 16).ok().map(Color::from_hex)
+    }
+
+    // Converts the color to an sRGB-encoded hex code, clamping it to [0, 1].
+    pub fn to_hex(self) -> u32 {
+        let channel = |x: f32| (linear_to_srgb(x.clamp(0.0, 1.0)) * 255.0).round() as u32;
+        (channel(self.red) << 16) | (channel(self.green) << 8) | channel(self.blue)
+    }
+
+    // Creates a color from hue (in degrees), saturation and value.
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let hue = hue.rem_euclid(360.0) / 60.0;
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+        let m = value - chroma;
+
+        let (red, green, blue) = match hue as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x)
+        };
+        Color::new(red + m, green + m, blue + m)
+    }
+
+    // Converts the color to hue (in degrees), saturation and value.
+    pub fn to_hsv(self) -> (f32, f32, f32) {
+        let max = self.max_component();
+        let chroma = max - self.min_component();
+
+        let hue = if chroma == 0.0 { 0.0 }
+                  else if max == self.red   { 60.0 * ((self.green - self.blue) / chroma).rem_euclid(6.0) }
+                  else if max == self.green { 60.0 * ((self.blue - self.red) / chroma + 2.0) }
+                  else                      { 60.0 * ((self.red - self.green) / chroma + 4.0) };
+        let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+
+        (hue, saturation, max)
+    }
 }
 
 impl Add for Color {
@@ -65,8 +154,18 @@ impl Add for Color {
         Color {
             red:   self.red   + rhs.red,
             green: self.green + rhs.green,
-            blue:  self.blue  + rhs.blue,
-            _private: ()
+            blue:  self.blue  + rhs.blue
+        }
+    }
+}
+
+impl Sub for Color {
+    type Output = Color;
+    fn sub(self, rhs: Color) -> Color {
+        Color {
+            red:   self.red   - rhs.red,
+            green: self.green - rhs.green,
+            blue:  self.blue  - rhs.blue
         }
     }
 }
@@ -77,8 +176,7 @@ impl Mul for Color {
         Color {
             red:   self.red   * rhs.red,
             green: self.green * rhs.green,
-            blue:  self.blue  * rhs.blue,
-            _private: ()
+            blue:  self.blue  * rhs.blue
         }
     }
 }
@@ -89,12 +187,69 @@ impl Mul<f32> for Color {
         Color {
             red:   self.red   * rhs,
             green: self.green * rhs,
-            blue:  self.blue  * rhs,
-            _private: ()
+            blue:  self.blue  * rhs
         }
     }
 }
 
+impl Mul<Color> for f32 {
+    type Output = Color;
+    fn mul(self, rhs: Color) -> Color { rhs * self }
+}
+
+impl Div for Color {
+    type Output = Color;
+    fn div(self, rhs: Color) -> Color {
+        Color {
+            red:   self.red   / rhs.red,
+            green: self.green / rhs.green,
+            blue:  self.blue  / rhs.blue
+        }
+    }
+}
+
+impl Div<f32> for Color {
+    type Output = Color;
+    fn div(self, rhs: f32) -> Color { self * (1.0 / rhs) }
+}
+
+impl Neg for Color {
+    type Output = Color;
+    fn neg(self) -> Color { self * -1.0 }
+}
+
+impl AddAssign for Color {
+    fn add_assign(&mut self, rhs: Color) { *self = *self + rhs; }
+}
+
+impl SubAssign for Color {
+    fn sub_assign(&mut self, rhs: Color) { *self = *self - rhs; }
+}
+
+impl MulAssign for Color {
+    fn mul_assign(&mut self, rhs: Color) { *self = *self * rhs; }
+}
+
+impl MulAssign<f32> for Color {
+    fn mul_assign(&mut self, rhs: f32) { *self = *self * rhs; }
+}
+
+impl DivAssign for Color {
+    fn div_assign(&mut self, rhs: Color) { *self = *self / rhs; }
+}
+
+impl DivAssign<f32> for Color {
+    fn div_assign(&mut self, rhs: f32) { *self = *self / rhs; }
+}
+
+impl Sum for Color {
+    fn sum<I: Iterator<Item = Color>>(iter: I) -> Color { iter.fold(Color::black(), |acc, c| acc + c) }
+}
+
+impl<'a> Sum<&'a Color> for Color {
+    fn sum<I: Iterator<Item = &'a Color>>(iter: I) -> Color { iter.copied().sum() }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Texture {
     pub color: Color,
@@ -109,4 +264,36 @@ impl Texture {
             albedo
         }
     }
+
+    // A white texture that reflects all light, handy for untextured geometry.
+    #[allow(dead_code)]
+    pub fn white() -> Self { Texture::new(1.0, 1.0, 1.0, 1.0) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_hex() {
+        assert_eq!(Color::from_hex(0xFF8000).to_hex(), 0xFF8000);
+        assert_eq!(Color::from_hex_str("#ffffff"), Some(Color::white()));
+        assert_eq!(Color::from_hex_str("fff"), None);
+    }
+
+    #[test]
+    fn color_hsv() {
+        let color = Color::new(0.2, 0.6, 0.4);
+        let (h, s, v) = color.to_hsv();
+        let back = Color::from_hsv(h, s, v);
+        assert!((back - color).map(f32::abs).max_component() < 1e-5);
+
+        assert_eq!(Color::from_hsv(120.0, 1.0, 1.0), Color::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn color_sum() {
+        let colors = [Color::new(0.1, 0.2, 0.3), Color::new(0.3, 0.2, 0.1)];
+        assert_eq!(colors.iter().sum::<Color>(), Color::new(0.4, 0.4, 0.4));
+    }
 }
@@ -0,0 +1,206 @@
+extern crate nalgebra as na;
+
+use na::*;
+
+use super::Color;
+
+// A chromaticity coordinate in the CIE 1931 xy diagram.
+pub type Chromaticity = (f32, f32);
+
+// Standard white points.
+pub const D65: Chromaticity = (0.3127, 0.3290);
+pub const D60: Chromaticity = (0.32168, 0.33767); // ACES white point.
+pub const E: Chromaticity   = (1.0 / 3.0, 1.0 / 3.0);
+
+// An RGB color space, defined by the chromaticities of its
+// primaries and its white point. All spaces here are linear;
+// the renderer itself works in linear sRGB.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    LinearSRGB, // Linear sRGB, which shares its primaries with Rec.709.
+    Rec2020,
+    ACEScg,
+    DisplayP3,
+    Xyz         // CIE 1931 XYZ.
+}
+
+#[allow(dead_code)]
+impl ColorSpace {
+    pub const REC709: ColorSpace = ColorSpace::LinearSRGB;
+
+    // Returns the chromaticities of the red, green and blue primaries,
+    // or None for XYZ (which is not defined by primaries).
+    pub fn primaries(self) -> Option<[Chromaticity; 3]> {
+        match self {
+            ColorSpace::LinearSRGB => Some([(0.640, 0.330), (0.300, 0.600), (0.150, 0.060)]),
+            ColorSpace::Rec2020    => Some([(0.708, 0.292), (0.170, 0.797), (0.131, 0.046)]),
+            ColorSpace::ACEScg     => Some([(0.713, 0.293), (0.165, 0.830), (0.128, 0.044)]),
+            ColorSpace::DisplayP3  => Some([(0.680, 0.320), (0.265, 0.690), (0.150, 0.060)]),
+            ColorSpace::Xyz        => None
+        }
+    }
+
+    pub fn white_point(self) -> Chromaticity {
+        match self {
+            ColorSpace::ACEScg => D60,
+            ColorSpace::Xyz    => E,
+            _                  => D65
+        }
+    }
+
+    // Returns the matrix converting from this space to XYZ
+    // (without any chromatic adaptation).
+    pub fn rgb_to_xyz(self) -> Matrix3<f32> {
+        let [r, g, b] = match self.primaries() {
+            Some(primaries) => primaries,
+            None => return Matrix3::identity()
+        };
+
+        let primaries = Matrix3::from_columns(&[xy_to_xyz(r), xy_to_xyz(g), xy_to_xyz(b)]);
+
+        // Scale each primary so that (1, 1, 1) maps onto the white point.
+        let scale = primaries.try_inverse().unwrap() * xy_to_xyz(self.white_point());
+        primaries * Matrix3::from_diagonal(&scale)
+    }
+
+    // Returns the matrix converting from XYZ to this space.
+    pub fn xyz_to_rgb(self) -> Matrix3<f32> { self.rgb_to_xyz().try_inverse().unwrap() }
+
+    // Returns the matrix converting colors from this space to another,
+    // adapting between the white points of the two spaces if they differ.
+    // XYZ has no white point of its own, so no adaptation is done to or from it.
+    pub fn conversion(self, target: ColorSpace) -> Matrix3<f32> {
+        if self == target { return Matrix3::identity(); }
+
+        let adapt = if self == ColorSpace::Xyz || target == ColorSpace::Xyz { Matrix3::identity() }
+                    else { chromatic_adaptation(self.white_point(), target.white_point()) };
+
+        target.xyz_to_rgb() * adapt * self.rgb_to_xyz()
+    }
+}
+
+// Converts an xy chromaticity to an XYZ color with luminance 1.
+fn xy_to_xyz((x, y): Chromaticity) -> Vector3<f32> {
+    Vector3::new(x / y, 1.0, (1.0 - x - y) / y)
+}
+
+// Computes the Bradford chromatic adaptation matrix, which maps
+// XYZ colors viewed under one white point to another.
+pub fn chromatic_adaptation(source: Chromaticity, target: Chromaticity) -> Matrix3<f32> {
+    let bradford = Matrix3::new( 0.8951,  0.2664, -0.1614,
+                                -0.7502,  1.7135,  0.0367,
+                                 0.0389, -0.0685,  1.0296);
+
+    let source_cone = bradford * xy_to_xyz(source);
+    let target_cone = bradford * xy_to_xyz(target);
+    let scale = Matrix3::from_diagonal(&target_cone.component_div(&source_cone));
+
+    bradford.try_inverse().unwrap() * scale * bradford
+}
+
+// Analytic approximation to the CIE 1931 color matching functions
+// (Wyman, Sloan and Shirley, 2013). Takes a wavelength in nanometers.
+fn color_matching(lambda: f32) -> Vector3<f32> {
+    fn lobe(x: f32, mu: f32, sigma1: f32, sigma2: f32) -> f32 {
+        let t = (x - mu) / if x < mu { sigma1 } else { sigma2 };
+        (-0.5 * t * t).exp()
+    }
+
+    Vector3::new(1.056 * lobe(lambda, 599.8, 37.9, 31.0)
+               + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+               - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+                 0.821 * lobe(lambda, 568.8, 46.9, 40.5)
+               + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+                 1.217 * lobe(lambda, 437.0, 11.8, 36.0)
+               + 0.681 * lobe(lambda, 459.0, 26.0, 13.8))
+}
+
+#[allow(dead_code)]
+impl Color {
+    // Converts a color from one color space to another. Colors out of the
+    // target's gamut come out with negative components, which are kept so
+    // that converting back gives the original color; clamp them if needed.
+    pub fn convert(self, source: ColorSpace, target: ColorSpace) -> Color {
+        let converted = source.conversion(target) * Vector3::new(self.red, self.green, self.blue);
+        Color { red: converted.x, green: converted.y, blue: converted.z }
+    }
+
+    // Computes the color of a blackbody radiator at the given
+    // temperature in Kelvin, in linear sRGB and normalized to a luminance of 1.
+    // Parts outside the sRGB gamut are clamped. Temperatures too low to give
+    // off any visible light are black.
+    pub fn blackbody(kelvin: f32) -> Color {
+        // Planck's law, with the wavelength in nanometers. Constant factors
+        // are dropped, since the result is normalized anyway.
+        let planck = |lambda: f32| {
+            let lambda = lambda as f64 * 1e-9;
+            let c2 = 1.438_776_9e-2; // hc / k, in meter-Kelvin.
+            (1.0 / (lambda.powi(5) * ((c2 / (lambda * kelvin as f64)).exp() - 1.0))) as f32
+        };
+
+        let xyz = (0..=80).map(|i| 380.0 + 5.0 * i as f32)
+                          .map(|lambda| color_matching(lambda) * planck(lambda))
+                          .fold(Vector3::zeros(), |acc, c| acc + c);
+        if !(xyz.y > 0.0 && xyz.y.is_finite()) { return Color::black(); }
+        let xyz = xyz / xyz.y;
+
+        Color::new(xyz.x, xyz.y, xyz.z).convert(ColorSpace::Xyz, ColorSpace::LinearSRGB).map(|x| x.max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx(a: Color, b: Color) -> bool {
+        (a.red - b.red).abs() < 1e-3 && (a.green - b.green).abs() < 1e-3 && (a.blue - b.blue).abs() < 1e-3
+    }
+
+    #[test]
+    fn color_space_white() {
+        // White should stay white between spaces sharing a white point,
+        // and be adapted to white between spaces that don't.
+        for &space in &[ColorSpace::Rec2020, ColorSpace::ACEScg, ColorSpace::DisplayP3] {
+            assert!(approx(Color::white().convert(ColorSpace::LinearSRGB, space), Color::white()));
+        }
+    }
+
+    #[test]
+    fn color_space_round_trip() {
+        let color = Color::new(0.2, 0.5, 0.7);
+        let back = color.convert(ColorSpace::LinearSRGB, ColorSpace::ACEScg)
+                        .convert(ColorSpace::ACEScg, ColorSpace::LinearSRGB);
+        assert!(approx(color, back));
+
+        // Colors out of the sRGB gamut keep their negative components and survive the trip.
+        let green = Color::new(0.0, 1.0, 0.0);
+        let srgb = green.convert(ColorSpace::Rec2020, ColorSpace::LinearSRGB);
+        assert!(srgb.red < 0.0 && srgb.blue < 0.0);
+        assert!(approx(srgb.convert(ColorSpace::LinearSRGB, ColorSpace::Rec2020), green));
+    }
+
+    #[test]
+    fn color_space_xyz_luminance() {
+        let xyz = Color::new(1.0, 0.0, 0.0).convert(ColorSpace::LinearSRGB, ColorSpace::Xyz);
+        assert!((xyz.green - 0.2126).abs() < 1e-3);
+    }
+
+    #[test]
+    fn blackbody() {
+        let d65 = Color::blackbody(6504.0);
+        assert!((d65.red - d65.blue).abs() < 0.1);
+
+        let warm = Color::blackbody(2700.0);
+        assert!(warm.red > warm.green && warm.green > warm.blue);
+
+        let cold = Color::blackbody(12000.0);
+        assert!(cold.blue > cold.red);
+
+        for &kelvin in &[0.0, -100.0, 1.0, f32::NAN] {
+            assert_eq!(Color::blackbody(kelvin), Color::black());
+        }
+        let ember = Color::blackbody(1000.0);
+        assert!(ember.green >= 0.0 && ember.blue >= 0.0);
+    }
+}